@@ -0,0 +1,379 @@
+//! ns/order through `OrderBook::add`, without the mutex, WAL or gRPC layers.
+//! `cargo bench --bench order_book`
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::collections::{HashMap, VecDeque};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use matching::order_book::{Order, OrderBook, Side};
+
+const ORDERS: u64 = 10_000;
+
+/// System allocator that counts allocations, so `fill_delivery` can report them.
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+/// Allocations (and reallocations) made while `f` runs; benches are single-threaded.
+fn allocations(f: impl FnOnce()) -> usize {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    f();
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
+
+fn order(seq: u64, side: Side, price: i64, qty: i64) -> Order {
+    Order {
+        seq,
+        side,
+        price,
+        qty,
+        client_order_id: String::new(),
+    }
+}
+
+/// Deterministic xorshift, so every run sees the same order stream.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: u64) -> i64 {
+        (self.next() % n) as i64
+    }
+}
+
+/// `levels` ask levels of `per_level` one-lot orders from price 1_000 up.
+fn deep_asks(levels: i64, per_level: u64) -> OrderBook {
+    let mut book = OrderBook::new();
+    let mut seq = 0;
+    for price in 1_000..1_000 + levels {
+        for _ in 0..per_level {
+            seq += 1;
+            book.add(order(seq, Side::Sell, price, 1));
+        }
+    }
+    book
+}
+
+fn pure_rest(c: &mut Criterion) {
+    // Bids below 1_000, asks above: nothing ever crosses.
+    let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+    let stream: Vec<Order> = (1..=ORDERS)
+        .map(|seq| match seq % 2 {
+            0 => order(seq, Side::Buy, 900 + rng.below(100), 1 + rng.below(10)),
+            _ => order(seq, Side::Sell, 1_001 + rng.below(100), 1 + rng.below(10)),
+        })
+        .collect();
+
+    let mut g = c.benchmark_group("pure_rest");
+    g.throughput(Throughput::Elements(ORDERS));
+    g.bench_function("10k_orders_200_levels", |b| {
+        b.iter_batched(
+            || stream.clone(),
+            |orders| {
+                let mut book = OrderBook::new();
+                for o in orders {
+                    black_box(book.add(o));
+                }
+                book
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    g.finish();
+}
+
+fn mixed_rest_cross(c: &mut Criterion) {
+    // Both sides quote over the same 21 ticks, so orders both cross and rest.
+    let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+    let stream: Vec<Order> = (1..=ORDERS)
+        .map(|seq| {
+            let side = if rng.next() % 2 == 0 {
+                Side::Buy
+            } else {
+                Side::Sell
+            };
+            order(seq, side, 990 + rng.below(21), 1 + rng.below(20))
+        })
+        .collect();
+
+    let mut g = c.benchmark_group("mixed_rest_cross");
+    g.throughput(Throughput::Elements(ORDERS));
+    g.bench_function("10k_orders", |b| {
+        b.iter_batched(
+            || stream.clone(),
+            |orders| {
+                let mut book = OrderBook::new();
+                for o in orders {
+                    black_box(book.add(o));
+                }
+                book
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    g.finish();
+}
+
+fn deep_sweep(c: &mut Criterion) {
+    let mut g = c.benchmark_group("deep_sweep");
+    for (levels, per_level) in [(10, 100), (100, 10), (1_000, 1)] {
+        let makers = levels as u64 * per_level;
+        let book = deep_asks(levels, per_level);
+        g.throughput(Throughput::Elements(makers));
+        g.bench_function(format!("{levels}_levels_x_{per_level}"), |b| {
+            b.iter_batched(
+                || book_clone(&book),
+                |mut book| {
+                    let taker = order(makers + 1, Side::Buy, 1_000 + levels, makers as i64);
+                    let mut filled = 0;
+                    book.add_with(taker, |f| filled += f.qty);
+                    black_box(filled)
+                },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    g.finish();
+}
+
+fn fill_delivery(c: &mut Criterion) {
+    // One 1_000-maker sweep: `add` collects the fills into a Vec, `add_with` hands them over.
+    let book = deep_asks(10, 100);
+    let taker = || order(1_001, Side::Buy, 1_010, 1_000);
+
+    let mut collecting = book_clone(&book);
+    let collected = allocations(|| {
+        black_box(collecting.add(taker()));
+    });
+    let mut streaming = book_clone(&book);
+    let streamed = allocations(|| {
+        let mut filled = 0;
+        streaming.add_with(taker(), |f| filled += f.qty);
+        black_box(filled);
+    });
+    println!("1000-maker sweep allocations: add {collected}, add_with {streamed}");
+    assert!(
+        streamed < collected,
+        "add_with allocated {streamed}, add {collected}"
+    );
+
+    let mut g = c.benchmark_group("fill_delivery");
+    g.throughput(Throughput::Elements(1_000));
+    g.bench_function("add_collect", |b| {
+        b.iter_batched(
+            || book_clone(&book),
+            |mut book| black_box(book.add(taker())),
+            BatchSize::LargeInput,
+        )
+    });
+    g.bench_function("add_with_callback", |b| {
+        b.iter_batched(
+            || book_clone(&book),
+            |mut book| {
+                let mut filled = 0;
+                book.add_with(taker(), |f| filled += f.qty);
+                black_box(filled)
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    g.finish();
+}
+
+/// The engine's trade tape cut down to what an append touches: a bounded ring per symbol and
+/// the newest evicted trade_id. It mirrors `append_trades` in the binary, which benches can't link.
+#[derive(Default)]
+struct Tape {
+    trades: HashMap<String, VecDeque<TapeTrade>>,
+    evicted_through: HashMap<String, u64>,
+}
+
+// Never read back: the fields are there for the engine trade's size and its symbol clone.
+#[allow(dead_code)]
+struct TapeTrade {
+    trade_id: u64,
+    symbol: String,
+    price: i64,
+    qty: i64,
+    maker_seq: u64,
+    taker_seq: u64,
+}
+
+/// The engine's per-symbol ring size (`MAX_TRADES_PER_SYMBOL`).
+const TAPE_CAP: usize = 10_000;
+
+impl Tape {
+    /// A ring already at capacity, so every append also evicts.
+    fn full(symbol: &str) -> Self {
+        let mut tape = Tape::default();
+        let q = tape.trades.entry(symbol.to_string()).or_default();
+        q.extend((1..=TAPE_CAP as u64).map(|trade_id| TapeTrade {
+            trade_id,
+            symbol: symbol.to_string(),
+            price: 0,
+            qty: 0,
+            maker_seq: 0,
+            taker_seq: 0,
+        }));
+        tape
+    }
+
+    /// The old path: one lookup, push and trim per trade.
+    fn append_one(&mut self, symbol: &str, trade: TapeTrade) {
+        let q = self.trades.entry(symbol.to_string()).or_default();
+        q.push_back(trade);
+        while q.len() > TAPE_CAP {
+            if let Some(evicted) = q.pop_front() {
+                self.evicted_through
+                    .insert(symbol.to_string(), evicted.trade_id);
+            }
+        }
+    }
+
+    /// The current path: one lookup, extend and trim per order.
+    fn append_batch(&mut self, symbol: &str, trades: Vec<TapeTrade>) {
+        let q = self.trades.entry(symbol.to_string()).or_default();
+        q.extend(trades);
+        let excess = q.len().saturating_sub(TAPE_CAP);
+        if let Some(evicted) = q.drain(..excess).next_back() {
+            self.evicted_through
+                .insert(symbol.to_string(), evicted.trade_id);
+        }
+    }
+}
+
+fn tape_append(c: &mut Criterion) {
+    // The same 1_000-maker sweep, its trades appended one by one or as one batch.
+    const SYMBOL: &str = "BTC-USD";
+    let book = deep_asks(10, 100);
+    let taker = || order(1_001, Side::Buy, 1_010, 1_000);
+    let trade = |trade_id: &mut u64, f: matching::order_book::Fill| {
+        *trade_id += 1;
+        TapeTrade {
+            trade_id: *trade_id,
+            symbol: SYMBOL.to_string(),
+            price: f.price,
+            qty: f.qty,
+            maker_seq: f.maker_seq,
+            taker_seq: f.taker_seq,
+        }
+    };
+    let per_trade = |book: &mut OrderBook, tape: &mut Tape| {
+        let mut trade_id = TAPE_CAP as u64;
+        book.add_with(taker(), |f| {
+            tape.append_one(SYMBOL, trade(&mut trade_id, f))
+        });
+    };
+    let batched = |book: &mut OrderBook, tape: &mut Tape| {
+        let mut trade_id = TAPE_CAP as u64;
+        let mut trades = Vec::new();
+        book.add_with(taker(), |f| trades.push(trade(&mut trade_id, f)));
+        tape.append_batch(SYMBOL, trades);
+    };
+
+    let (mut b1, mut t1) = (book_clone(&book), Tape::full(SYMBOL));
+    let one = allocations(|| per_trade(&mut b1, &mut t1));
+    let (mut b2, mut t2) = (book_clone(&book), Tape::full(SYMBOL));
+    let batch = allocations(|| batched(&mut b2, &mut t2));
+    println!("1000-maker sweep + tape append allocations: per trade {one}, batched {batch}");
+
+    let mut g = c.benchmark_group("tape_append");
+    g.throughput(Throughput::Elements(1_000));
+    g.bench_function("per_trade", |b| {
+        b.iter_batched(
+            || (book_clone(&book), Tape::full(SYMBOL)),
+            |(mut book, mut tape)| {
+                per_trade(&mut book, &mut tape);
+                black_box(tape)
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    g.bench_function("batched", |b| {
+        b.iter_batched(
+            || (book_clone(&book), Tape::full(SYMBOL)),
+            |(mut book, mut tape)| {
+                batched(&mut book, &mut tape);
+                black_box(tape)
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    g.finish();
+}
+
+fn heavy_cancel(c: &mut Criterion) {
+    // Rest 10k orders, then cancel 90% of them in arrival order (quote churn).
+    let mut rng = Rng(0xdead_beef_cafe_f00d);
+    let stream: Vec<Order> = (1..=ORDERS)
+        .map(|seq| order(seq, Side::Buy, 900 + rng.below(100), 1))
+        .collect();
+    let cancels: Vec<u64> = (1..=ORDERS).filter(|seq| seq % 10 != 0).collect();
+
+    let mut g = c.benchmark_group("heavy_cancel");
+    g.throughput(Throughput::Elements(ORDERS + cancels.len() as u64));
+    g.bench_function("10k_rest_9k_cancel", |b| {
+        b.iter_batched(
+            || stream.clone(),
+            |orders| {
+                let mut book = OrderBook::new();
+                for o in orders {
+                    book.add(o);
+                }
+                for seq in cancels.iter() {
+                    black_box(book.cancel(*seq));
+                }
+                book
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    g.finish();
+}
+
+/// `OrderBook` isn't `Clone` (books are never copied in the engine); rebuild from its levels.
+fn book_clone(book: &OrderBook) -> OrderBook {
+    let mut clone = OrderBook::with_negative_prices(book.allow_negative_price);
+    for o in book.bids.values().chain(book.asks.values()).flatten() {
+        clone.rest(o.clone());
+    }
+    clone
+}
+
+criterion_group!(
+    benches,
+    pure_rest,
+    mixed_rest_cross,
+    deep_sweep,
+    fill_delivery,
+    tape_append,
+    heavy_cancel
+);
+criterion_main!(benches);
@@ -1,9 +1,6 @@
 fn main() {
     tonic_build::configure()
         .build_server(true)
-        .compile(
-            &["../../../proto/engine.proto"],
-            &["../../../proto"],
-        )
+        .compile(&["../../../proto/engine.proto"], &["../../../proto"])
         .unwrap();
 }
@@ -0,0 +1,212 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+
+/// Per-symbol engine settings.
+/// Symbols without an entry get `SymbolConfig::default()` (today's unrestricted behavior).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SymbolConfig {
+    pub circuit_breaker: Option<CircuitBreakerConfig>,
+    // Accept price < 0 (calendar spreads, some energy products). Default: rejected.
+    pub allow_negative_price: bool,
+    // Reject price == 0 at order entry. Default: accepted; a 0 bid then only crosses asks at 0
+    // (or below, with negative prices). Entry-only, so WAL replay still rebuilds older 0-priced orders.
+    pub reject_zero_price: bool,
+    // Price must be a multiple of tick_size, qty a multiple of lot_size. Default: any integer.
+    pub tick_size: Option<i64>,
+    pub lot_size: Option<i64>,
+    // Largest qty a single order may carry. Default: unbounded.
+    pub max_qty: Option<i64>,
+    // Signed basis points of |price| * qty. Negative maker = rebate (credit to the maker).
+    pub maker_fee_bps: i64,
+    pub taker_fee_bps: i64,
+    // Decimal places of qty: an integer qty counts 10^-qty_scale units (3 = thousandths). Default 0.
+    // Fixed once a symbol has WAL/snapshot state; restore rejects a change.
+    pub qty_scale: u32,
+    // Tape one trade per price level a taker sweeps instead of one per maker: qty and fees summed,
+    // first fill's trade_id, maker_seq 0 if several makers. Responses and order events keep every
+    // fill; GetOrderFills only finds a maker in trades it filled alone. Default: one trade per fill.
+    pub coalesce_tape: bool,
+}
+
+/// 10^18 still fits in i64.
+pub const MAX_QTY_SCALE: u32 = 18;
+
+/// Auto-halt when the last trade moves too far from the window's reference price.
+///
+/// The reference is the first trade price of the current window; it is re-anchored once
+/// `window_ms` has elapsed, and after every resume. A trip halts the symbol for `cooldown_ms`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CircuitBreakerConfig {
+    pub threshold_bps: i64,
+    pub window_ms: i64,
+    pub cooldown_ms: i64,
+}
+
+static DEFAULT_SYMBOL_CONFIG: SymbolConfig = SymbolConfig {
+    circuit_breaker: None,
+    allow_negative_price: false,
+    reject_zero_price: false,
+    tick_size: None,
+    lot_size: None,
+    max_qty: None,
+    maker_fee_bps: 0,
+    taker_fee_bps: 0,
+    qty_scale: 0,
+    coalesce_tape: false,
+};
+
+/// Static engine configuration, loaded once at startup.
+///
+/// File format (JSON, keyed by symbol):
+/// `{ "BTC-USD": { "circuit_breaker": { "threshold_bps": 500, "window_ms": 60000, "cooldown_ms": 300000 },
+///                 "tick_size": 5, "lot_size": 10 },
+///    "CL-SPREAD": { "allow_negative_price": true } }`
+#[derive(Debug, Clone, Default)]
+pub struct EngineConfig {
+    symbols: HashMap<String, SymbolConfig>,
+}
+
+impl EngineConfig {
+    /// Load from a JSON file. Any parse or validation error is returned so startup can fail loudly.
+    pub fn load(path: &str) -> io::Result<Self> {
+        let buf = fs::read(path)?;
+        Self::from_json(&buf)
+    }
+
+    pub fn from_json(buf: &[u8]) -> io::Result<Self> {
+        let symbols: HashMap<String, SymbolConfig> = serde_json::from_slice(buf).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("symbol config parse error: {}", e),
+            )
+        })?;
+
+        let cfg = Self { symbols };
+        cfg.validate()?;
+        Ok(cfg)
+    }
+
+    fn validate(&self) -> io::Result<()> {
+        for (symbol, sc) in self.symbols.iter() {
+            if let Some(cb) = sc.circuit_breaker {
+                if cb.threshold_bps <= 0 || cb.window_ms <= 0 || cb.cooldown_ms <= 0 {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "symbol config '{}': circuit_breaker threshold_bps, window_ms and cooldown_ms must be > 0",
+                            symbol
+                        ),
+                    ));
+                }
+            }
+            if sc.tick_size.is_some_and(|t| t <= 0)
+                || sc.lot_size.is_some_and(|l| l <= 0)
+                || sc.max_qty.is_some_and(|m| m <= 0)
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "symbol config '{}': tick_size, lot_size and max_qty must be > 0",
+                        symbol
+                    ),
+                ));
+            }
+            // Only makers may be paid; a rebate larger than the taker fee would pay out on every trade.
+            if sc.taker_fee_bps < 0
+                || sc.taker_fee_bps > 10_000
+                || sc.maker_fee_bps.abs() > 10_000
+                || sc.maker_fee_bps + sc.taker_fee_bps < 0
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "symbol config '{}': fees must satisfy 0 <= taker_fee_bps <= 10000, |maker_fee_bps| <= 10000 and maker + taker >= 0",
+                        symbol
+                    ),
+                ));
+            }
+            if sc.qty_scale > MAX_QTY_SCALE {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "symbol config '{}': qty_scale must be <= {}",
+                        symbol, MAX_QTY_SCALE
+                    ),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn symbol(&self, symbol: &str) -> &SymbolConfig {
+        self.symbols.get(symbol).unwrap_or(&DEFAULT_SYMBOL_CONFIG)
+    }
+
+    pub fn symbols(&self) -> impl Iterator<Item = (&String, &SymbolConfig)> {
+        self.symbols.iter()
+    }
+
+    pub fn symbol_count(&self) -> usize {
+        self.symbols.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_symbol_gets_defaults() {
+        let cfg = EngineConfig::from_json(br#"{ "ETH-USD": {} }"#).unwrap();
+        assert!(cfg.symbol("BTC-USD").circuit_breaker.is_none());
+        assert!(cfg.symbol("ETH-USD").circuit_breaker.is_none());
+        assert!(!cfg.symbol("ETH-USD").allow_negative_price);
+    }
+
+    #[test]
+    fn rejects_non_positive_breaker_settings() {
+        let err = EngineConfig::from_json(
+            br#"{ "BTC-USD": { "circuit_breaker": { "threshold_bps": 500, "window_ms": 1000, "cooldown_ms": 0 } } }"#,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("BTC-USD"));
+    }
+
+    #[test]
+    fn rejects_non_positive_tick_and_lot() {
+        assert!(EngineConfig::from_json(br#"{ "BTC-USD": { "tick_size": 0 } }"#).is_err());
+        assert!(EngineConfig::from_json(br#"{ "BTC-USD": { "lot_size": -1 } }"#).is_err());
+        assert!(EngineConfig::from_json(br#"{ "BTC-USD": { "max_qty": 0 } }"#).is_err());
+    }
+
+    #[test]
+    fn maker_rebate_must_be_covered_by_taker_fee() {
+        let cfg = EngineConfig::from_json(
+            br#"{ "BTC-USD": { "maker_fee_bps": -2, "taker_fee_bps": 5 } }"#,
+        )
+        .unwrap();
+        assert_eq!(cfg.symbol("BTC-USD").maker_fee_bps, -2);
+        assert!(EngineConfig::from_json(
+            br#"{ "BTC-USD": { "maker_fee_bps": -6, "taker_fee_bps": 5 } }"#
+        )
+        .is_err());
+        assert!(EngineConfig::from_json(br#"{ "BTC-USD": { "taker_fee_bps": -1 } }"#).is_err());
+    }
+
+    #[test]
+    fn qty_scale_defaults_to_integer_and_is_bounded() {
+        let cfg = EngineConfig::from_json(br#"{ "BTC-USD": { "qty_scale": 18 } }"#).unwrap();
+        assert_eq!(cfg.symbol("BTC-USD").qty_scale, 18);
+        assert_eq!(cfg.symbol("ETH-USD").qty_scale, 0);
+        assert!(EngineConfig::from_json(br#"{ "BTC-USD": { "qty_scale": 19 } }"#).is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        assert!(EngineConfig::from_json(br#"{ "BTC-USD": { "circut_breaker": null } }"#).is_err());
+    }
+}
@@ -0,0 +1,194 @@
+use std::collections::VecDeque;
+
+use tokio::sync::broadcast;
+
+use crate::engine::OrderEvent;
+
+/// Events kept for resume/lag recovery.
+const MAX_RETAINED_EVENTS: usize = 10_000;
+/// Live fan-out buffer per subscriber before it is reported as lagged.
+const BROADCAST_CAPACITY: usize = 4_096;
+
+/// Order-lifecycle event log: sequenced, bounded, fanned out to subscribers.
+///
+/// `emit` is called under the engine state lock and never blocks: a slow subscriber lags
+/// on its own receiver and is re-synced from `recent` (see `EventCursor` for what it sees if the
+/// events it missed were already evicted). event_seq restarts at 1 on every process start,
+/// which the `epoch` stamped on each event (start time, unix ms) tells clients.
+#[derive(Debug)]
+pub struct OrderEvents {
+    epoch: u64,
+    next_seq: u64,
+    recent: VecDeque<OrderEvent>,
+    tx: broadcast::Sender<OrderEvent>,
+}
+
+impl Default for OrderEvents {
+    fn default() -> Self {
+        let (tx, _) = broadcast::channel(BROADCAST_CAPACITY);
+        Self {
+            epoch: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64,
+            next_seq: 0,
+            recent: VecDeque::new(),
+            tx,
+        }
+    }
+}
+
+impl OrderEvents {
+    /// Assign the next event_seq, retain, and broadcast.
+    pub fn emit(&mut self, mut ev: OrderEvent) {
+        self.next_seq += 1;
+        ev.event_seq = self.next_seq;
+        ev.epoch = self.epoch;
+
+        self.recent.push_back(ev.clone());
+        while self.recent.len() > MAX_RETAINED_EVENTS {
+            self.recent.pop_front();
+        }
+
+        // Err only means "no subscribers right now".
+        let _ = self.tx.send(ev);
+    }
+
+    /// Retained events with event_seq > after_seq, oldest first.
+    pub fn since(&self, after_seq: u64) -> Vec<OrderEvent> {
+        self.recent
+            .iter()
+            .filter(|ev| ev.event_seq > after_seq)
+            .cloned()
+            .collect()
+    }
+
+    /// Backlog after `after_seq` plus a live receiver, taken atomically (caller holds the state lock),
+    /// so nothing emitted in between is lost.
+    pub fn subscribe(&self, after_seq: u64) -> (Vec<OrderEvent>, broadcast::Receiver<OrderEvent>) {
+        (self.since(after_seq), self.tx.subscribe())
+    }
+}
+
+/// One `StreamOrderEvents` subscriber's position. event_seq is engine-wide, so a filtered stream
+/// can't tell a skipped value from a lost event; the cursor sees every event, filtered or not,
+/// and numbers what it delivers (`stream_seq`) so the stream itself is contiguous.
+#[derive(Debug)]
+pub struct EventCursor {
+    prefix: String,
+    last_event_seq: u64,
+    delivered: u64,
+    // An event_seq was skipped (evicted before this stream read it): flag the next delivery.
+    gap: bool,
+}
+
+impl EventCursor {
+    pub fn new(prefix: String, after_event_seq: u64) -> Self {
+        Self {
+            prefix,
+            last_event_seq: after_event_seq,
+            delivered: 0,
+            gap: false,
+        }
+    }
+
+    pub fn last_event_seq(&self) -> u64 {
+        self.last_event_seq
+    }
+
+    /// Events after the cursor that match the prefix, stamped with `stream_seq` and `gap`.
+    /// Events already seen (a lag re-sync overlaps the live buffer) are dropped.
+    pub fn select(&mut self, events: Vec<OrderEvent>) -> Vec<OrderEvent> {
+        let mut out = Vec::new();
+        for mut ev in events {
+            if ev.event_seq <= self.last_event_seq {
+                continue;
+            }
+            // Whether the missing events matched the prefix is unknowable once they're evicted.
+            self.gap |= ev.event_seq > self.last_event_seq + 1;
+            self.last_event_seq = ev.event_seq;
+            if !ev.client_order_id.starts_with(&self.prefix) {
+                continue;
+            }
+            self.delivered += 1;
+            ev.stream_seq = self.delivered;
+            ev.gap = std::mem::take(&mut self.gap);
+            out.push(ev);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ev(client_order_id: &str) -> OrderEvent {
+        OrderEvent {
+            client_order_id: client_order_id.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn events_are_sequenced_and_resumable() {
+        let mut log = OrderEvents::default();
+        log.emit(ev("a"));
+        log.emit(ev("b"));
+
+        let (backlog, mut rx) = log.subscribe(1);
+        assert_eq!(backlog.len(), 1);
+        assert_eq!(
+            (backlog[0].event_seq, backlog[0].client_order_id.as_str()),
+            (2, "b")
+        );
+
+        log.emit(ev("c"));
+        assert_eq!(rx.try_recv().unwrap().event_seq, 3);
+    }
+
+    #[test]
+    fn cursor_numbers_its_own_stream_and_flags_evicted_events() {
+        let mut log = OrderEvents::default();
+        for id in ["a1", "b1", "a2", "b2", "a3"] {
+            log.emit(ev(id));
+        }
+
+        // Filtered out events don't make the stream look lossy
+        let mut cursor = EventCursor::new("a".to_string(), 0);
+        let got = cursor.select(log.since(0));
+        let rows: Vec<_> = got
+            .iter()
+            .map(|e| (e.event_seq, e.stream_seq, e.gap))
+            .collect();
+        assert_eq!(rows, vec![(1, 1, false), (3, 2, false), (5, 3, false)]);
+        assert!(got.iter().all(|e| e.epoch == got[0].epoch && e.epoch > 0));
+
+        // A re-sync overlapping what was already read delivers nothing twice
+        assert!(cursor.select(log.since(3)).is_empty());
+        assert_eq!(cursor.last_event_seq(), 5);
+
+        // Events 6..=7 evicted before this stream read them: the next delivery says so, once
+        log.emit(ev("b3"));
+        log.emit(ev("a4"));
+        log.emit(ev("b4"));
+        log.emit(ev("a5"));
+        let got = cursor.select(log.since(7));
+        let rows: Vec<_> = got
+            .iter()
+            .map(|e| (e.event_seq, e.stream_seq, e.gap))
+            .collect();
+        assert_eq!(rows, vec![(9, 4, true)]);
+    }
+
+    #[test]
+    fn retention_is_bounded() {
+        let mut log = OrderEvents::default();
+        for _ in 0..MAX_RETAINED_EVENTS + 5 {
+            log.emit(ev("x"));
+        }
+        let all = log.since(0);
+        assert_eq!(all.len(), MAX_RETAINED_EVENTS);
+        assert_eq!(all[0].event_seq, 6);
+    }
+}
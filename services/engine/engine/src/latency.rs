@@ -0,0 +1,175 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+// Log-linear buckets: exact below 8us, then 8 sub-buckets per power of two (<= 12.5% error).
+const SUB_BUCKETS: u64 = 8;
+const BUCKETS: usize = 62 * SUB_BUCKETS as usize;
+
+/// Lock-free latency histogram in microseconds. `record` is two relaxed atomic ops, so it is
+/// cheap enough to sit on the order path; readers see a best-effort (not instantaneous) view.
+#[derive(Debug)]
+pub struct LatencyHistogram {
+    buckets: Box<[AtomicU64]>,
+    max_us: AtomicU64,
+}
+
+/// Percentiles are bucket upper bounds, so they never understate latency.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LatencySummary {
+    pub count: u64,
+    pub p50_us: u64,
+    pub p95_us: u64,
+    pub p99_us: u64,
+    pub max_us: u64,
+}
+
+impl LatencyHistogram {
+    pub fn new() -> Self {
+        Self {
+            buckets: (0..BUCKETS).map(|_| AtomicU64::new(0)).collect(),
+            max_us: AtomicU64::new(0),
+        }
+    }
+
+    pub fn record(&self, d: Duration) {
+        let us = d.as_micros().min(u64::MAX as u128) as u64;
+        self.buckets[bucket_of(us)].fetch_add(1, Ordering::Relaxed);
+        self.max_us.fetch_max(us, Ordering::Relaxed);
+    }
+
+    pub fn summary(&self) -> LatencySummary {
+        let counts: Vec<u64> = self
+            .buckets
+            .iter()
+            .map(|b| b.load(Ordering::Relaxed))
+            .collect();
+        summarize(&counts, self.max_us.load(Ordering::Relaxed))
+    }
+
+    /// `summary`, clearing as it reads to start a fresh window. Each bucket is swapped to zero,
+    /// so a record racing with it is counted in exactly one window, never lost.
+    pub fn take_summary(&self) -> LatencySummary {
+        let counts: Vec<u64> = self
+            .buckets
+            .iter()
+            .map(|b| b.swap(0, Ordering::Relaxed))
+            .collect();
+        summarize(&counts, self.max_us.swap(0, Ordering::Relaxed))
+    }
+}
+
+/// `max_us` is a separate atomic from the bucket a record lands in, so a racing take can split
+/// the two; it is clamped to the highest counted bucket to stay consistent with the counts.
+fn summarize(counts: &[u64], max_us: u64) -> LatencySummary {
+    let count: u64 = counts.iter().sum();
+    let Some(top) = counts.iter().rposition(|c| *c > 0) else {
+        return LatencySummary::default();
+    };
+    let top_lower = if top == 0 {
+        0
+    } else {
+        bucket_upper(top - 1) + 1
+    };
+    let max_us = max_us.clamp(top_lower, bucket_upper(top));
+
+    let percentile = |q: f64| {
+        let target = ((count as f64 * q).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (idx, c) in counts.iter().enumerate() {
+            seen += c;
+            if seen >= target {
+                return bucket_upper(idx).min(max_us);
+            }
+        }
+        max_us
+    };
+
+    LatencySummary {
+        count,
+        p50_us: percentile(0.50),
+        p95_us: percentile(0.95),
+        p99_us: percentile(0.99),
+        max_us,
+    }
+}
+
+fn bucket_of(us: u64) -> usize {
+    if us < SUB_BUCKETS {
+        return us as usize;
+    }
+    let exp = 63 - us.leading_zeros() as u64; // >= 3
+    let sub = (us >> (exp - 3)) & (SUB_BUCKETS - 1);
+    ((exp - 2) * SUB_BUCKETS + sub) as usize
+}
+
+fn bucket_upper(idx: usize) -> u64 {
+    let idx = idx as u64;
+    if idx < SUB_BUCKETS {
+        return idx;
+    }
+    let exp = idx / SUB_BUCKETS + 2;
+    let sub = idx % SUB_BUCKETS;
+    let width = 1u64 << (exp - 3);
+    ((SUB_BUCKETS + sub) << (exp - 3)).saturating_add(width - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buckets_bound_their_values() {
+        for us in [0, 7, 8, 15, 16, 17, 1_000, 123_456, u64::MAX / 3, u64::MAX] {
+            let idx = bucket_of(us);
+            assert!(idx < BUCKETS);
+            assert!(bucket_upper(idx) >= us, "{us}");
+            assert!(idx == 0 || bucket_upper(idx - 1) < us, "{us}");
+        }
+    }
+
+    #[test]
+    fn percentiles_and_reset() {
+        let h = LatencyHistogram::new();
+        for us in 1..=100 {
+            h.record(Duration::from_micros(us));
+        }
+
+        let s = h.summary();
+        assert_eq!((s.count, s.max_us), (100, 100));
+        // Within one bucket (12.5%) above the exact value
+        assert!((50..=56).contains(&s.p50_us), "{s:?}");
+        assert!((95..=100).contains(&s.p95_us), "{s:?}");
+        assert!((99..=100).contains(&s.p99_us), "{s:?}");
+
+        assert_eq!(h.take_summary(), s);
+        assert_eq!(h.summary(), LatencySummary::default());
+    }
+
+    #[test]
+    fn take_summary_never_loses_a_racing_record() {
+        const THREADS: u64 = 4;
+        const PER_THREAD: u64 = 20_000;
+        let h = std::sync::Arc::new(LatencyHistogram::new());
+        let writers: Vec<_> = (0..THREADS)
+            .map(|t| {
+                let h = h.clone();
+                std::thread::spawn(move || {
+                    for i in 0..PER_THREAD {
+                        h.record(Duration::from_micros(t * 1_000 + i % 500));
+                    }
+                })
+            })
+            .collect();
+
+        let mut counted = 0;
+        while !writers.iter().all(|w| w.is_finished()) {
+            let s = h.take_summary();
+            assert!(s.count == 0 || s.p99_us <= s.max_us, "{s:?}");
+            counted += s.count;
+        }
+        for w in writers {
+            w.join().unwrap();
+        }
+        assert_eq!(counted + h.take_summary().count, THREADS * PER_THREAD);
+    }
+}
@@ -0,0 +1,7 @@
+//! The matcher on its own: `OrderBook` is synchronous and owns no lock, WAL or gRPC state, so
+//! benchmarks and offline tools drive it directly. The `engine` binary wraps it.
+//!
+//! Only the book lives here. `EngineState` and submit/cancel stay in the binary: they append to
+//! the WAL and build proto responses, both binary-only, and still run under the service's lock.
+
+pub mod order_book;
@@ -0,0 +1,65 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use tonic::metadata::{MetadataMap, MetadataValue};
+use tonic::Status;
+use tracing_subscriber::EnvFilter;
+
+/// Request metadata key a client can set to join its logs with ours; echoed on every response.
+pub const CORRELATION_ID_HEADER: &str = "x-correlation-id";
+const MAX_CORRELATION_ID_LEN: usize = 128;
+
+static NEXT_GENERATED_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Install the global tracing subscriber.
+/// `ENGINE_LOG` takes `EnvFilter` directives (default "warn": rejections, failures and recovery
+/// warnings; "info" adds startup/restore/snapshot progress and one line per accepted request). `ENGINE_LOG_FORMAT` is "text" (default) or "json".
+pub fn init() -> Result<(), String> {
+    let directives = std::env::var("ENGINE_LOG").unwrap_or_else(|_| "warn".to_string());
+    let filter = EnvFilter::try_new(directives.trim()).map_err(|e| format!("ENGINE_LOG: {e}"))?;
+    let fmt = tracing_subscriber::fmt().with_env_filter(filter);
+
+    let format = std::env::var("ENGINE_LOG_FORMAT").unwrap_or_else(|_| "text".to_string());
+    match format.trim() {
+        "text" => fmt.try_init(),
+        "json" => fmt.json().flatten_event(true).try_init(),
+        other => {
+            return Err(format!(
+                "ENGINE_LOG_FORMAT must be 'text' or 'json', got '{}'",
+                other
+            ))
+        }
+    }
+    .map_err(|e| e.to_string())
+}
+
+/// The client's `x-correlation-id` if it sent a usable one, else a generated `eng-<pid>-<n>`.
+pub fn correlation_id(md: &MetadataMap) -> String {
+    let supplied = md
+        .get(CORRELATION_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::trim)
+        .filter(|v| !v.is_empty() && v.len() <= MAX_CORRELATION_ID_LEN);
+
+    match supplied {
+        Some(id) => id.to_string(),
+        None => format!(
+            "eng-{}-{}",
+            std::process::id(),
+            NEXT_GENERATED_ID.fetch_add(1, Ordering::Relaxed) + 1
+        ),
+    }
+}
+
+/// Attach the correlation id to outgoing response/error metadata.
+pub fn tag(md: &mut MetadataMap, cid: &str) {
+    if let Ok(v) = MetadataValue::try_from(cid) {
+        md.insert(CORRELATION_ID_HEADER, v);
+    }
+}
+
+/// Log a rejected request inside the current span and return the status tagged with its id.
+pub fn rejected(mut status: Status, cid: &str) -> Status {
+    tracing::warn!(code = ?status.code(), error = status.message(), "rejected");
+    tag(status.metadata_mut(), cid);
+    status
+}
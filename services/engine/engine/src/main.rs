@@ -1,15 +1,37 @@
 // services/engine/engine/src/main.rs
 
-mod order_book;
+// RPC helpers return tonic::Status directly; it is large but boxing it buys nothing here.
+#![allow(clippy::result_large_err)]
+
+mod config;
+mod events;
+mod latency;
+mod logging;
+mod publish;
+mod status;
 mod wal;
 
-use std::collections::{HashMap, VecDeque};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use matching::order_book;
 
-use order_book::{Order, OrderBook, Side as BookSide};
-use wal::{Wal, WalEntry};
+use config::{EngineConfig, SymbolConfig};
+use events::{EventCursor, OrderEvents};
+use latency::LatencyHistogram;
+use order_book::{level_qty, Order, OrderBook, RestingOrder, Side as BookSide};
+use publish::{ChannelPublisher, JsonLinesSink, NoopPublisher, TradePublisher};
+use status::invalid_field;
+use wal::{
+    DecodeMode, SeqMismatchPolicy, SnapshotAtomicity, SnapshotReplace, SnapshotWrite, StateDump,
+    TailRepair, Wal, WalEntry, WalKind, WalLayout, WalRetention,
+};
 
-use tonic::{transport::Server, Request, Response, Status};
+use tokio::sync::{broadcast, mpsc, Notify};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{metadata::MetadataMap, transport::Server, Request, Response, Status, Streaming};
 
 pub mod engine {
     tonic::include_proto!("engine.v1");
@@ -17,40 +39,225 @@ pub mod engine {
 
 use engine::engine_server::{Engine, EngineServer};
 use engine::{
-    Fill, GetBookDepthRequest, GetBookDepthResponse, GetRecentTradesRequest, GetRecentTradesResponse,
-    GetTopOfBookRequest, GetTopOfBookResponse, HealthRequest, HealthResponse, PriceLevel, Side,
-    SubmitOrderRequest, SubmitOrderResponse, Trade,
+    CancelByClientOrderIdRequest, CancelByClientOrderIdResponse, CancelRangeRequest,
+    CancelRangeResponse, ConsistencyIssue, DrainRequest, DrainResponse, DrainState, DumpStateChunk,
+    DumpStateRequest, Fill, ForceSnapshotRequest, ForceSnapshotResponse, GetBookDepthRequest,
+    GetBookDepthResponse, GetHaltStatusRequest, GetHaltStatusResponse, GetLatencyStatsRequest,
+    GetLatencyStatsResponse, GetLiquidityCurveRequest, GetLiquidityCurveResponse,
+    GetOrderFillsRequest, GetOrderFillsResponse, GetQueuePositionRequest, GetQueuePositionResponse,
+    GetRecentTradesRequest, GetRecentTradesResponse, GetRestingNotionalRequest,
+    GetRestingNotionalResponse, GetTickerRequest, GetTickerResponse, GetTopOfBookRequest,
+    GetTopOfBookResponse, GetTradeCursorRequest, GetTradeCursorResponse, HealthRequest,
+    HealthResponse, LiquidityPoint, LiquidityRole, MakerShare, OrderEvent, OrderEventType,
+    OrderFill, PriceLevel, SessionRequest, SessionResponse, SetOrderEntryRequest,
+    SetOrderEntryResponse, Side, StreamOrderEventsRequest, SubmitOrderRequest, SubmitOrderResponse,
+    Trade, UndrainRequest, UndrainResponse, VerifyConsistencyRequest, VerifyConsistencyResponse,
 };
 
 const MAX_TRADES_PER_SYMBOL: usize = 10_000;
 const MAX_TRADES_LIMIT: usize = 1_000;
+const SESSION_CHANNEL_CAPACITY: usize = 64;
+const EVENT_STREAM_CHANNEL_CAPACITY: usize = 256;
+const HALT_RESUME_TICK: Duration = Duration::from_millis(250);
+const DRAIN_TICK: Duration = Duration::from_millis(250);
+const ADMIN_TOKEN_HEADER: &str = "x-admin-token";
+const DUMP_CHANNEL_CAPACITY: usize = 16;
+// Resting orders per DumpState "book" chunk: well under tonic's 4 MB message limit.
+const DUMP_CHUNK_ORDERS: usize = 1_000;
+const TRADE_SINK_CHANNEL_CAPACITY: usize = 8_192;
 
-#[derive(Debug)]
+/// Trading halt on one symbol (set by the circuit breaker, WAL-logged as HALT/RESUME).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymbolHalt {
+    pub since_ms: i64,
+    pub resume_at_ms: i64,
+    pub trigger_price: i64,
+}
+
+/// Circuit-breaker reference price: first trade of the current window.
+#[derive(Debug, Clone, Copy)]
+pub struct BreakerRef {
+    pub price: i64,
+    pub since_ms: i64,
+}
+
+#[derive(Debug, Default)]
 pub struct EngineState {
     pub seq: u64,
+    // External seq mode: engine-originated WAL entries logged so far (see `SeqMode`).
+    pub engine_seq: u64,
     // symbol -> full price-level book (real FIFO order book)
     pub books: HashMap<String, OrderBook>,
 
     // Trade tape (pull-based). Per symbol ring buffer of recent trades.
     pub next_trade_id: u64,
     pub trades: HashMap<String, VecDeque<Trade>>,
+    // Newest trade_id evicted from each symbol's ring (tape gap detection).
+    pub trades_evicted_through: HashMap<String, u64>,
+    // Traded qty per symbol since process start (ticker volume; in-memory only).
+    pub volume: HashMap<String, i64>,
+
+    // Live streaming sessions: session_id -> seq -> symbol of its orders still resting, and the
+    // reverse index (order seq -> session_id) so a fill or cancel drops the order from its session.
+    // In-memory only: after a crash nothing replays as session-owned, so a dead session's orders
+    // stay resting like any other order until the client cancels them.
+    pub next_session_id: u64,
+    pub sessions: HashMap<u64, BTreeMap<u64, String>>,
+    pub session_of: HashMap<u64, u64>,
+
+    // Halted symbols (persisted via WAL + snapshot) and breaker references (in-memory only).
+    pub halts: HashMap<String, SymbolHalt>,
+    pub breaker_refs: HashMap<String, BreakerRef>,
+
+    // Symbols closed to new orders by an operator (persisted via WAL + snapshot). Unlike a halt
+    // this is purely operational: cancels still work and it never expires on its own.
+    pub entry_disabled: BTreeSet<String>,
+
+    // Order lifecycle events (in-memory; not replayed).
+    pub events: OrderEvents,
+
+    // Static per-symbol config; part of state so replay builds books the same way live does.
+    pub config: EngineConfig,
+
+    // Seq covered by the last WAL checkpoint written by this process (runtime only).
+    pub last_checkpoint_seq: u64,
+}
+
+impl EngineState {
+    /// Book for `symbol`, created with its per-symbol settings on first use.
+    pub fn book_mut(&mut self, symbol: &str) -> &mut OrderBook {
+        let config = &self.config;
+        self.books.entry(symbol.to_string()).or_insert_with(|| {
+            OrderBook::with_negative_prices(config.symbol(symbol).allow_negative_price)
+        })
+    }
+
+    /// Forget `seq` in the session that left it resting, if any: it filled or was cancelled.
+    pub fn release_session_order(&mut self, seq: u64) {
+        if let Some(session_id) = self.session_of.remove(&seq) {
+            if let Some(orders) = self.sessions.get_mut(&session_id) {
+                orders.remove(&seq);
+            }
+        }
+    }
+}
+
+/// Who assigns order seqs. Fixed for the process so the two can't interleave.
+/// In external mode engine-originated events (cancels, halts, resumes, entry toggles) leave the
+/// seq space to upstream: they repeat the last seq and count on `engine_seq` instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SeqMode {
+    Internal,
+    External,
+}
+
+/// What to do when a WAL append fails (`ENGINE_WAL_FAILURE_POLICY`).
+/// Memory is never mutated before its WAL entry is durable, so every policy leaves the two in agreement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WalFailurePolicy {
+    /// Reject the request with UNAVAILABLE (default).
+    FailRequest,
+    /// Retry up to N more times (1ms, 2ms, ... backoff, holding the state lock), then reject.
+    Retry(u32),
+    /// Reject, refuse every later write and shut the engine down (snapshot first if possible).
+    FailStop,
+}
+
+impl WalFailurePolicy {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "FAIL_REQUEST" => Some(Self::FailRequest),
+            "FAIL_STOP" => Some(Self::FailStop),
+            _ => s.strip_prefix("RETRY_")?.parse().ok().map(Self::Retry),
+        }
+    }
+}
+
+/// Tripped once under FAIL_STOP; main's shutdown future waits on `notify`.
+#[derive(Debug, Default)]
+struct FailStop {
+    tripped: AtomicBool,
+    notify: Notify,
 }
 
-impl Default for EngineState {
+/// Rolling-deploy drain state (`Drain`/`Undrain`), in memory only: a restarted node comes back serving.
+/// The phase only changes under the state lock, so no order can land behind the final snapshot;
+/// it is an atomic so `Health` can read it without that lock.
+#[derive(Debug)]
+struct Drain {
+    phase: AtomicI32,
+    // Quiet-period clock while draining: the last seq seen and when it was first seen.
+    quiet: Mutex<(u64, Instant)>,
+}
+
+impl Default for Drain {
     fn default() -> Self {
         Self {
-            seq: 0,
-            books: HashMap::new(),
-            next_trade_id: 0,
-            trades: HashMap::new(),
+            phase: AtomicI32::new(DrainState::Serving as i32),
+            quiet: Mutex::new((0, Instant::now())),
+        }
+    }
+}
+
+impl Drain {
+    fn phase(&self) -> DrainState {
+        DrainState::try_from(self.phase.load(Ordering::Acquire)).unwrap_or(DrainState::Serving)
+    }
+
+    fn set(&self, phase: DrainState) {
+        self.phase.store(phase as i32, Ordering::Release);
+    }
+}
+
+/// Process-lifetime counters for `Health`, plus order-path latency for `GetLatencyStats`.
+/// Atomics so the health path never takes the state mutex; the counters only grow and reset on restart.
+#[derive(Debug)]
+struct EngineStats {
+    started_at: Instant,
+    orders_processed: AtomicU64,
+    fills_total: AtomicU64,
+    seq: AtomicU64,
+    // Request receipt -> WAL append done / -> matching done (accepted orders only).
+    to_durable: LatencyHistogram,
+    to_matched: LatencyHistogram,
+}
+
+impl EngineStats {
+    fn new(seq: u64) -> Self {
+        Self {
+            started_at: Instant::now(),
+            orders_processed: AtomicU64::new(0),
+            fills_total: AtomicU64::new(0),
+            seq: AtomicU64::new(seq),
+            to_durable: LatencyHistogram::new(),
+            to_matched: LatencyHistogram::new(),
         }
     }
+
+    fn record_order(&self, seq: u64, fills: usize) {
+        self.orders_processed.fetch_add(1, Ordering::Relaxed);
+        self.fills_total.fetch_add(fills as u64, Ordering::Relaxed);
+        self.seq.fetch_max(seq, Ordering::Relaxed);
+    }
 }
 
 #[derive(Clone)]
 struct EngineSvc {
     state: Arc<Mutex<EngineState>>,
     wal: Wal,
+    stats: Arc<EngineStats>,
+    // Maintenance RPCs are refused outright when unset.
+    admin_token: Option<Arc<str>>,
+    seq_mode: SeqMode,
+    wal_policy: WalFailurePolicy,
+    fail_stop: Arc<FailStop>,
+    // Every trade as it is taped; never blocks (see `TradePublisher`).
+    publisher: Arc<dyn TradePublisher>,
+    // Write a WAL checkpoint once this many seqs have passed since the last one (0 = never).
+    checkpoint_every: u64,
+    drain: Arc<Drain>,
+    // How long a draining engine must go without taking a seq before its final snapshot.
+    drain_quiet: Duration,
 }
 
 impl EngineSvc {
@@ -59,7 +266,60 @@ impl EngineSvc {
         F: FnOnce(&mut EngineState) -> R,
     {
         let mut st = self.state.lock().expect("engine state mutex poisoned");
-        f(&mut st)
+        let out = f(&mut st);
+        self.maybe_checkpoint(&mut st);
+        out
+    }
+
+    /// Log `state_checksum` at the current seq once `checkpoint_every` seqs have passed. Runs at
+    /// the end of `with_state`, where every mutation made under the lock is complete and logged.
+    /// The request that got here has already succeeded, so a failed append is only reported.
+    fn maybe_checkpoint(&self, st: &mut EngineState) {
+        if self.checkpoint_every == 0 || st.seq < st.last_checkpoint_seq + self.checkpoint_every {
+            return;
+        }
+        let entry = WalEntry {
+            kind: WalKind::Checkpoint,
+            seq: st.seq,
+            engine_seq: st.engine_seq,
+            checksum: Some(wal::state_checksum(st)),
+            ..Default::default()
+        };
+        match self.append_wal(&entry) {
+            Ok(()) => st.last_checkpoint_seq = st.seq,
+            Err(e) => tracing::error!(seq = st.seq, error = %e, "wal checkpoint not written"),
+        }
+    }
+
+    /// Every WAL append goes through here so the failure policy applies uniformly.
+    fn append_wal(&self, entry: &WalEntry) -> std::io::Result<()> {
+        if self.fail_stop.tripped.load(Ordering::Acquire) {
+            return Err(std::io::Error::other(
+                "engine is stopping after a WAL failure",
+            ));
+        }
+
+        let mut attempt = 0;
+        loop {
+            let Err(e) = self.wal.append(entry) else {
+                return Ok(());
+            };
+            match self.wal_policy {
+                WalFailurePolicy::Retry(n) if attempt < n => {
+                    attempt += 1;
+                    tracing::warn!(attempt, retries = n, error = %e, "wal append failed, retrying");
+                    std::thread::sleep(Duration::from_millis(attempt as u64));
+                }
+                WalFailurePolicy::FailStop => {
+                    if !self.fail_stop.tripped.swap(true, Ordering::AcqRel) {
+                        tracing::error!(error = %e, "wal append failed, FAIL_STOP: shutting down");
+                        self.fail_stop.notify.notify_one();
+                    }
+                    return Err(e);
+                }
+                _ => return Err(e),
+            }
+        }
     }
 
     fn next_seq(st: &mut EngineState) -> u64 {
@@ -67,132 +327,315 @@ impl EngineSvc {
         st.seq
     }
 
-    fn next_trade_id(st: &mut EngineState) -> u64 {
-        st.next_trade_id += 1;
-        st.next_trade_id
+    /// (seq, engine_seq) for an engine-originated WAL entry: the next seq in internal mode, the
+    /// last seq and the next engine_seq in external mode, so upstream's next seq stays free.
+    fn next_engine_seq(&self, st: &mut EngineState) -> (u64, u64) {
+        match self.seq_mode {
+            SeqMode::Internal => (Self::next_seq(st), 0),
+            SeqMode::External => {
+                st.engine_seq += 1;
+                (st.seq, st.engine_seq)
+            }
+        }
     }
 
-    fn append_trade(st: &mut EngineState, symbol: &str, trade: Trade) {
-        let q = st
-            .trades
-            .entry(symbol.to_string())
-            .or_insert_with(VecDeque::new);
-        q.push_back(trade);
-
-        // Bounded memory
-        while q.len() > MAX_TRADES_PER_SYMBOL {
-            q.pop_front();
+    /// Give back what `next_engine_seq` took after a failed WAL append, keeping seqs gap-free.
+    fn release_engine_seq(&self, st: &mut EngineState) {
+        match self.seq_mode {
+            SeqMode::Internal => st.seq -= 1,
+            SeqMode::External => st.engine_seq -= 1,
         }
     }
-}
 
-fn env_or_default(key: &str, default: &str) -> String {
-    std::env::var(key)
-        .ok()
-        .map(|s| s.trim().to_string())
-        .filter(|s| !s.is_empty())
-        .unwrap_or_else(|| default.to_string())
-}
+    fn next_trade_id(st: &mut EngineState) -> u64 {
+        st.next_trade_id += 1;
+        st.next_trade_id
+    }
 
-#[tonic::async_trait]
-impl Engine for EngineSvc {
-    async fn health(
-        &self,
-        _req: Request<HealthRequest>,
-    ) -> Result<Response<HealthResponse>, Status> {
-        Ok(Response::new(HealthResponse {
-            status: "ok".to_string(),
-        }))
+    /// The per-symbol layout names a directory after each symbol, so a symbol it can't use is
+    /// rejected before it takes a seq, not when its WAL append fails.
+    fn check_symbol_layout(&self, symbol: &str) -> Result<(), Status> {
+        if self.wal.layout() == WalLayout::PerSymbol && !wal::symbol_names_a_dir(symbol) {
+            return Err(invalid_field(
+                "symbol",
+                symbol,
+                "must be ASCII letters, digits, '.', '_' or '-' (not '.' or '..') under ENGINE_WAL_LAYOUT=per_symbol",
+            ));
+        }
+        Ok(())
     }
 
-    async fn submit_order(
+    /// Validate, WAL-log and match one order. Shared by unary `SubmitOrder` and `Session`;
+    /// `session_id` tags resting orders for cancel-on-disconnect.
+    fn submit(
         &self,
-        req: Request<SubmitOrderRequest>,
-    ) -> Result<Response<SubmitOrderResponse>, Status> {
-        let o = req.into_inner();
+        mut o: SubmitOrderRequest,
+        session_id: Option<u64>,
+    ) -> Result<SubmitOrderResponse, Status> {
+        let received = Instant::now();
 
-        // Validation
         let symbol = o.symbol.trim().to_string();
         if symbol.is_empty() {
-            return Err(Status::invalid_argument("symbol must be non-empty"));
-        }
-        if o.qty <= 0 {
-            return Err(Status::invalid_argument("qty must be > 0"));
-        }
-        if o.price < 0 {
-            return Err(Status::invalid_argument("price must be >= 0"));
+            return Err(invalid_field("symbol", &o.symbol, "must be non-empty"));
         }
-        if o.side == Side::Unspecified as i32 {
-            return Err(Status::invalid_argument("side must be BUY or SELL"));
+        self.check_symbol_layout(&symbol)?;
+
+        match (self.seq_mode, o.external_seq) {
+            (SeqMode::Internal, x) if x != 0 => {
+                return Err(invalid_field(
+                    "external_seq",
+                    x,
+                    "must be 0: engine assigns seqs",
+                ))
+            }
+            (SeqMode::External, 0) => {
+                return Err(invalid_field(
+                    "external_seq",
+                    0,
+                    "is required in external seq mode",
+                ))
+            }
+            _ => {}
         }
 
         let client_order_id = o.client_order_id.trim().to_string();
 
         // Single-writer mutex: append WAL then mutate memory.
-        let (accepted_seq, fills_out) = self.with_state(|st| {
-            let seq = Self::next_seq(st);
+        let (accepted_seq, fills_out, qty_scale, sweep) = self.with_state(|st| {
+            // Under the lock, so the final drain snapshot can't miss an order that got past this.
+            if self.drain.phase() != DrainState::Serving {
+                return Err(Status::unavailable("engine is draining for a restart; retry on another node"));
+            }
+            let cfg = st.config.symbol(&symbol);
+            let qty_scale = cfg.qty_scale;
+            if !o.qty_decimal.is_empty() {
+                if o.qty != 0 {
+                    return Err(invalid_field("qty", o.qty, "must be 0 when qty_decimal is set"));
+                }
+                o.qty = parse_scaled_qty(o.qty_decimal.trim(), qty_scale).ok_or_else(|| {
+                    invalid_field(
+                        "qty_decimal",
+                        &o.qty_decimal,
+                        &format!("must be a decimal with at most {qty_scale} fractional digits that fits int64 when scaled"),
+                    )
+                })?;
+            }
+            validate_order(cfg, o.side, o.price, o.qty)?;
+            if o.min_fill_qty < 0 || o.min_fill_qty > o.qty {
+                return Err(invalid_field("min_fill_qty", o.min_fill_qty, "must be between 0 and qty"));
+            }
+
+            // Before the halt resume below or anything else that logs, so a stale seq leaves no trace.
+            if self.seq_mode == SeqMode::External && o.external_seq <= st.seq {
+                return Err(invalid_field(
+                    "external_seq",
+                    o.external_seq,
+                    &format!("must be > last seq {}", st.seq),
+                ));
+            }
+
+            if st.entry_disabled.contains(&symbol) {
+                return Err(Status::failed_precondition(format!(
+                    "symbol {} is not accepting new orders",
+                    symbol
+                )));
+            }
+
+            if let Some(h) = st.halts.get(&symbol) {
+                if now_ms() < h.resume_at_ms {
+                    return Err(Status::failed_precondition(format!(
+                        "symbol {} is halted until {}",
+                        symbol, h.resume_at_ms
+                    )));
+                }
+                self.resume_symbol(st, &symbol, now_ms())
+                    .map_err(wal_unavailable)?;
+            }
+
+            let side = if o.side == Side::Buy as i32 {
+                BookSide::Buy
+            } else {
+                BookSide::Sell
+            };
+
+            // Pre-scan before the WAL append, so a rejected order leaves no trace and replay is unaffected.
+            if o.min_fill_qty > 0 {
+                let matchable = st
+                    .books
+                    .get(&symbol)
+                    .map(|b| b.matchable_qty(side, o.price, o.qty, o.max_levels))
+                    .unwrap_or(0);
+                if matchable > 0 && matchable < o.min_fill_qty {
+                    return Err(Status::failed_precondition(format!(
+                        "only {} of min_fill_qty {} can fill immediately",
+                        matchable, o.min_fill_qty
+                    )));
+                }
+            }
+
+            let prev_seq = st.seq;
+            let seq = match self.seq_mode {
+                SeqMode::Internal => Self::next_seq(st),
+                SeqMode::External => {
+                    st.seq = o.external_seq;
+                    o.external_seq
+                }
+            };
 
             let side_str = if o.side == Side::Buy as i32 { "BUY" } else { "SELL" };
 
             // 1) Append WAL entry FIRST (durability boundary for "accepted")
             let entry = WalEntry {
+                kind: WalKind::Order,
                 seq,
                 symbol: symbol.clone(),
                 side: side_str.to_string(),
                 price: o.price,
                 qty: o.qty,
                 client_order_id: client_order_id.clone(),
-            };
+                qty_scale,
+                max_levels: o.max_levels,
+                // Validated above: a negative price here was allowed by the symbol's config.
+                allow_negative_price: o.price < 0,
+                ..Default::default()
+            }
+            .with_must_understand();
 
-            if let Err(e) = self.wal.append(&entry) {
+            if let Err(e) = self.append_wal(&entry) {
                 // Roll back seq so sequence stays gap-free if WAL write fails
-                st.seq -= 1;
-                return Err(Status::unavailable(format!("WAL append failed: {e}")));
+                st.seq = prev_seq;
+                return Err(wal_unavailable(e));
             }
+            self.stats.to_durable.record(received.elapsed());
 
             // 2) Apply to in-memory book (matching happens here)
-            let side = if o.side == Side::Buy as i32 {
-                BookSide::Buy
-            } else {
-                BookSide::Sell
-            };
+            let book = st.book_mut(&symbol);
+
+            // Makers' client ids only for their fill events, copied here rather than per Fill.
+            let mut fills = Vec::new();
+            let mut maker_ids = Vec::new();
+            let sweep = book.add_capped_with_maker(
+                Order {
+                    seq,
+                    side,
+                    price: o.price,
+                    qty: o.qty,
+                    client_order_id: client_order_id.clone(),
+                },
+                o.max_levels,
+                |f, maker| {
+                    fills.push(f);
+                    maker_ids.push(maker.client_order_id.clone());
+                },
+            );
 
-            let book = st.books.entry(symbol.clone()).or_insert_with(OrderBook::new);
+            let rested_qty = (sweep.rested_qty > 0).then_some(sweep.rested_qty);
 
-            let fills = book.add(Order {
+            // Remember what this session left resting so it can be swept on disconnect.
+            if let (Some(session_id), Some(_)) = (session_id, rested_qty) {
+                st.sessions
+                    .entry(session_id)
+                    .or_default()
+                    .insert(seq, symbol.clone());
+                st.session_of.insert(seq, session_id);
+            }
+
+            let now = now_ms();
+            let taker_side = if o.side == Side::Buy as i32 {
+                Side::Buy
+            } else {
+                Side::Sell
+            };
+            let maker_side = if taker_side == Side::Buy {
+                Side::Sell
+            } else {
+                Side::Buy
+            };
+            let event = |event_type: OrderEventType| OrderEvent {
+                event_type: event_type as i32,
+                symbol: symbol.clone(),
                 seq,
-                side,
+                client_order_id: client_order_id.clone(),
+                side: taker_side as i32,
+                ts_ms: now,
+                ..Default::default()
+            };
+
+            st.events.emit(OrderEvent {
                 price: o.price,
                 qty: o.qty,
-                client_order_id: client_order_id.clone(),
+                remaining_qty: o.qty,
+                ..event(OrderEventType::Accepted)
             });
 
+            let mut taker_remaining = o.qty;
+            for (f, maker_id) in fills.iter().zip(maker_ids) {
+                taker_remaining -= f.qty;
+                if f.maker_remaining_qty == 0 {
+                    st.release_session_order(f.maker_seq);
+                }
+
+                st.events.emit(OrderEvent {
+                    seq: f.maker_seq,
+                    client_order_id: maker_id,
+                    side: maker_side as i32,
+                    price: f.price,
+                    qty: f.qty,
+                    remaining_qty: f.maker_remaining_qty,
+                    ..event(fill_event_type(f.maker_remaining_qty))
+                });
+                st.events.emit(OrderEvent {
+                    price: f.price,
+                    qty: f.qty,
+                    remaining_qty: taker_remaining,
+                    ..event(fill_event_type(taker_remaining))
+                });
+            }
+
+            if let Some(rested) = rested_qty {
+                st.events.emit(OrderEvent {
+                    price: o.price,
+                    qty: rested,
+                    remaining_qty: rested,
+                    ..event(OrderEventType::Resting)
+                });
+            }
+            if sweep.capped_qty > 0 {
+                st.events.emit(OrderEvent {
+                    price: o.price,
+                    qty: sweep.capped_qty,
+                    remaining_qty: 0,
+                    ..event(OrderEventType::Cancelled)
+                });
+            }
+
             // Map internal fills to gRPC fills AND append trades to the tape.
-            // Each Fill becomes one Trade. trade_id monotonic in engine state.
-            let mut fills_out: Vec<Fill> = Vec::with_capacity(fills.len());
+            // Each Fill becomes one Trade (or, with coalesce_tape, one per price level).
+            // trade_id monotonic in engine state.
+            let (maker_bps, taker_bps, coalesce) = {
+                let cfg = st.config.symbol(&symbol);
+                (cfg.maker_fee_bps, cfg.taker_fee_bps, cfg.coalesce_tape)
+            };
 
-            for f in fills.into_iter() {
-                fills_out.push(Fill {
+            let fills_out: Vec<Fill> = fills
+                .into_iter()
+                .map(|f| Fill {
                     maker_seq: f.maker_seq,
                     taker_seq: f.taker_seq,
                     price: f.price,
                     qty: f.qty,
-                });
+                    maker_fee: fee(f.price, f.qty, maker_bps, qty_scale),
+                    taker_fee: fee(f.price, f.qty, taker_bps, qty_scale),
+                })
+                .collect();
 
+            let mut trades: Vec<Trade> = Vec::with_capacity(fills_out.len());
+            for level in fills_out.chunk_by(|a, b| coalesce && a.price == b.price) {
+                let f = merge_fills(level);
                 let trade_id = Self::next_trade_id(st);
 
-                // taker_side: the incoming order's side
-                let taker_side = if o.side == Side::Buy as i32 {
-                    Side::Buy
-                } else {
-                    Side::Sell
-                };
-
                 // NEW: stable server-side timestamp in ms since epoch
-                let ts_ms: i64 = std::time::SystemTime::now()
-                    .duration_since(std::time::UNIX_EPOCH)
-                    .unwrap_or_default()
-                    .as_millis() as i64;
+                let ts_ms = now;
 
                 let trade = Trade {
                     trade_id,
@@ -203,246 +646,3522 @@ impl Engine for EngineSvc {
                     taker_seq: f.taker_seq,
                     taker_side: taker_side as i32,
                     ts_ms, // <--- NEW FIELD
+                    maker_fee: f.maker_fee,
+                    taker_fee: f.taker_fee,
+                    coalesced_fills: if coalesce { level.len() as u32 } else { 0 },
+                    // Kept so GetOrderFills can still answer for each merged maker.
+                    makers: if level.len() > 1 {
+                        level
+                            .iter()
+                            .map(|f| MakerShare {
+                                maker_seq: f.maker_seq,
+                                qty: f.qty,
+                                maker_fee: f.maker_fee,
+                                taker_fee: f.taker_fee,
+                            })
+                            .collect()
+                    } else {
+                        Vec::new()
+                    },
                 };
 
-                Self::append_trade(st, &symbol, trade);
+                trades.push(trade);
             }
 
-            Ok((seq, fills_out))
+            // One tape append per order, however many makers it swept.
+            self.append_trades(st, &symbol, trades);
+
+            self.stats.record_order(seq, fills_out.len());
+            self.stats.to_matched.record(received.elapsed());
+
+            Ok((seq, fills_out, qty_scale, sweep))
         })?;
 
-        Ok(Response::new(SubmitOrderResponse {
+        // Response-only: the tape above is per maker, or per level with its makers listed
+        // under coalesce_tape, either way already appended.
+        let fills_out = if o.aggregate_fills {
+            aggregate_fills_by_price(&fills_out)
+        } else {
+            fills_out
+        };
+
+        let (avg_fill_price, total_filled_qty) = fill_vwap(&fills_out);
+
+        Ok(SubmitOrderResponse {
             accepted_seq,
             fills: fills_out,
-        }))
+            qty_scale,
+            avg_fill_price,
+            total_filled_qty,
+            levels_swept: sweep.levels_swept,
+            cancelled_qty: sweep.capped_qty,
+            resting_qty: sweep.rested_qty,
+            orders_ahead: sweep.rest_rank as u32,
+        })
     }
 
-    async fn get_top_of_book(
+    /// Resolve and cancel under one lock acquisition, so the order can't fill or be replaced in between.
+    fn cancel_by_client_order_id_inner(
         &self,
-        req: Request<GetTopOfBookRequest>,
-    ) -> Result<Response<GetTopOfBookResponse>, Status> {
-        let symbol = req.into_inner().symbol.trim().to_string();
+        r: CancelByClientOrderIdRequest,
+    ) -> Result<CancelByClientOrderIdResponse, Status> {
+        let symbol = r.symbol.trim().to_string();
         if symbol.is_empty() {
             return Err(Status::invalid_argument("symbol must be non-empty"));
         }
+        let client_order_id = r.client_order_id.trim().to_string();
+        if client_order_id.is_empty() {
+            return Err(invalid_field("client_order_id", "", "must be non-empty"));
+        }
 
-        let (bid_p, bid_q, ask_p, ask_q) = self.with_state(|st| {
-            st.books
+        self.with_state(|st| {
+            let seqs = st
+                .books
                 .get(&symbol)
-                .map(|b| b.top_of_book())
-                .unwrap_or((0, 0, 0, 0))
-        });
+                .map(|b| b.seqs_for_client_order_id(&client_order_id))
+                .unwrap_or_default();
+            let seq = match seqs.as_slice() {
+                [] => {
+                    return Err(Status::not_found(format!(
+                        "no resting order with client_order_id '{client_order_id}' on {symbol}"
+                    )))
+                }
+                [seq] => *seq,
+                _ => {
+                    return Err(Status::failed_precondition(format!(
+                        "client_order_id '{client_order_id}' is shared by resting orders {seqs:?}; cancel by seq"
+                    )))
+                }
+            };
 
-        Ok(Response::new(GetTopOfBookResponse {
-            best_bid_price: bid_p,
-            best_bid_qty: bid_q,
-            best_ask_price: ask_p,
-            best_ask_qty: ask_q,
-        }))
+            match self.cancel_resting(st, &symbol, seq) {
+                Ok(Some(removed)) => Ok(CancelByClientOrderIdResponse {
+                    seq,
+                    side: match removed.side {
+                        BookSide::Buy => Side::Buy as i32,
+                        BookSide::Sell => Side::Sell as i32,
+                    },
+                    price: removed.price,
+                    cancelled_qty: removed.remaining_qty,
+                }),
+                Ok(None) => Err(Status::internal(format!("seq {seq} vanished under the state lock"))),
+                Err(e) => Err(wal_unavailable(e)),
+            }
+        })
     }
 
-    async fn get_book_depth(
+    /// Cancel one resting order under the state lock: WAL-log the cancel (with its own seq), then remove it.
+    /// Returns Ok(None) if the seq is no longer resting, in which case nothing is logged.
+    fn cancel_resting(
         &self,
-        req: Request<GetBookDepthRequest>,
-    ) -> Result<Response<GetBookDepthResponse>, Status> {
-        let r = req.into_inner();
-        let symbol = r.symbol.trim().to_string();
-        if symbol.is_empty() {
-            return Err(Status::invalid_argument("symbol must be non-empty"));
+        st: &mut EngineState,
+        symbol: &str,
+        target_seq: u64,
+    ) -> std::io::Result<Option<RestingOrder>> {
+        let (side, price, remaining_qty, client_order_id) =
+            match st.books.get(symbol).and_then(|b| b.get(target_seq)) {
+                Some(r) => (r.side, r.price, r.remaining_qty, r.client_order_id.clone()),
+                None => return Ok(None),
+            };
+
+        let (seq, engine_seq) = self.next_engine_seq(st);
+        let side_str = match side {
+            BookSide::Buy => "BUY",
+            BookSide::Sell => "SELL",
+        };
+        let entry = WalEntry {
+            kind: WalKind::Cancel,
+            seq,
+            engine_seq,
+            symbol: symbol.to_string(),
+            side: side_str.to_string(),
+            price,
+            qty: remaining_qty,
+            client_order_id,
+            target_seq: Some(target_seq),
+            ..Default::default()
         }
+        .with_must_understand();
 
-        // default: 10 levels if not provided or invalid
-        let mut levels: usize = if r.levels <= 0 { 10 } else { r.levels as usize };
-        if levels > 100 {
-            levels = 100; // hard cap to keep response bounded
+        if let Err(e) = self.append_wal(&entry) {
+            // Same gap-free rollback as submit
+            self.release_engine_seq(st);
+            return Err(e);
         }
+        self.stats.seq.fetch_max(seq, Ordering::Relaxed);
 
-        let (bids, asks) = self.with_state(|st| {
-            let book = match st.books.get(&symbol) {
-                Some(b) => b,
-                None => return (Vec::new(), Vec::new()),
+        let removed = st.books.get_mut(symbol).and_then(|b| b.cancel(target_seq));
+        st.release_session_order(target_seq);
+        if let Some(r) = &removed {
+            let side = match r.side {
+                BookSide::Buy => Side::Buy,
+                BookSide::Sell => Side::Sell,
             };
-
-            let bids_out: Vec<PriceLevel> = book
-                .bids
-                .iter()
-                .rev()
-                .take(levels)
-                .map(|(price, q)| PriceLevel {
-                    price: *price,
-                    qty: q.iter().map(|o| o.remaining_qty).sum::<i64>(),
-                })
-                .collect();
-
-            let asks_out: Vec<PriceLevel> = book
-                .asks
-                .iter()
-                .take(levels)
-                .map(|(price, q)| PriceLevel {
-                    price: *price,
-                    qty: q.iter().map(|o| o.remaining_qty).sum::<i64>(),
-                })
-                .collect();
-
-            (bids_out, asks_out)
-        });
-
-        Ok(Response::new(GetBookDepthResponse { bids, asks }))
+            st.events.emit(OrderEvent {
+                event_type: OrderEventType::Cancelled as i32,
+                symbol: symbol.to_string(),
+                seq: target_seq,
+                client_order_id: r.client_order_id.clone(),
+                side: side as i32,
+                price: r.price,
+                qty: r.remaining_qty,
+                remaining_qty: 0,
+                ts_ms: now_ms(),
+                ..Default::default()
+            });
+        }
+        Ok(removed)
     }
 
-    async fn get_recent_trades(
-        &self,
-        req: Request<GetRecentTradesRequest>,
-    ) -> Result<Response<GetRecentTradesResponse>, Status> {
-        let r = req.into_inner();
+    fn cancel_range_inner(&self, r: CancelRangeRequest) -> Result<CancelRangeResponse, Status> {
         let symbol = r.symbol.trim().to_string();
         if symbol.is_empty() {
             return Err(Status::invalid_argument("symbol must be non-empty"));
         }
-
-        let after_trade_id = r.after_trade_id;
-        let mut limit: usize = if r.limit <= 0 { 50 } else { r.limit as usize };
-        if limit > MAX_TRADES_LIMIT {
-            limit = MAX_TRADES_LIMIT;
+        let side = if r.side == Side::Buy as i32 {
+            BookSide::Buy
+        } else if r.side == Side::Sell as i32 {
+            BookSide::Sell
+        } else {
+            return Err(Status::invalid_argument("side must be BUY or SELL"));
+        };
+        if r.min_price > r.max_price {
+            return Err(Status::invalid_argument("min_price must be <= max_price"));
         }
 
-        let (trades, last_trade_id) = self.with_state(|st| {
-            let q = match st.trades.get(&symbol) {
-                Some(q) => q,
-                None => return (Vec::new(), after_trade_id),
+        let (cancelled_orders, cancelled_qty) = self.with_state(|st| {
+            let seqs = match st.books.get(&symbol) {
+                Some(book) => book.seqs_in_range(side, r.min_price, r.max_price),
+                None => Vec::new(),
             };
 
-            // trades are stored in ascending trade_id order
-            let mut out: Vec<Trade> = Vec::new();
-            out.reserve(limit);
-
-            for t in q.iter() {
-                if t.trade_id > after_trade_id {
-                    out.push(t.clone());
-                    if out.len() >= limit {
-                        break;
+            // i128 like `level_qty`: a range can hold more than i64::MAX in total.
+            let (mut n, mut qty) = (0u32, 0i128);
+            for seq in seqs {
+                match self.cancel_resting(st, &symbol, seq) {
+                    Ok(Some(removed)) => {
+                        n += 1;
+                        qty += removed.remaining_qty as i128;
+                    }
+                    Ok(None) => {}
+                    Err(e) => {
+                        return Err(Status::unavailable(format!(
+                            "WAL append failed after cancelling {n} orders: {e}"
+                        )))
                     }
                 }
             }
+            Ok((n, qty.min(i64::MAX as i128) as i64))
+        })?;
 
-            let last = out
-                .last()
-                .map(|t| t.trade_id)
-                .unwrap_or(after_trade_id);
-
-            (out, last)
-        });
-
-        Ok(Response::new(GetRecentTradesResponse {
-            trades,
-            last_trade_id,
-        }))
+        Ok(CancelRangeResponse {
+            cancelled_orders,
+            cancelled_qty,
+        })
     }
-}
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Default WAL path under engine crate:
-    // services/engine/engine/data/wal.jsonl
-    let wal_path = env_or_default("ENGINE_WAL_PATH", "data/wal.jsonl");
-    let wal = Wal::new(&wal_path);
+    /// Sweep everything a session left resting, oldest first. Fills and cancels already took
+    /// their orders out of the session, so only orders still resting are left.
+    fn end_session(&self, session_id: u64) -> usize {
+        self.with_state(|st| {
+            let Some(orders) = st.sessions.remove(&session_id) else {
+                return 0;
+            };
 
-    // ---- startup debug (prove we're reading the file we think we are) ----
-    let cwd = std::env::current_dir().ok();
-    println!("[startup] cwd = {:?}", cwd);
+            let mut cancelled = 0;
+            for (seq, symbol) in orders {
+                st.session_of.remove(&seq);
+                match self.cancel_resting(st, &symbol, seq) {
+                    Ok(Some(_)) => cancelled += 1,
+                    Ok(None) => {}
+                    Err(e) => tracing::error!(session_id, seq, error = %e, "session cancel failed"),
+                }
+            }
+            cancelled
+        })
+    }
 
-    let wal_abs = cwd
-        .as_ref()
-        .map(|d| d.join(&wal_path))
-        .unwrap_or_else(|| std::path::PathBuf::from(&wal_path));
-    println!("[startup] wal_path (cfg) = {}", wal_path);
-    println!("[startup] wal_path (abs) = {:?}", wal_abs);
+    /// Append one order's trades (ascending trade_id) to the tape, trimming once at the end.
+    fn append_trades(&self, st: &mut EngineState, symbol: &str, trades: Vec<Trade>) {
+        if trades.is_empty() {
+            return;
+        }
 
-    match std::fs::metadata(wal.wal_path()) {
-        Ok(m) => println!("[startup] wal metadata: exists=true size={} bytes", m.len()),
-        Err(e) => println!("[startup] wal metadata: exists=false err={}", e),
+        // The breaker never reads the tape, so checking before the append is equivalent.
+        for t in trades.iter() {
+            self.check_circuit_breaker(st, symbol, t.price, t.ts_ms);
+            self.publisher.publish(t);
+        }
+        *st.volume.entry(symbol.to_string()).or_default() +=
+            trades.iter().map(|t| t.qty).sum::<i64>();
+
+        let q = st.trades.entry(symbol.to_string()).or_default();
+        q.extend(trades);
+
+        // Bounded memory
+        let excess = q.len().saturating_sub(MAX_TRADES_PER_SYMBOL);
+        if let Some(evicted) = q.drain(..excess).next_back() {
+            st.trades_evicted_through
+                .insert(symbol.to_string(), evicted.trade_id);
+        }
     }
 
-    match std::fs::metadata(wal.snapshot_path()) {
-        Ok(m) => println!(
-            "[startup] snapshot metadata: exists=true size={} bytes",
-            m.len()
-        ),
-        Err(e) => println!("[startup] snapshot metadata: exists=false err={}", e),
+    /// Halt `symbol` if `price` moved more than the configured threshold from the window reference.
+    /// The order that produced the trade completes; the halt applies to subsequent submissions.
+    fn check_circuit_breaker(&self, st: &mut EngineState, symbol: &str, price: i64, now: i64) {
+        let Some(cb) = st.config.symbol(symbol).circuit_breaker else {
+            return;
+        };
+        if st.halts.contains_key(symbol) {
+            return;
+        }
+
+        let fresh = BreakerRef {
+            price,
+            since_ms: now,
+        };
+        let r = *st.breaker_refs.entry(symbol.to_string()).or_insert(fresh);
+
+        // Re-anchor when the window rolls over, or if the reference can't express a percentage.
+        if now - r.since_ms > cb.window_ms || r.price == 0 {
+            st.breaker_refs.insert(symbol.to_string(), fresh);
+            return;
+        }
+
+        let moved_bps = (price as i128 - r.price as i128).abs() * 10_000 / (r.price as i128).abs();
+        if moved_bps <= cb.threshold_bps as i128 {
+            return;
+        }
+
+        let halt = SymbolHalt {
+            since_ms: now,
+            resume_at_ms: now + cb.cooldown_ms,
+            trigger_price: price,
+        };
+        match self.halt_symbol(st, symbol, halt) {
+            Ok(()) => tracing::warn!(
+                symbol,
+                trade_price = price,
+                moved_bps,
+                reference = r.price,
+                resume_at_ms = now + cb.cooldown_ms,
+                "circuit breaker halted symbol"
+            ),
+            // Not halted in memory either, so replay and live state stay in agreement.
+            Err(e) => {
+                tracing::error!(symbol, error = %e, "circuit breaker tripped but WAL append failed")
+            }
+        }
     }
-    // ---------------------------------------------------------------
 
-    // Create state, then replay snapshot + WAL into it BEFORE serving.
-    let mut st = EngineState::default();
+    fn halt_symbol(
+        &self,
+        st: &mut EngineState,
+        symbol: &str,
+        halt: SymbolHalt,
+    ) -> std::io::Result<()> {
+        let (seq, engine_seq) = self.next_engine_seq(st);
+        let entry = WalEntry {
+            kind: WalKind::Halt,
+            seq,
+            engine_seq,
+            symbol: symbol.to_string(),
+            price: halt.trigger_price,
+            ts_ms: Some(halt.since_ms),
+            resume_at_ms: Some(halt.resume_at_ms),
+            ..Default::default()
+        }
+        .with_must_understand();
+        if let Err(e) = self.append_wal(&entry) {
+            self.release_engine_seq(st);
+            return Err(e);
+        }
+        self.stats.seq.fetch_max(seq, Ordering::Relaxed);
 
-    match wal.replay_into_with_stats(&mut st) {
-        Ok(stats) => {
-            if stats.snapshot_present {
-                println!(
-                    "[snapshot] loaded seq={} books={} orders={} from {}",
-                    stats.snapshot_seq,
-                    stats.snapshot_books,
-                    stats.snapshot_orders,
-                    wal.snapshot_path().display()
-                );
+        st.halts.insert(symbol.to_string(), halt);
+        Ok(())
+    }
+
+    fn resume_symbol(&self, st: &mut EngineState, symbol: &str, now: i64) -> std::io::Result<()> {
+        let (seq, engine_seq) = self.next_engine_seq(st);
+        let entry = WalEntry {
+            kind: WalKind::Resume,
+            seq,
+            engine_seq,
+            symbol: symbol.to_string(),
+            ts_ms: Some(now),
+            ..Default::default()
+        }
+        .with_must_understand();
+        if let Err(e) = self.append_wal(&entry) {
+            self.release_engine_seq(st);
+            return Err(e);
+        }
+        self.stats.seq.fetch_max(seq, Ordering::Relaxed);
+
+        st.halts.remove(symbol);
+        // Fresh reference on the first post-resume trade, so the old move can't re-trip it.
+        st.breaker_refs.remove(symbol);
+        tracing::info!(symbol, "halt resumed");
+        Ok(())
+    }
+
+    fn authorize_admin(&self, md: &MetadataMap) -> Result<(), Status> {
+        let Some(expected) = &self.admin_token else {
+            return Err(Status::permission_denied(
+                "maintenance RPCs are disabled (ENGINE_ADMIN_TOKEN not set)",
+            ));
+        };
+        match md.get(ADMIN_TOKEN_HEADER).and_then(|v| v.to_str().ok()) {
+            Some(token) if token == &**expected => Ok(()),
+            Some(_) => Err(Status::permission_denied("invalid admin token")),
+            None => Err(Status::unauthenticated("missing x-admin-token")),
+        }
+    }
+
+    /// Snapshot under the state lock, so it is consistent with the WAL and concurrent
+    /// calls run one at a time. The WAL is only retired (truncated, or archived in keep mode)
+    /// once the snapshot is in place.
+    /// Returns (seq, how the snapshot was written, wal truncated).
+    fn force_snapshot(&self, truncate_wal: bool) -> std::io::Result<(u64, SnapshotWrite, bool)> {
+        self.with_state(|st| self.snapshot_locked(st, truncate_wal))
+    }
+
+    fn snapshot_locked(
+        &self,
+        st: &EngineState,
+        truncate_wal: bool,
+    ) -> std::io::Result<(u64, SnapshotWrite, bool)> {
+        let written = self.wal.write_snapshot(st)?;
+        let truncated = truncate_wal
+            && match self.wal.retire_wal(st.seq, st.engine_seq) {
+                Ok(()) => true,
+                // The snapshot covers every entry, so an untruncated WAL only replays as skips.
+                Err(e) => {
+                    tracing::error!(error = %e, "wal truncate after forced snapshot failed");
+                    false
+                }
+            };
+        Ok((st.seq, written, truncated))
+    }
+
+    /// Stop taking orders and start the quiet-period clock. Returns (phase now, changed).
+    fn start_drain(&self) -> (DrainState, bool) {
+        self.with_state(|st| match self.drain.phase() {
+            DrainState::Serving => {
+                *self.drain.quiet.lock().expect("drain clock mutex poisoned") =
+                    (st.seq, Instant::now());
+                self.drain.set(DrainState::Draining);
+                (DrainState::Draining, true)
+            }
+            phase => (phase, false),
+        })
+    }
+
+    /// Take orders again. Refused once drained: the WAL is retired and the node is out of rotation.
+    fn stop_drain(&self) -> Result<(DrainState, bool), Status> {
+        self.with_state(|_| match self.drain.phase() {
+            DrainState::Draining => {
+                self.drain.set(DrainState::Serving);
+                Ok((DrainState::Serving, true))
+            }
+            DrainState::Drained => Err(Status::failed_precondition(
+                "final drain snapshot already written; restart the engine to take orders again",
+            )),
+            phase => Ok((phase, false)),
+        })
+    }
+
+    /// Driven by a background tick: once draining and no seq has been taken (orders are refused,
+    /// but cancels and halt resumes still take one) for `drain_quiet`, write the final snapshot,
+    /// retire the WAL and report not ready. A failed snapshot leaves it draining to retry.
+    fn drain_tick(&self) {
+        self.with_state(|st| {
+            if self.drain.phase() != DrainState::Draining {
+                return;
+            }
+            let mut quiet = self.drain.quiet.lock().expect("drain clock mutex poisoned");
+            if quiet.0 != st.seq {
+                *quiet = (st.seq, Instant::now());
+                return;
+            }
+            if quiet.1.elapsed() < self.drain_quiet {
+                return;
+            }
+            match self.snapshot_locked(st, true) {
+                Ok((seq, w, truncated)) => {
+                    self.drain.set(DrainState::Drained);
+                    tracing::info!(
+                        seq,
+                        bytes = w.bytes,
+                        wal_truncated = truncated,
+                        "drained: final snapshot written, not ready"
+                    );
+                }
+                Err(e) => {
+                    tracing::error!(error = %e, "drain final snapshot failed, still draining")
+                }
+            }
+        });
+    }
+
+    /// Open or close `symbol` to new orders (WAL-logged with its own seq). Returns whether
+    /// anything changed; a no-op toggle logs nothing.
+    fn apply_order_entry(
+        &self,
+        st: &mut EngineState,
+        symbol: &str,
+        accept: bool,
+    ) -> std::io::Result<bool> {
+        if st.entry_disabled.contains(symbol) != accept {
+            return Ok(false);
+        }
+
+        let (seq, engine_seq) = self.next_engine_seq(st);
+        let entry = WalEntry {
+            kind: if accept {
+                WalKind::EnableEntry
             } else {
-                println!("[snapshot] none present (cold start)");
+                WalKind::DisableEntry
+            },
+            seq,
+            engine_seq,
+            symbol: symbol.to_string(),
+            ..Default::default()
+        }
+        .with_must_understand();
+        if let Err(e) = self.append_wal(&entry) {
+            self.release_engine_seq(st);
+            return Err(e);
+        }
+        self.stats.seq.fetch_max(seq, Ordering::Relaxed);
+
+        if accept {
+            st.entry_disabled.remove(symbol);
+        } else {
+            st.entry_disabled.insert(symbol.to_string());
+        }
+        Ok(true)
+    }
+
+    /// Resume every halt whose cooldown has elapsed (driven by a background tick).
+    fn resume_due_halts(&self) {
+        let now = now_ms();
+        self.with_state(|st| {
+            let mut due: Vec<String> = st
+                .halts
+                .iter()
+                .filter(|(_, h)| now >= h.resume_at_ms)
+                .map(|(symbol, _)| symbol.clone())
+                .collect();
+            due.sort();
+
+            for symbol in due {
+                if let Err(e) = self.resume_symbol(st, &symbol, now) {
+                    tracing::error!(symbol, error = %e, "halt resume failed");
+                }
             }
+        });
+    }
+}
 
-            println!(
-                "[wal] replayed {} entries after snapshot_seq={} from {}",
-                stats.wal_replayed,
-                stats.wal_after_seq,
-                wal.wal_path().display()
-            );
+/// The one order-entry validator: every path that accepts an order calls this before touching state,
+/// so symbol rules (negative prices, tick, lot) can't be applied differently per entry point.
+fn validate_order(cfg: &SymbolConfig, side: i32, price: i64, qty: i64) -> Result<(), Status> {
+    if qty <= 0 {
+        return Err(invalid_field("qty", qty, "must be > 0"));
+    }
+    if side != Side::Buy as i32 && side != Side::Sell as i32 {
+        return Err(invalid_field("side", side, "must be BUY or SELL"));
+    }
+    if price < 0 && !cfg.allow_negative_price {
+        return Err(invalid_field("price", price, "must be >= 0"));
+    }
+    if price == 0 && cfg.reject_zero_price {
+        let constraint = if cfg.allow_negative_price {
+            "must be != 0"
+        } else {
+            "must be > 0"
+        };
+        return Err(invalid_field("price", price, constraint));
+    }
+    if let Some(tick) = cfg.tick_size {
+        if price % tick != 0 {
+            return Err(invalid_field(
+                "price",
+                price,
+                &format!("must be a multiple of tick size {tick}"),
+            ));
         }
-        Err(e) => {
-            // Hard fail: if WAL/snapshot is corrupt, we should not serve incorrect state.
-            eprintln!(
-                "[startup] restore failed (snapshot={}, wal={}): {}",
-                wal.snapshot_path().display(),
-                wal.wal_path().display(),
-                e
-            );
-            return Err(e.into());
+    }
+    if let Some(max) = cfg.max_qty {
+        if qty > max {
+            return Err(invalid_field(
+                "qty",
+                qty,
+                &format!("must be <= max qty {max}"),
+            ));
+        }
+    }
+    if let Some(lot) = cfg.lot_size {
+        if qty % lot != 0 {
+            return Err(invalid_field(
+                "qty",
+                qty,
+                &format!("must be a multiple of lot size {lot}"),
+            ));
         }
     }
+    Ok(())
+}
 
-    let svc = EngineSvc {
-        state: Arc::new(Mutex::new(st)),
-        wal,
-    };
+/// Recompute every invariant from the authoritative level queues and tape. Read-only; runs
+/// under the state lock so the result describes one instant. Symbols are checked in sorted order.
+fn verify_consistency(st: &EngineState) -> VerifyConsistencyResponse {
+    let mut issues = Vec::new();
+    let mut orders_checked = 0u64;
 
-    let addr = "0.0.0.0:50051".parse()?;
-    println!("engine listening on {}", addr);
+    let mut symbols: Vec<&String> = st.books.keys().collect();
+    symbols.sort();
+    for symbol in symbols.iter() {
+        let book = &st.books[*symbol];
+        let issue = |side: BookSide, price, seq, detail| ConsistencyIssue {
+            symbol: symbol.to_string(),
+            side: match side {
+                BookSide::Buy => Side::Buy as i32,
+                BookSide::Sell => Side::Sell as i32,
+            },
+            price,
+            seq,
+            detail,
+            ..Default::default()
+        };
 
-    let state_for_shutdown = svc.state.clone();
-    let wal_for_shutdown = svc.wal.clone();
+        for v in book.verify() {
+            issues.push(issue(v.side, v.price, v.seq, v.detail));
+        }
+        for o in book
+            .bids
+            .values()
+            .chain(book.asks.values())
+            .flat_map(|q| q.iter())
+        {
+            orders_checked += 1;
+            if o.seq > st.seq {
+                issues.push(issue(
+                    o.side,
+                    o.price,
+                    o.seq,
+                    format!("seq ahead of engine seq {}", st.seq),
+                ));
+            }
+        }
+    }
 
-    Server::builder()
-        .add_service(EngineServer::new(svc))
-        .serve_with_shutdown(addr, async move {
-            // waits for Ctrl+C
-            let _ = tokio::signal::ctrl_c().await;
+    let mut tapes: Vec<(&String, &VecDeque<Trade>)> = st.trades.iter().collect();
+    tapes.sort_by(|a, b| a.0.cmp(b.0));
+    for (symbol, q) in tapes {
+        let issue = |trade_id, detail| ConsistencyIssue {
+            symbol: symbol.clone(),
+            trade_id,
+            detail,
+            ..Default::default()
+        };
 
-            // best-effort snapshot on clean shutdown
-            if let Ok(st) = state_for_shutdown.lock() {
-                if let Err(e) = wal_for_shutdown.write_snapshot(&st) {
-                    eprintln!("[snapshot] write failed: {e}");
-                } else {
-                    println!("[snapshot] wrote snapshot OK");
+        let evicted_through = st.trades_evicted_through.get(symbol).copied().unwrap_or(0);
+        let mut prev = evicted_through;
+        for t in q.iter() {
+            if t.trade_id <= prev {
+                issues.push(issue(
+                    t.trade_id,
+                    format!("trade_id not above previous {}", prev),
+                ));
+            }
+            if t.trade_id > st.next_trade_id {
+                issues.push(issue(
+                    t.trade_id,
+                    format!("trade_id ahead of next_trade_id {}", st.next_trade_id),
+                ));
+            }
+            prev = t.trade_id;
+        }
+        if q.len() > MAX_TRADES_PER_SYMBOL {
+            issues.push(issue(
+                0,
+                format!(
+                    "{} trades buffered, cap is {}",
+                    q.len(),
+                    MAX_TRADES_PER_SYMBOL
+                ),
+            ));
+        }
+        // Volume is process-lifetime and the tape only a suffix, so volume can only be larger.
+        let buffered: i64 = q.iter().map(|t| t.qty).sum();
+        let volume = st.volume.get(symbol).copied().unwrap_or(0);
+        if volume < buffered {
+            issues.push(issue(
+                0,
+                format!("volume {} below buffered trade qty {}", volume, buffered),
+            ));
+        }
+    }
 
-                    if let Err(e) = wal_for_shutdown.truncate_wal() {
-                        eprintln!("[wal] truncate failed: {e}");
-                    } else {
-                        println!("[wal] truncated");
+    VerifyConsistencyResponse {
+        seq: st.seq,
+        books_checked: st.books.len() as u32,
+        orders_checked,
+        issues,
+    }
+}
+
+/// (volume-weighted average price, total qty) of `fills`: sum(price * qty) / sum(qty) in i128,
+/// truncated toward zero. (0, 0) when nothing filled.
+fn fill_vwap(fills: &[Fill]) -> (i64, i64) {
+    let qty: i128 = fills.iter().map(|f| f.qty as i128).sum();
+    if qty == 0 {
+        return (0, 0);
+    }
+    let notional: i128 = fills.iter().map(|f| f.price as i128 * f.qty as i128).sum();
+    // A weighted average lies between the min and max fill price, so it fits in i64.
+    ((notional / qty) as i64, qty as i64)
+}
+
+/// Signed fee for one fill: |price| * (qty / 10^qty_scale) * bps / 10_000 in i128, truncated toward zero.
+/// Negative bps gives a negative fee, i.e. a credit (maker rebate).
+fn fee(price: i64, qty: i64, bps: i64, qty_scale: u32) -> i64 {
+    let fee = (price as i128).abs() * qty as i128 * bps as i128 / (10_000 * 10i128.pow(qty_scale));
+    fee.clamp(i64::MIN as i128, i64::MAX as i128) as i64
+}
+
+/// `qty_decimal` ("0.125") as an integer count of 10^-scale units. None if it has more fractional
+/// digits than the scale, isn't plain digits, or overflows i64.
+fn parse_scaled_qty(s: &str, scale: u32) -> Option<i64> {
+    let (int, frac) = s.split_once('.').unwrap_or((s, ""));
+    if (int.is_empty() && frac.is_empty()) || frac.len() > scale as usize {
+        return None;
+    }
+    if !int.bytes().chain(frac.bytes()).all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    format!("{int}{frac:0<width$}", width = scale as usize)
+        .parse()
+        .ok()
+}
+
+/// Exact decimal rendering of a value counted in 10^-scale units ("12.345" for 12345 at scale 3).
+fn scaled_decimal(v: i128, scale: u32) -> String {
+    if scale == 0 {
+        return v.to_string();
+    }
+    let div = 10u128.pow(scale);
+    let sign = if v < 0 { "-" } else { "" };
+    let abs = v.unsigned_abs();
+    format!(
+        "{sign}{}.{:0width$}",
+        abs / div,
+        abs % div,
+        width = scale as usize
+    )
+}
+
+fn fill_event_type(remaining_qty: i64) -> OrderEventType {
+    if remaining_qty == 0 {
+        OrderEventType::Filled
+    } else {
+        OrderEventType::PartiallyFilled
+    }
+}
+
+fn now_ms() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64
+}
+
+fn wal_unavailable(e: std::io::Error) -> Status {
+    Status::unavailable(format!("WAL append failed: {e}"))
+}
+
+/// Collapse consecutive fills at the same price into one fill per level (see `merge_fills`).
+fn aggregate_fills_by_price(fills: &[Fill]) -> Vec<Fill> {
+    fills
+        .chunk_by(|a, b| a.price == b.price)
+        .map(merge_fills)
+        .collect()
+}
+
+/// One fill for a non-empty run at one price: quantities and signed fees summed exactly,
+/// maker_seq 0 when more than one maker is merged. The response's aggregate and the coalesced
+/// tape both merge through here.
+fn merge_fills(level: &[Fill]) -> Fill {
+    let mut merged = level[0].clone();
+    for f in &level[1..] {
+        merged.qty += f.qty;
+        merged.maker_fee += f.maker_fee;
+        merged.taker_fee += f.taker_fee;
+        if merged.maker_seq != f.maker_seq {
+            merged.maker_seq = 0;
+        }
+    }
+    merged
+}
+
+fn env_or_default(key: &str, default: &str) -> String {
+    std::env::var(key)
+        .ok()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(|| default.to_string())
+}
+
+#[tonic::async_trait]
+impl Engine for EngineSvc {
+    async fn health(
+        &self,
+        _req: Request<HealthRequest>,
+    ) -> Result<Response<HealthResponse>, Status> {
+        Ok(Response::new(HealthResponse {
+            status: "ok".to_string(),
+            uptime_ms: self.stats.started_at.elapsed().as_millis() as u64,
+            orders_processed: self.stats.orders_processed.load(Ordering::Relaxed),
+            fills_total: self.stats.fills_total.load(Ordering::Relaxed),
+            seq: self.stats.seq.load(Ordering::Relaxed),
+            ready: self.drain.phase() != DrainState::Drained,
+            drain_state: self.drain.phase() as i32,
+        }))
+    }
+
+    async fn submit_order(
+        &self,
+        req: Request<SubmitOrderRequest>,
+    ) -> Result<Response<SubmitOrderResponse>, Status> {
+        let cid = logging::correlation_id(req.metadata());
+        let o = req.into_inner();
+        let span = tracing::info_span!(
+            "submit_order",
+            cid = %cid,
+            symbol = %o.symbol,
+            side = o.side,
+            price = o.price,
+            qty = o.qty,
+            seq = tracing::field::Empty,
+            fills = tracing::field::Empty,
+        );
+        let _enter = span.enter();
+
+        let ack = self
+            .submit(o, None)
+            .map_err(|s| logging::rejected(s, &cid))?;
+        span.record("seq", ack.accepted_seq);
+        span.record("fills", ack.fills.len());
+        tracing::info!("accepted");
+
+        let mut resp = Response::new(ack);
+        logging::tag(resp.metadata_mut(), &cid);
+        Ok(resp)
+    }
+
+    type SessionStream = ReceiverStream<Result<SessionResponse, Status>>;
+
+    async fn session(
+        &self,
+        req: Request<Streaming<SessionRequest>>,
+    ) -> Result<Response<Self::SessionStream>, Status> {
+        let cid = logging::correlation_id(req.metadata());
+        let mut inbound = req.into_inner();
+        let session_id = self.with_state(|st| {
+            st.next_session_id += 1;
+            st.next_session_id
+        });
+        let session_span = tracing::info_span!("session", cid = %cid, session_id);
+
+        let (tx, rx) = mpsc::channel(SESSION_CHANNEL_CAPACITY);
+        let svc = self.clone();
+
+        tokio::spawn(async move {
+            // Ok(None) = client closed its side cleanly; Err = reset/deadline/transport drop.
+            // Either way the loop ends and the session's resting orders are swept.
+            while let Ok(Some(msg)) = inbound.message().await {
+                let out = match msg.order {
+                    Some(order) => {
+                        let span = tracing::info_span!(
+                            parent: &session_span,
+                            "submit_order",
+                            symbol = %order.symbol,
+                            side = order.side,
+                            price = order.price,
+                            qty = order.qty,
+                        );
+                        let _enter = span.enter();
+                        match svc.submit(order, Some(session_id)) {
+                            Ok(ack) => {
+                                tracing::info!(
+                                    seq = ack.accepted_seq,
+                                    fills = ack.fills.len(),
+                                    "accepted"
+                                );
+                                SessionResponse {
+                                    session_id,
+                                    ack: Some(ack),
+                                    error: String::new(),
+                                }
+                            }
+                            Err(status) => {
+                                let status = logging::rejected(status, &cid);
+                                SessionResponse {
+                                    session_id,
+                                    ack: None,
+                                    error: format!("{} (correlation id {})", status.message(), cid),
+                                }
+                            }
+                        }
                     }
+                    None => SessionResponse {
+                        session_id,
+                        ack: None,
+                        error: "order must be set".to_string(),
+                    },
+                };
+
+                if tx.send(Ok(out)).await.is_err() {
+                    break; // response side gone: treat as disconnect
                 }
-            } else {
-                eprintln!("[snapshot] state mutex poisoned; snapshot skipped");
             }
-        })
-        .await?;
 
-    Ok(())
+            let cancelled = svc.end_session(session_id);
+            tracing::info!(session_id, cancelled, "session ended");
+        });
+
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    type StreamOrderEventsStream = ReceiverStream<Result<OrderEvent, Status>>;
+
+    async fn stream_order_events(
+        &self,
+        req: Request<StreamOrderEventsRequest>,
+    ) -> Result<Response<Self::StreamOrderEventsStream>, Status> {
+        let r = req.into_inner();
+
+        // Backlog + live receiver under one lock acquisition: no gap between them.
+        let (backlog, mut live) = self.with_state(|st| st.events.subscribe(r.after_event_seq));
+
+        let (tx, rx) = mpsc::channel(EVENT_STREAM_CHANNEL_CAPACITY);
+        let svc = self.clone();
+
+        tokio::spawn(async move {
+            let mut cursor = EventCursor::new(r.client_order_id_prefix, r.after_event_seq);
+
+            // Awaiting a slow client only stalls this task, never matching.
+            for ev in cursor.select(backlog) {
+                if tx.send(Ok(ev)).await.is_err() {
+                    return;
+                }
+            }
+
+            loop {
+                let events = match live.recv().await {
+                    Ok(ev) => vec![ev],
+                    // Fell behind the broadcast buffer: re-sync from the retained log.
+                    Err(broadcast::error::RecvError::Lagged(_)) => {
+                        svc.with_state(|st| st.events.since(cursor.last_event_seq()))
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return,
+                };
+
+                for ev in cursor.select(events) {
+                    if tx.send(Ok(ev)).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    async fn cancel_range(
+        &self,
+        req: Request<CancelRangeRequest>,
+    ) -> Result<Response<CancelRangeResponse>, Status> {
+        let cid = logging::correlation_id(req.metadata());
+        let r = req.into_inner();
+        let span = tracing::info_span!(
+            "cancel_range",
+            cid = %cid,
+            symbol = %r.symbol,
+            side = r.side,
+            min_price = r.min_price,
+            max_price = r.max_price,
+        );
+        let _enter = span.enter();
+        self.cancel_range_inner(r)
+            .map(|resp| {
+                tracing::info!(
+                    orders = resp.cancelled_orders,
+                    qty = resp.cancelled_qty,
+                    "cancelled"
+                );
+                let mut resp = Response::new(resp);
+                logging::tag(resp.metadata_mut(), &cid);
+                resp
+            })
+            .map_err(|s| logging::rejected(s, &cid))
+    }
+
+    async fn cancel_by_client_order_id(
+        &self,
+        req: Request<CancelByClientOrderIdRequest>,
+    ) -> Result<Response<CancelByClientOrderIdResponse>, Status> {
+        let cid = logging::correlation_id(req.metadata());
+        let r = req.into_inner();
+        let span = tracing::info_span!(
+            "cancel_by_client_order_id",
+            cid = %cid,
+            symbol = %r.symbol,
+            client_order_id = %r.client_order_id,
+        );
+        let _enter = span.enter();
+        self.cancel_by_client_order_id_inner(r)
+            .map(|resp| {
+                tracing::info!(seq = resp.seq, qty = resp.cancelled_qty, "cancelled");
+                let mut resp = Response::new(resp);
+                logging::tag(resp.metadata_mut(), &cid);
+                resp
+            })
+            .map_err(|s| logging::rejected(s, &cid))
+    }
+
+    async fn force_snapshot(
+        &self,
+        req: Request<ForceSnapshotRequest>,
+    ) -> Result<Response<ForceSnapshotResponse>, Status> {
+        self.authorize_admin(req.metadata())?;
+        let truncate_wal = req.into_inner().truncate_wal;
+
+        let (seq, written, wal_truncated) = self
+            .force_snapshot(truncate_wal)
+            .map_err(|e| Status::internal(format!("snapshot failed: {e}")))?;
+        tracing::info!(
+            seq,
+            bytes = written.bytes,
+            replace = ?written.replace,
+            dir_synced = written.dir_synced,
+            wal_truncated,
+            "forced snapshot"
+        );
+
+        Ok(Response::new(ForceSnapshotResponse {
+            seq,
+            bytes: written.bytes,
+            wal_truncated,
+            replace: match written.replace {
+                SnapshotReplace::AtomicRename => engine::SnapshotReplace::AtomicRename,
+                SnapshotReplace::ViaBackup => engine::SnapshotReplace::ViaBackup,
+            } as i32,
+            dir_synced: written.dir_synced,
+        }))
+    }
+
+    async fn drain(&self, req: Request<DrainRequest>) -> Result<Response<DrainResponse>, Status> {
+        self.authorize_admin(req.metadata())?;
+
+        let (state, changed) = self.start_drain();
+        if changed {
+            tracing::info!(
+                quiet = ?self.drain_quiet,
+                "draining: new orders rejected, final snapshot once no new seq for the quiet period"
+            );
+        }
+        Ok(Response::new(DrainResponse {
+            state: state as i32,
+            changed,
+        }))
+    }
+
+    async fn undrain(
+        &self,
+        req: Request<UndrainRequest>,
+    ) -> Result<Response<UndrainResponse>, Status> {
+        self.authorize_admin(req.metadata())?;
+
+        let (state, changed) = self.stop_drain()?;
+        if changed {
+            tracing::info!("undrained: taking orders again");
+        }
+        Ok(Response::new(UndrainResponse {
+            state: state as i32,
+            changed,
+        }))
+    }
+
+    async fn verify_consistency(
+        &self,
+        req: Request<VerifyConsistencyRequest>,
+    ) -> Result<Response<VerifyConsistencyResponse>, Status> {
+        self.authorize_admin(req.metadata())?;
+
+        let report = self.with_state(|st| verify_consistency(st));
+        for i in report.issues.iter() {
+            tracing::error!(symbol = %i.symbol, seq = i.seq.max(i.trade_id), detail = %i.detail, "consistency check failed");
+        }
+        Ok(Response::new(report))
+    }
+
+    async fn get_latency_stats(
+        &self,
+        req: Request<GetLatencyStatsRequest>,
+    ) -> Result<Response<GetLatencyStatsResponse>, Status> {
+        let reset = req.get_ref().reset;
+        if reset {
+            self.authorize_admin(req.metadata())?;
+        }
+
+        // Read and clear in one pass per histogram, so no record falls between the two.
+        let summary = |h: &LatencyHistogram| {
+            let s = if reset { h.take_summary() } else { h.summary() };
+            engine::LatencySummary {
+                count: s.count,
+                p50_us: s.p50_us,
+                p95_us: s.p95_us,
+                p99_us: s.p99_us,
+                max_us: s.max_us,
+            }
+        };
+        Ok(Response::new(GetLatencyStatsResponse {
+            to_durable: Some(summary(&self.stats.to_durable)),
+            to_matched: Some(summary(&self.stats.to_matched)),
+        }))
+    }
+
+    type DumpStateStream = ReceiverStream<Result<DumpStateChunk, Status>>;
+
+    async fn dump_state(
+        &self,
+        req: Request<DumpStateRequest>,
+    ) -> Result<Response<Self::DumpStateStream>, Status> {
+        self.authorize_admin(req.metadata())?;
+
+        // One short lock acquisition per step; serialization and sending happen outside it.
+        let (tx, rx) = mpsc::channel(DUMP_CHANNEL_CAPACITY);
+        let svc = self.clone();
+        tokio::spawn(async move {
+            let mut dump = StateDump::default();
+            loop {
+                let chunks = svc.with_state(|st| dump.next(st, DUMP_CHUNK_ORDERS));
+                if chunks.is_empty() {
+                    return;
+                }
+                for chunk in chunks {
+                    let msg = chunk
+                        .to_json()
+                        .map(|json| DumpStateChunk {
+                            kind: chunk.kind().to_string(),
+                            json,
+                        })
+                        .map_err(|e| {
+                            Status::internal(format!("state dump serialization failed: {e}"))
+                        });
+                    let failed = msg.is_err();
+                    if tx.send(msg).await.is_err() || failed {
+                        return;
+                    }
+                }
+            }
+        });
+
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    async fn get_resting_notional(
+        &self,
+        req: Request<GetRestingNotionalRequest>,
+    ) -> Result<Response<GetRestingNotionalResponse>, Status> {
+        let symbol = req.into_inner().symbol.trim().to_string();
+        if symbol.is_empty() {
+            return Err(Status::invalid_argument("symbol must be non-empty"));
+        }
+
+        let (bid, ask, qty_scale) = self.with_state(|st| {
+            let (bid, ask) = st
+                .books
+                .get(&symbol)
+                .map(|b| {
+                    (
+                        b.resting_notional(BookSide::Buy),
+                        b.resting_notional(BookSide::Sell),
+                    )
+                })
+                .unwrap_or(((0, false), (0, false)));
+            (bid, ask, st.config.symbol(&symbol).qty_scale)
+        });
+
+        Ok(Response::new(GetRestingNotionalResponse {
+            bid_notional: scaled_decimal(bid.0, qty_scale),
+            ask_notional: scaled_decimal(ask.0, qty_scale),
+            bid_notional_saturated: bid.1,
+            ask_notional_saturated: ask.1,
+        }))
+    }
+
+    async fn get_queue_position(
+        &self,
+        req: Request<GetQueuePositionRequest>,
+    ) -> Result<Response<GetQueuePositionResponse>, Status> {
+        let r = req.into_inner();
+        let symbol = r.symbol.trim().to_string();
+        if symbol.is_empty() {
+            return Err(Status::invalid_argument("symbol must be non-empty"));
+        }
+
+        let pos = self
+            .with_state(|st| st.books.get(&symbol).and_then(|b| b.queue_position(r.seq)))
+            .ok_or_else(|| {
+                Status::not_found(format!("seq {} is not resting on {}", r.seq, symbol))
+            })?;
+
+        let side = match pos.side {
+            BookSide::Buy => Side::Buy,
+            BookSide::Sell => Side::Sell,
+        };
+        Ok(Response::new(GetQueuePositionResponse {
+            side: side as i32,
+            price: pos.price,
+            orders_ahead: pos.orders_ahead as u32,
+            qty_ahead: pos.qty_ahead,
+            remaining_qty: pos.remaining_qty,
+        }))
+    }
+
+    async fn get_halt_status(
+        &self,
+        req: Request<GetHaltStatusRequest>,
+    ) -> Result<Response<GetHaltStatusResponse>, Status> {
+        let symbol = req.into_inner().symbol.trim().to_string();
+        if symbol.is_empty() {
+            return Err(Status::invalid_argument("symbol must be non-empty"));
+        }
+
+        let halt = self.with_state(|st| st.halts.get(&symbol).cloned());
+
+        Ok(Response::new(match halt {
+            Some(h) => GetHaltStatusResponse {
+                halted: true,
+                since_ms: h.since_ms,
+                resume_at_ms: h.resume_at_ms,
+                trigger_price: h.trigger_price,
+            },
+            None => GetHaltStatusResponse::default(),
+        }))
+    }
+
+    async fn set_order_entry(
+        &self,
+        req: Request<SetOrderEntryRequest>,
+    ) -> Result<Response<SetOrderEntryResponse>, Status> {
+        self.authorize_admin(req.metadata())?;
+        let r = req.into_inner();
+        let symbol = r.symbol.trim().to_string();
+        if symbol.is_empty() {
+            return Err(invalid_field("symbol", &r.symbol, "must be non-empty"));
+        }
+        self.check_symbol_layout(&symbol)?;
+
+        let changed = self
+            .with_state(|st| self.apply_order_entry(st, &symbol, r.accept_orders))
+            .map_err(wal_unavailable)?;
+        if changed {
+            tracing::info!(
+                symbol,
+                accept_orders = r.accept_orders,
+                "order entry changed"
+            );
+        }
+
+        Ok(Response::new(SetOrderEntryResponse {
+            accept_orders: r.accept_orders,
+            changed,
+        }))
+    }
+
+    async fn get_top_of_book(
+        &self,
+        req: Request<GetTopOfBookRequest>,
+    ) -> Result<Response<GetTopOfBookResponse>, Status> {
+        let symbol = req.into_inner().symbol.trim().to_string();
+        if symbol.is_empty() {
+            return Err(Status::invalid_argument("symbol must be non-empty"));
+        }
+
+        let ((bid_p, bid_q, ask_p, ask_q), (bid_sat, ask_sat), qty_scale) = self.with_state(|st| {
+            let book = st.books.get(&symbol);
+            (
+                book.map(|b| b.top_of_book()).unwrap_or((0, 0, 0, 0)),
+                book.map(|b| b.top_of_book_saturated()).unwrap_or_default(),
+                st.config.symbol(&symbol).qty_scale,
+            )
+        });
+
+        Ok(Response::new(GetTopOfBookResponse {
+            best_bid_price: bid_p,
+            best_bid_qty: bid_q,
+            best_ask_price: ask_p,
+            best_ask_qty: ask_q,
+            qty_scale,
+            best_bid_qty_saturated: bid_sat,
+            best_ask_qty_saturated: ask_sat,
+        }))
+    }
+
+    async fn get_book_depth(
+        &self,
+        req: Request<GetBookDepthRequest>,
+    ) -> Result<Response<GetBookDepthResponse>, Status> {
+        let r = req.into_inner();
+        let symbol = r.symbol.trim().to_string();
+        if symbol.is_empty() {
+            return Err(Status::invalid_argument("symbol must be non-empty"));
+        }
+
+        // default: 10 levels if not provided or invalid
+        let mut levels: usize = if r.levels <= 0 { 10 } else { r.levels as usize };
+        if levels > 100 {
+            levels = 100; // hard cap to keep response bounded
+        }
+
+        let (bids, asks, qty_scale) = self.with_state(|st| {
+            let qty_scale = st.config.symbol(&symbol).qty_scale;
+            let book = match st.books.get(&symbol) {
+                Some(b) => b,
+                None => return (Vec::new(), Vec::new(), qty_scale),
+            };
+
+            let bids_out: Vec<PriceLevel> = book
+                .bids
+                .iter()
+                .rev()
+                .take(levels)
+                .map(|(price, q)| {
+                    let (qty, qty_saturated) = level_qty(q);
+                    PriceLevel {
+                        price: *price,
+                        qty,
+                        qty_saturated,
+                    }
+                })
+                .collect();
+
+            let asks_out: Vec<PriceLevel> = book
+                .asks
+                .iter()
+                .take(levels)
+                .map(|(price, q)| {
+                    let (qty, qty_saturated) = level_qty(q);
+                    PriceLevel {
+                        price: *price,
+                        qty,
+                        qty_saturated,
+                    }
+                })
+                .collect();
+
+            (bids_out, asks_out, qty_scale)
+        });
+
+        Ok(Response::new(GetBookDepthResponse {
+            bids,
+            asks,
+            qty_scale,
+        }))
+    }
+
+    async fn get_liquidity_curve(
+        &self,
+        req: Request<GetLiquidityCurveRequest>,
+    ) -> Result<Response<GetLiquidityCurveResponse>, Status> {
+        let r = req.into_inner();
+        let symbol = r.symbol.trim().to_string();
+        if symbol.is_empty() {
+            return Err(Status::invalid_argument("symbol must be non-empty"));
+        }
+
+        // Same bounds as GetBookDepth's levels
+        let points: usize = if r.max_points <= 0 {
+            10
+        } else {
+            (r.max_points as usize).min(100)
+        };
+
+        Ok(Response::new(self.with_state(|st| {
+            let curve = |side| {
+                st.books
+                    .get(&symbol)
+                    .map(|b| b.liquidity_curve(side, points))
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(
+                        |(price, cumulative_qty, cumulative_saturated)| LiquidityPoint {
+                            price,
+                            cumulative_qty,
+                            cumulative_saturated,
+                        },
+                    )
+                    .collect()
+            };
+            GetLiquidityCurveResponse {
+                bids: curve(BookSide::Buy),
+                asks: curve(BookSide::Sell),
+                qty_scale: st.config.symbol(&symbol).qty_scale,
+            }
+        })))
+    }
+
+    async fn get_recent_trades(
+        &self,
+        req: Request<GetRecentTradesRequest>,
+    ) -> Result<Response<GetRecentTradesResponse>, Status> {
+        let r = req.into_inner();
+        let symbol = r.symbol.trim().to_string();
+        if symbol.is_empty() {
+            return Err(Status::invalid_argument("symbol must be non-empty"));
+        }
+
+        let after_trade_id = r.after_trade_id;
+        let mut limit: usize = if r.limit <= 0 { 50 } else { r.limit as usize };
+        if limit > MAX_TRADES_LIMIT {
+            limit = MAX_TRADES_LIMIT;
+        }
+
+        let (trades, last_trade_id, qty_scale, gap) = self.with_state(|st| {
+            let qty_scale = st.config.symbol(&symbol).qty_scale;
+            let gap = after_trade_id < st.trades_evicted_through.get(&symbol).copied().unwrap_or(0);
+            let q = match st.trades.get(&symbol) {
+                Some(q) => q,
+                None => return (Vec::new(), after_trade_id, qty_scale, gap),
+            };
+
+            // Ascending trade_id, but engine-wide ids leave holes per symbol, so the start is
+            // found by binary search rather than id arithmetic: O(log n) + O(limit).
+            let start = q.partition_point(|t| t.trade_id <= after_trade_id);
+            let out: Vec<Trade> = q.range(start..).take(limit).cloned().collect();
+
+            let last = out.last().map(|t| t.trade_id).unwrap_or(after_trade_id);
+
+            (out, last, qty_scale, gap)
+        });
+
+        Ok(Response::new(GetRecentTradesResponse {
+            trades,
+            last_trade_id,
+            qty_scale,
+            gap,
+        }))
+    }
+
+    async fn get_ticker(
+        &self,
+        req: Request<GetTickerRequest>,
+    ) -> Result<Response<GetTickerResponse>, Status> {
+        let symbol = req.into_inner().symbol.trim().to_string();
+        if symbol.is_empty() {
+            return Err(Status::invalid_argument("symbol must be non-empty"));
+        }
+
+        // Everything under one lock acquisition, so the row can't straddle a match.
+        let ticker = self.with_state(|st| {
+            let book = st.books.get(&symbol);
+            let (bid_p, bid_q, ask_p, ask_q) =
+                book.map(|b| b.top_of_book()).unwrap_or((0, 0, 0, 0));
+            let (bid_sat, ask_sat) = book.map(|b| b.top_of_book_saturated()).unwrap_or_default();
+            let last = st.trades.get(&symbol).and_then(|q| q.back());
+
+            GetTickerResponse {
+                best_bid_price: bid_p,
+                best_bid_qty: bid_q,
+                best_ask_price: ask_p,
+                best_ask_qty: ask_q,
+                last_trade_id: last.map(|t| t.trade_id).unwrap_or(0),
+                last_price: last.map(|t| t.price).unwrap_or(0),
+                last_qty: last.map(|t| t.qty).unwrap_or(0),
+                volume: st.volume.get(&symbol).copied().unwrap_or(0),
+                halted: st.halts.contains_key(&symbol),
+                qty_scale: st.config.symbol(&symbol).qty_scale,
+                entry_closed: st.entry_disabled.contains(&symbol),
+                bid_level_count: book.map_or(0, |b| b.bids.len() as u32),
+                ask_level_count: book.map_or(0, |b| b.asks.len() as u32),
+                best_bid_qty_saturated: bid_sat,
+                best_ask_qty_saturated: ask_sat,
+            }
+        });
+
+        Ok(Response::new(ticker))
+    }
+
+    async fn get_trade_cursor(
+        &self,
+        req: Request<GetTradeCursorRequest>,
+    ) -> Result<Response<GetTradeCursorResponse>, Status> {
+        let symbol = req.into_inner().symbol.trim().to_string();
+        if symbol.is_empty() {
+            return Err(Status::invalid_argument("symbol must be non-empty"));
+        }
+
+        Ok(Response::new(self.with_state(|st| {
+            let q = st.trades.get(&symbol);
+            GetTradeCursorResponse {
+                oldest_trade_id: q.and_then(|q| q.front()).map(|t| t.trade_id).unwrap_or(0),
+                newest_trade_id: q.and_then(|q| q.back()).map(|t| t.trade_id).unwrap_or(0),
+                evicted_through_trade_id: st
+                    .trades_evicted_through
+                    .get(&symbol)
+                    .copied()
+                    .unwrap_or(0),
+            }
+        })))
+    }
+
+    async fn get_order_fills(
+        &self,
+        req: Request<GetOrderFillsRequest>,
+    ) -> Result<Response<GetOrderFillsResponse>, Status> {
+        let r = req.into_inner();
+        let symbol = r.symbol.trim().to_string();
+        if symbol.is_empty() {
+            return Err(Status::invalid_argument("symbol must be non-empty"));
+        }
+        if r.seq == 0 {
+            return Err(status::invalid_field("seq", "0", "must be > 0"));
+        }
+
+        Ok(Response::new(self.with_state(|st| {
+            let Some(q) = st.trades.get(&symbol) else {
+                return GetOrderFillsResponse::default();
+            };
+
+            let fills = q
+                .iter()
+                .filter_map(|t| {
+                    let (trade, role) = if t.maker_seq == r.seq {
+                        (t.clone(), LiquidityRole::Maker)
+                    } else if t.taker_seq == r.seq {
+                        (t.clone(), LiquidityRole::Taker)
+                    } else {
+                        // One of several makers merged into a coalesced trade: just its part.
+                        let share = t.makers.iter().find(|m| m.maker_seq == r.seq)?;
+                        let part = Trade {
+                            qty: share.qty,
+                            maker_seq: share.maker_seq,
+                            maker_fee: share.maker_fee,
+                            taker_fee: share.taker_fee,
+                            coalesced_fills: 1,
+                            makers: Vec::new(),
+                            ..t.clone()
+                        };
+                        (part, LiquidityRole::Maker)
+                    };
+                    Some(OrderFill {
+                        trade: Some(trade),
+                        role: role as i32,
+                    })
+                })
+                .collect();
+
+            // An order only trades once it is accepted, and taker seqs rise with trade_id, so
+            // evicted trades (all older than the ring's front) can't involve it if the front's
+            // taker came before it.
+            let evicted = st.trades_evicted_through.get(&symbol).copied().unwrap_or(0) > 0;
+            let truncated = evicted && q.front().is_some_and(|t| t.taker_seq >= r.seq);
+
+            GetOrderFillsResponse { fills, truncated }
+        })))
+    }
+}
+
+fn dump_symbol(wal: &Wal, symbol: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(book) = wal.restore_symbol(symbol)? else {
+        println!(
+            "{} not in snapshot {}",
+            symbol,
+            wal.snapshot_path().display()
+        );
+        return Ok(());
+    };
+
+    let levels = book
+        .asks
+        .iter()
+        .rev()
+        .map(|l| ("ASK", l))
+        .chain(book.bids.iter().rev().map(|l| ("BID", l)));
+    for (side, (price, q)) in levels {
+        let (qty, saturated) = level_qty(q);
+        let capped = if saturated { " (capped)" } else { "" };
+        println!("{side} {price} qty={qty}{capped} orders={}", q.len());
+    }
+    Ok(())
+}
+
+/// Snapshot + WAL into a fresh state, logging restore stats. Read-only: the server repairs a
+/// torn WAL tail afterwards (`Wal::repair_tail`), before it serves.
+fn restore_state(wal: &Wal, config: EngineConfig) -> std::io::Result<EngineState> {
+    let mut st = EngineState {
+        config,
+        ..Default::default()
+    };
+
+    match wal.replay_into_with_stats(&mut st) {
+        Ok(stats) => {
+            if stats.snapshot_present {
+                tracing::info!(
+                    seq = stats.snapshot_seq,
+                    books = stats.snapshot_books,
+                    orders = stats.snapshot_orders,
+                    checksum = if stats.snapshot_checksum_verified { "verified" } else { "absent" },
+                    path = %wal.snapshot_path().display(),
+                    "snapshot loaded"
+                );
+            } else {
+                tracing::info!("no snapshot present (cold start)");
+            }
+
+            tracing::info!(
+                entries = stats.wal_replayed,
+                after_seq = stats.wal_after_seq,
+                path = %wal.wal_path().display(),
+                "wal replayed"
+            );
+
+            if wal.layout() == WalLayout::PerSymbol {
+                tracing::info!(
+                    symbols = stats.symbol_wals,
+                    dir = %wal.symbols_dir().display(),
+                    "per-symbol wals restored"
+                );
+            }
+
+            if stats.wal_segments_replayed + stats.wal_segments_skipped > 0 {
+                tracing::info!(
+                    replayed = stats.wal_segments_replayed,
+                    skipped = stats.wal_segments_skipped,
+                    "wal archive segments (skipped ones are covered by the snapshot)"
+                );
+            }
+
+            if stats.wal_checkpoints_verified > 0 {
+                tracing::info!(
+                    checkpoints = stats.wal_checkpoints_verified,
+                    "wal checkpoints verified"
+                );
+            }
+
+            if stats.wal_torn_tail_bytes > 0 {
+                tracing::warn!(
+                    bytes = stats.wal_torn_tail_bytes,
+                    path = %wal.wal_path().display(),
+                    "skipped torn final wal line (never acknowledged)"
+                );
+            }
+        }
+        Err(e) => {
+            // Hard fail: if WAL/snapshot is corrupt, we should not serve incorrect state.
+            tracing::error!(
+                snapshot = %wal.snapshot_path().display(),
+                wal = %wal.wal_path().display(),
+                error = %e,
+                "restore failed"
+            );
+            return Err(e);
+        }
+    }
+
+    // Restored state is already verified; count checkpoint intervals from here.
+    st.last_checkpoint_seq = st.seq;
+    Ok(st)
+}
+
+/// `engine --replay`: the startup restore, then the report, and nothing else. Unlike startup it
+/// never prepares dirs or repairs a torn tail, so the WAL and snapshot files are left as found.
+fn replay_offline(wal: &Wal, config: EngineConfig) -> std::io::Result<String> {
+    let st = restore_state(wal, config)?;
+    Ok(replay_summary(&st))
+}
+
+/// `--replay` report: seq, checksum and per-symbol resting order counts (symbols sorted).
+fn replay_summary(st: &EngineState) -> String {
+    let mut symbols: Vec<&String> = st.books.keys().collect();
+    symbols.sort();
+
+    let mut out = format!(
+        "seq={} checksum={:016x} symbols={} halted={}\n",
+        st.seq,
+        wal::state_checksum(st),
+        symbols.len(),
+        st.halts.len()
+    );
+    for symbol in symbols {
+        let book = &st.books[symbol];
+        let count = |levels: &std::collections::BTreeMap<i64, VecDeque<RestingOrder>>| {
+            levels.values().map(|q| q.len()).sum::<usize>()
+        };
+        out.push_str(&format!(
+            "{} bids={} asks={}\n",
+            symbol,
+            count(&book.bids),
+            count(&book.asks)
+        ));
+    }
+    out
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    logging::init()?;
+
+    // Default WAL path under engine crate:
+    // services/engine/engine/data/wal.jsonl
+    let wal_path = env_or_default("ENGINE_WAL_PATH", "data/wal.jsonl");
+    // Snapshot defaults to snapshot.json next to the WAL.
+    let wal = match std::env::var("ENGINE_SNAPSHOT_PATH") {
+        Ok(p) if !p.trim().is_empty() => Wal::with_snapshot_path(&wal_path, p.trim()),
+        _ => Wal::new(&wal_path),
+    };
+    // strict (default on unix) | best_effort: see `SnapshotAtomicity`.
+    let wal = match std::env::var("ENGINE_SNAPSHOT_ATOMICITY")
+        .as_deref()
+        .map(str::trim)
+    {
+        Ok("strict") => wal.with_snapshot_atomicity(SnapshotAtomicity::Strict),
+        Ok("best_effort") => wal.with_snapshot_atomicity(SnapshotAtomicity::BestEffort),
+        Ok("") | Err(_) => wal,
+        Ok(other) => {
+            return Err(format!(
+                "ENGINE_SNAPSHOT_ATOMICITY must be 'strict' or 'best_effort', got '{}'",
+                other
+            )
+            .into())
+        }
+    };
+    // keep = never truncate: each snapshot archives the WAL as a segment (audit retention).
+    let wal = match env_or_default("ENGINE_WAL_RETENTION", "truncate").as_str() {
+        "truncate" => wal,
+        "keep" => wal.with_retention(WalRetention::Keep),
+        other => {
+            return Err(format!(
+                "ENGINE_WAL_RETENTION must be 'truncate' or 'keep', got '{}'",
+                other
+            )
+            .into())
+        }
+    };
+    // lenient = skip snapshot/WAL fields this build doesn't know (rolling back past a format addition).
+    let wal = match env_or_default("ENGINE_DECODE", "strict").as_str() {
+        "strict" => wal,
+        "lenient" => {
+            tracing::warn!(
+                "ENGINE_DECODE=lenient: unknown snapshot/WAL fields will be skipped, not rejected"
+            );
+            wal.with_decode_mode(DecodeMode::Lenient)
+        }
+        other => {
+            return Err(format!(
+                "ENGINE_DECODE must be 'strict' or 'lenient', got '{}'",
+                other
+            )
+            .into())
+        }
+    };
+    // per_symbol = a WAL and snapshot per symbol under <wal dir>/symbols/, recoverable one at a time.
+    // Symbols then name directories, so orders for any other than [A-Za-z0-9._-] are rejected.
+    let wal = match env_or_default("ENGINE_WAL_LAYOUT", "single").as_str() {
+        "single" => wal,
+        "per_symbol" => wal.with_layout(WalLayout::PerSymbol),
+        other => {
+            return Err(format!(
+                "ENGINE_WAL_LAYOUT must be 'single' or 'per_symbol', got '{}'",
+                other
+            )
+            .into())
+        }
+    };
+    // Snapshot seq ahead of every WAL entry: strict fails startup; production should set it.
+    let wal = match env_or_default("ENGINE_SEQ_MISMATCH", "warn").as_str() {
+        "strict" => wal.with_seq_mismatch(SeqMismatchPolicy::Strict),
+        "trust_snapshot" => wal.with_seq_mismatch(SeqMismatchPolicy::TrustSnapshot),
+        "warn" => wal.with_seq_mismatch(SeqMismatchPolicy::Warn),
+        other => {
+            return Err(format!(
+                "ENGINE_SEQ_MISMATCH must be 'strict', 'trust_snapshot' or 'warn', got '{}'",
+                other
+            )
+            .into())
+        }
+    };
+
+    // Offline: `engine --dump-symbol SYMBOL` prints that book from the snapshot and exits.
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("--dump-symbol") {
+        let symbol = args.get(2).ok_or("usage: engine --dump-symbol SYMBOL")?;
+        return dump_symbol(&wal, symbol);
+    }
+
+    // Optional per-symbol config (JSON). A bad file is a startup error, not a silent default.
+    let config = match std::env::var("ENGINE_SYMBOL_CONFIG") {
+        Ok(path) if !path.trim().is_empty() => {
+            let path = path.trim();
+            let cfg = EngineConfig::load(path).map_err(|e| {
+                tracing::error!(path, error = %e, "symbol config failed to load");
+                e
+            })?;
+            tracing::info!(symbols = cfg.symbol_count(), path, "symbol config loaded");
+            cfg
+        }
+        _ => EngineConfig::default(),
+    };
+
+    // Offline: `engine --replay` restores snapshot + WAL read-only, prints a summary and exits
+    // without serving. A restore error exits non-zero.
+    if args.get(1).map(String::as_str) == Some("--replay") {
+        print!("{}", replay_offline(&wal, config)?);
+        return Ok(());
+    }
+
+    if let Err(e) = wal.prepare_dirs() {
+        tracing::error!(
+            wal = %wal.wal_path().display(),
+            snapshot = %wal.snapshot_path().display(),
+            error = %e,
+            "WAL/snapshot paths unusable"
+        );
+        return Err(e.into());
+    }
+
+    // ---- startup debug (prove we're reading the file we think we are) ----
+    let cwd = std::env::current_dir().ok();
+    tracing::info!(cwd = ?cwd, "startup");
+
+    let wal_abs = cwd
+        .as_ref()
+        .map(|d| d.join(&wal_path))
+        .unwrap_or_else(|| std::path::PathBuf::from(&wal_path));
+    tracing::info!(configured = %wal_path, absolute = ?wal_abs, "wal path");
+
+    match std::fs::metadata(wal.wal_path()) {
+        Ok(m) => tracing::info!(exists = true, size = m.len(), "wal metadata"),
+        Err(e) => tracing::info!(exists = false, error = %e, "wal metadata"),
+    }
+
+    match std::fs::metadata(wal.snapshot_path()) {
+        Ok(m) => tracing::info!(exists = true, size = m.len(), "snapshot metadata"),
+        Err(e) => tracing::info!(exists = false, error = %e, "snapshot metadata"),
+    }
+    // ---------------------------------------------------------------
+
+    // Create state, then replay snapshot + WAL into it BEFORE serving.
+    let st = restore_state(&wal, config)?;
+
+    // Replay only reads; put the WAL back on a line boundary before the first append.
+    let repair = wal.repair_tail()?;
+    if repair != TailRepair::default() {
+        tracing::warn!(
+            cut_bytes = repair.cut_bytes,
+            newlines_added = repair.newlines_added,
+            "wal tail repaired"
+        );
+    }
+
+    // Opt-in (staging): prove the restored state survives a snapshot round trip before serving.
+    if env_or_default("ENGINE_VERIFY_SNAPSHOT", "0") == "1" {
+        if let Err(e) = wal::verify_snapshot_round_trip(&st) {
+            tracing::error!(error = %e, "snapshot round-trip verification failed");
+            return Err(e.into());
+        }
+        tracing::info!("snapshot round-trip verification OK");
+    }
+
+    let stats = Arc::new(EngineStats::new(st.seq));
+
+    let admin_token = std::env::var("ENGINE_ADMIN_TOKEN")
+        .ok()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .map(Arc::from);
+    if admin_token.is_none() {
+        tracing::warn!("ENGINE_ADMIN_TOKEN not set; maintenance RPCs disabled");
+    }
+
+    let seq_mode = match env_or_default("ENGINE_SEQ_MODE", "internal").as_str() {
+        "internal" => SeqMode::Internal,
+        "external" => SeqMode::External,
+        other => {
+            return Err(format!(
+                "ENGINE_SEQ_MODE must be 'internal' or 'external', got '{}'",
+                other
+            )
+            .into())
+        }
+    };
+    tracing::info!(?seq_mode, "startup");
+
+    let policy = env_or_default("ENGINE_WAL_FAILURE_POLICY", "FAIL_REQUEST");
+    let wal_policy = WalFailurePolicy::parse(&policy).ok_or_else(|| {
+        format!(
+            "ENGINE_WAL_FAILURE_POLICY must be FAIL_REQUEST, FAIL_STOP or RETRY_<n>, got '{}'",
+            policy
+        )
+    })?;
+    tracing::info!(?wal_policy, "startup");
+
+    // Self-verifying WAL: a checksum checkpoint every N seqs (0 = off), checked on replay.
+    let checkpoint_every: u64 = env_or_default("ENGINE_WAL_CHECKPOINT_EVERY", "0")
+        .parse()
+        .map_err(|e| format!("ENGINE_WAL_CHECKPOINT_EVERY: {e}"))?;
+    // A checkpoint checksums every symbol at one seq, which per-symbol replay never reconstructs.
+    if checkpoint_every > 0 && wal.layout() == WalLayout::PerSymbol {
+        return Err("ENGINE_WAL_CHECKPOINT_EVERY needs ENGINE_WAL_LAYOUT=single".into());
+    }
+
+    // Drain: how long without a new seq before the final snapshot.
+    let drain_quiet_ms: u64 = env_or_default("ENGINE_DRAIN_QUIET_MS", "5000")
+        .parse()
+        .map_err(|e| format!("ENGINE_DRAIN_QUIET_MS: {e}"))?;
+
+    // Trade fan-out to an external sink; off unless ENGINE_TRADE_SINK names a JSONL file.
+    let publisher: Arc<dyn TradePublisher> = match std::env::var("ENGINE_TRADE_SINK") {
+        Ok(path) if !path.trim().is_empty() => {
+            let sink = JsonLinesSink::open(path.trim())
+                .map_err(|e| format!("ENGINE_TRADE_SINK {}: {e}", path.trim()))?;
+            tracing::info!(path = path.trim(), "publishing trades");
+            Arc::new(ChannelPublisher::spawn(TRADE_SINK_CHANNEL_CAPACITY, sink)?)
+        }
+        _ => Arc::new(NoopPublisher),
+    };
+
+    let svc = EngineSvc {
+        state: Arc::new(Mutex::new(st)),
+        wal,
+        stats,
+        admin_token,
+        seq_mode,
+        wal_policy,
+        fail_stop: Arc::new(FailStop::default()),
+        publisher,
+        checkpoint_every,
+        drain: Arc::new(Drain::default()),
+        drain_quiet: Duration::from_millis(drain_quiet_ms),
+    };
+
+    let addr = "0.0.0.0:50051".parse()?;
+    tracing::info!(%addr, "engine listening");
+
+    // Auto-resume halts whose cooldown has elapsed
+    let svc_for_halts = svc.clone();
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(HALT_RESUME_TICK);
+        loop {
+            tick.tick().await;
+            svc_for_halts.resume_due_halts();
+        }
+    });
+
+    // Drain: final snapshot once quiet (no-op unless Drain was called)
+    let svc_for_drain = svc.clone();
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(DRAIN_TICK);
+        loop {
+            tick.tick().await;
+            svc_for_drain.drain_tick();
+        }
+    });
+
+    // Optional periodic snapshots (0 = off); each one retires the WAL per ENGINE_WAL_RETENTION.
+    let snapshot_secs: u64 = env_or_default("ENGINE_SNAPSHOT_INTERVAL_SECS", "0")
+        .parse()
+        .map_err(|e| format!("ENGINE_SNAPSHOT_INTERVAL_SECS: {e}"))?;
+    if snapshot_secs > 0 {
+        let svc_for_snapshots = svc.clone();
+        tokio::spawn(async move {
+            let mut tick = tokio::time::interval(Duration::from_secs(snapshot_secs));
+            tick.tick().await; // first tick is immediate
+            loop {
+                tick.tick().await;
+                match svc_for_snapshots.force_snapshot(true) {
+                    Ok((seq, w, _)) => tracing::info!(seq, bytes = w.bytes, "periodic snapshot"),
+                    Err(e) => tracing::error!(error = %e, "periodic snapshot failed"),
+                }
+            }
+        });
+    }
+
+    // Optional (staging): run the consistency self-check on a timer and log any divergence.
+    let verify_secs: u64 = env_or_default("ENGINE_VERIFY_INTERVAL_SECS", "0")
+        .parse()
+        .map_err(|e| format!("ENGINE_VERIFY_INTERVAL_SECS: {e}"))?;
+    if verify_secs > 0 {
+        let svc_for_verify = svc.clone();
+        tokio::spawn(async move {
+            let mut tick = tokio::time::interval(Duration::from_secs(verify_secs));
+            loop {
+                tick.tick().await;
+                let report = svc_for_verify.with_state(|st| verify_consistency(st));
+                for i in report.issues.iter() {
+                    tracing::error!(seq = report.seq, symbol = %i.symbol, detail = %i.detail, "consistency check failed");
+                }
+            }
+        });
+    }
+
+    let state_for_shutdown = svc.state.clone();
+    let wal_for_shutdown = svc.wal.clone();
+    let fail_stop = svc.fail_stop.clone();
+
+    Server::builder()
+        .add_service(EngineServer::new(svc))
+        .serve_with_shutdown(addr, async {
+            // waits for Ctrl+C, or a FAIL_STOP WAL failure
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = fail_stop.notify.notified() => {}
+            }
+
+            // Memory only ever holds durable entries, so this snapshot is consistent even after
+            // a WAL failure; if the disk is gone it fails and the WAL is left untouched.
+
+            // best-effort snapshot on clean shutdown
+            if let Ok(st) = state_for_shutdown.lock() {
+                if let Err(e) = wal_for_shutdown.write_snapshot(&st) {
+                    tracing::error!(error = %e, "shutdown snapshot failed");
+                } else {
+                    tracing::info!(atomicity = ?wal_for_shutdown.snapshot_atomicity(), "shutdown snapshot written");
+
+                    if let Err(e) = wal_for_shutdown.retire_wal(st.seq, st.engine_seq) {
+                        tracing::error!(error = %e, "wal retire failed");
+                    } else {
+                        tracing::info!(retention = ?wal_for_shutdown.retention(), "wal retired");
+                    }
+                }
+            } else {
+                tracing::error!("state mutex poisoned; shutdown snapshot skipped");
+            }
+        })
+        .await?;
+
+    if fail_stop.tripped.load(Ordering::Acquire) {
+        return Err("stopped after WAL append failure (FAIL_STOP)".into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn svc(config: EngineConfig) -> EngineSvc {
+        EngineSvc {
+            state: Arc::new(Mutex::new(EngineState {
+                config,
+                ..Default::default()
+            })),
+            wal: wal::tests::temp_wal(),
+            stats: Arc::new(EngineStats::new(0)),
+            admin_token: Some(Arc::from("secret")),
+            seq_mode: SeqMode::Internal,
+            wal_policy: WalFailurePolicy::FailRequest,
+            fail_stop: Arc::new(FailStop::default()),
+            publisher: Arc::new(NoopPublisher),
+            checkpoint_every: 0,
+            drain: Arc::new(Drain::default()),
+            drain_quiet: Duration::ZERO,
+        }
+    }
+
+    /// Serve `s` on a loopback port, for tests that need a real stream, and connect to it.
+    async fn serve(s: EngineSvc) -> engine::engine_client::EngineClient<tonic::transport::Channel> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let incoming =
+            tonic::transport::server::TcpIncoming::from_listener(listener, true, None).unwrap();
+        tokio::spawn(
+            Server::builder()
+                .add_service(EngineServer::new(s))
+                .serve_with_incoming(incoming),
+        );
+        engine::engine_client::EngineClient::connect(format!("http://{addr}"))
+            .await
+            .unwrap()
+    }
+
+    fn order(side: Side, price: i64, qty: i64) -> SubmitOrderRequest {
+        SubmitOrderRequest {
+            symbol: "BTC-USD".to_string(),
+            side: side as i32,
+            price,
+            qty,
+            ..Default::default()
+        }
+    }
+
+    fn cross(s: &EngineSvc, price: i64) {
+        s.submit(order(Side::Sell, price, 1), None).unwrap();
+        assert_eq!(
+            s.submit(order(Side::Buy, price, 1), None)
+                .unwrap()
+                .fills
+                .len(),
+            1
+        );
+    }
+
+    fn f(maker_seq: u64, price: i64, qty: i64) -> Fill {
+        Fill {
+            maker_seq,
+            taker_seq: 9,
+            price,
+            qty,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn aggregate_fills_merges_same_price_and_preserves_qty() {
+        let fills = vec![f(1, 101, 2), f(2, 101, 3), f(3, 102, 4)];
+        let total: i64 = fills.iter().map(|x| x.qty).sum();
+
+        let out = aggregate_fills_by_price(&fills);
+        assert_eq!(out.len(), 2);
+
+        // Two makers merged at 101 -> sentinel maker_seq
+        assert_eq!((out[0].maker_seq, out[0].price, out[0].qty), (0, 101, 5));
+        // Single maker at 102 keeps its seq
+        assert_eq!((out[1].maker_seq, out[1].price, out[1].qty), (3, 102, 4));
+
+        assert_eq!(out.iter().map(|x| x.qty).sum::<i64>(), total);
+        assert!(out.iter().all(|x| x.taker_seq == 9));
+    }
+
+    #[derive(Default)]
+    struct RecordingPublisher(Mutex<Vec<u64>>);
+
+    impl TradePublisher for RecordingPublisher {
+        fn publish(&self, trade: &Trade) {
+            self.0.lock().unwrap().push(trade.trade_id);
+        }
+    }
+
+    #[test]
+    fn every_taped_trade_is_published() {
+        let publisher = Arc::new(RecordingPublisher::default());
+        let s = EngineSvc {
+            publisher: publisher.clone(),
+            ..svc(EngineConfig::default())
+        };
+        s.submit(order(Side::Sell, 100, 2), None).unwrap();
+        s.submit(order(Side::Sell, 101, 2), None).unwrap();
+        s.submit(order(Side::Buy, 101, 4), None).unwrap();
+        s.submit(order(Side::Buy, 99, 1), None).unwrap();
+        assert_eq!(*publisher.0.lock().unwrap(), vec![1, 2]);
+    }
+
+    #[tokio::test]
+    async fn session_forgets_filled_and_cancelled_orders_and_sweeps_the_rest_on_close() {
+        let s = svc(EngineConfig::default());
+        let mut client = serve(s.clone()).await;
+        let (tx, rx) = mpsc::channel(4);
+        let mut acks = client
+            .session(ReceiverStream::new(rx))
+            .await
+            .unwrap()
+            .into_inner();
+
+        let mut session_id = 0;
+        for o in [
+            order(Side::Sell, 100, 2),
+            order(Side::Sell, 101, 1),
+            order(Side::Sell, 102, 1),
+        ] {
+            tx.send(SessionRequest { order: Some(o) }).await.unwrap();
+            let resp = acks.message().await.unwrap().unwrap();
+            assert!(
+                resp.ack.is_some() && resp.error.is_empty(),
+                "{}",
+                resp.error
+            );
+            session_id = resp.session_id;
+        }
+        tx.send(SessionRequest {
+            order: Some(order(Side::Sell, 100, 0)),
+        })
+        .await
+        .unwrap();
+        let rejected = acks.message().await.unwrap().unwrap();
+        assert!(
+            rejected.ack.is_none() && rejected.error.contains("qty"),
+            "{}",
+            rejected.error
+        );
+
+        // Seq 1 fills completely and seq 3 is cancelled: neither is the session's any more
+        let owned = || {
+            s.with_state(|st| {
+                let seqs = st
+                    .sessions
+                    .get(&session_id)
+                    .map(|o| o.keys().copied().collect::<Vec<_>>());
+                (seqs, st.session_of.len())
+            })
+        };
+        assert_eq!(owned(), (Some(vec![1, 2, 3]), 3));
+        s.submit(order(Side::Buy, 100, 2), None).unwrap();
+        s.with_state(|st| s.cancel_resting(st, "BTC-USD", 3))
+            .unwrap();
+        assert_eq!(owned(), (Some(vec![2]), 1));
+
+        // Closing the request stream sweeps what is left before the response stream ends
+        drop(tx);
+        assert!(acks.message().await.unwrap().is_none());
+        assert_eq!(owned(), (None, 0));
+        assert_eq!(
+            s.with_state(|st| st.books["BTC-USD"].top_of_book()),
+            (0, 0, 0, 0)
+        );
+    }
+
+    #[tokio::test]
+    async fn order_entry_toggle_is_independent_of_halts_and_persists() {
+        let s = svc(EngineConfig::default());
+        let set = |accept_orders| {
+            let mut req = Request::new(SetOrderEntryRequest {
+                symbol: "BTC-USD".to_string(),
+                accept_orders,
+            });
+            req.metadata_mut()
+                .insert(ADMIN_TOKEN_HEADER, "secret".parse().unwrap());
+            s.set_order_entry(req)
+        };
+        let unauthenticated = Request::new(SetOrderEntryRequest {
+            symbol: "BTC-USD".to_string(),
+            accept_orders: false,
+        });
+        assert!(s.set_order_entry(unauthenticated).await.is_err());
+
+        s.submit(order(Side::Buy, 100, 5), None).unwrap(); // seq 1
+        assert!(set(false).await.unwrap().into_inner().changed); // seq 2
+        assert!(!set(false).await.unwrap().into_inner().changed);
+
+        let err = s.submit(order(Side::Buy, 100, 1), None).unwrap_err();
+        assert_eq!(err.code(), tonic::Code::FailedPrecondition);
+        // resting orders stay and can still be cancelled; other symbols and halts are untouched
+        let eth = SubmitOrderRequest {
+            symbol: "ETH-USD".to_string(),
+            ..order(Side::Buy, 10, 1)
+        };
+        s.submit(eth, None).unwrap();
+        s.with_state(|st| {
+            assert!(st.halts.is_empty());
+            assert!(s.cancel_resting(st, "BTC-USD", 1).unwrap().is_some());
+        });
+
+        // survives restart from the WAL alone, then from a snapshot
+        let restored = restore_state(&s.wal, EngineConfig::default()).unwrap();
+        assert!(restored.entry_disabled.contains("BTC-USD"));
+        s.force_snapshot(true).unwrap();
+        let restored = restore_state(&s.wal, EngineConfig::default()).unwrap();
+        assert!(restored.entry_disabled.contains("BTC-USD"));
+
+        assert!(set(true).await.unwrap().into_inner().changed);
+        s.submit(order(Side::Buy, 100, 1), None).unwrap();
+        assert!(restore_state(&s.wal, EngineConfig::default())
+            .unwrap()
+            .entry_disabled
+            .is_empty());
+    }
+
+    #[test]
+    fn submit_reports_vwap_of_its_own_fills() {
+        let s = svc(EngineConfig::default());
+        let resting = s.submit(order(Side::Sell, 100, 3), None).unwrap();
+        assert_eq!((resting.avg_fill_price, resting.total_filled_qty), (0, 0));
+
+        s.submit(order(Side::Sell, 103, 2), None).unwrap();
+        // 3 @ 100 + 1 @ 103 = 403 / 4
+        let sweep = s.submit(order(Side::Buy, 103, 4), None).unwrap();
+        assert_eq!((sweep.avg_fill_price, sweep.total_filled_qty), (100, 4));
+
+        let big = [i64::MAX - 1, i64::MAX].map(|price| Fill {
+            price,
+            qty: i64::MAX / 2,
+            ..Default::default()
+        });
+        assert_eq!(fill_vwap(&big), (i64::MAX - 1, i64::MAX - 1));
+    }
+
+    #[test]
+    fn maker_rebates_are_negative_and_sum_across_a_sweep() {
+        let s = svc(EngineConfig::from_json(
+            br#"{ "BTC-USD": { "maker_fee_bps": -10, "taker_fee_bps": 25 } }"#,
+        )
+        .unwrap());
+        s.submit(order(Side::Sell, 1_000, 30), None).unwrap();
+        s.submit(order(Side::Sell, 1_000, 50), None).unwrap();
+
+        let sweep = SubmitOrderRequest {
+            aggregate_fills: true,
+            ..order(Side::Buy, 1_000, 80)
+        };
+        let fills = s.submit(sweep, None).unwrap().fills;
+        assert_eq!(fills.len(), 1);
+        // 30_000 * -10bps + 50_000 * -10bps; taker 80_000 * 25bps
+        assert_eq!((fills[0].maker_fee, fills[0].taker_fee), (-80, 200));
+
+        let tape = s.with_state(|st| {
+            st.trades["BTC-USD"]
+                .iter()
+                .map(|t| t.maker_fee)
+                .collect::<Vec<_>>()
+        });
+        assert_eq!(tape, vec![-30, -50]);
+    }
+
+    #[tokio::test]
+    async fn ticker_combines_book_last_trade_and_volume() {
+        let s = svc(EngineConfig::default());
+        let ticker = |symbol: &str| {
+            let req = Request::new(GetTickerRequest {
+                symbol: symbol.to_string(),
+            });
+            let s = s.clone();
+            async move { s.get_ticker(req).await.unwrap().into_inner() }
+        };
+        assert_eq!(ticker("BTC-USD").await, GetTickerResponse::default());
+
+        s.submit(order(Side::Sell, 101, 5), None).unwrap();
+        s.submit(order(Side::Sell, 102, 5), None).unwrap();
+        s.submit(order(Side::Buy, 102, 7), None).unwrap();
+        s.submit(order(Side::Buy, 99, 4), None).unwrap();
+
+        let t = ticker("BTC-USD").await;
+        assert_eq!(
+            (
+                t.best_bid_price,
+                t.best_bid_qty,
+                t.best_ask_price,
+                t.best_ask_qty
+            ),
+            (99, 4, 102, 3)
+        );
+        assert_eq!((t.last_trade_id, t.last_price, t.last_qty), (2, 102, 2));
+        assert_eq!((t.volume, t.halted), (7, false));
+
+        assert_eq!(ticker("ETH-USD").await, GetTickerResponse::default());
+    }
+
+    #[tokio::test]
+    async fn ticker_level_counts_match_book_depth() {
+        let s = svc(EngineConfig::default());
+        let ticker = || {
+            let req = Request::new(GetTickerRequest {
+                symbol: "BTC-USD".to_string(),
+            });
+            let s = s.clone();
+            async move { s.get_ticker(req).await.unwrap().into_inner() }
+        };
+        let depth = || {
+            let req = Request::new(GetBookDepthRequest {
+                symbol: "BTC-USD".to_string(),
+                ..Default::default()
+            });
+            let s = s.clone();
+            async move { s.get_book_depth(req).await.unwrap().into_inner() }
+        };
+
+        s.submit(order(Side::Sell, 101, 5), None).unwrap();
+        s.submit(order(Side::Sell, 102, 5), None).unwrap();
+        s.submit(order(Side::Buy, 99, 4), None).unwrap();
+        s.submit(order(Side::Buy, 98, 1), None).unwrap();
+        let (t, d) = (ticker().await, depth().await);
+        assert_eq!((t.bid_level_count, t.ask_level_count), (2, 2));
+        assert_eq!((d.bids.len(), d.asks.len()), (2, 2));
+
+        // A fully consumed level stops counting
+        s.submit(order(Side::Buy, 102, 10), None).unwrap();
+        let (t, d) = (ticker().await, depth().await);
+        assert_eq!((t.bid_level_count, t.ask_level_count), (2, 0));
+        assert_eq!((d.bids.len(), d.asks.len()), (2, 0));
+    }
+
+    #[tokio::test]
+    async fn latency_stats_time_accepted_orders_and_reset_per_window() {
+        let s = svc(EngineConfig::default());
+        let stats = |reset| {
+            let mut req = Request::new(GetLatencyStatsRequest { reset });
+            req.metadata_mut()
+                .insert(ADMIN_TOKEN_HEADER, "secret".parse().unwrap());
+            let s = s.clone();
+            async move {
+                let r = s.get_latency_stats(req).await.unwrap().into_inner();
+                (r.to_durable.unwrap().count, r.to_matched.unwrap().count)
+            }
+        };
+
+        s.submit(order(Side::Sell, 101, 5), None).unwrap();
+        s.submit(order(Side::Buy, 101, 2), None).unwrap();
+        assert!(s.submit(order(Side::Buy, 101, 0), None).is_err());
+
+        // Every accepted order was timed on both boundaries; rejected ones not at all
+        assert_eq!(stats(false).await, (2, 2));
+        assert_eq!(stats(true).await, (2, 2));
+        assert_eq!(stats(false).await, (0, 0));
+        s.submit(order(Side::Buy, 101, 1), None).unwrap();
+        assert_eq!(stats(false).await, (1, 1));
+
+        // Resetting needs the admin token; reading doesn't
+        let err = s
+            .get_latency_stats(Request::new(GetLatencyStatsRequest { reset: true }))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::Unauthenticated);
+        assert!(s
+            .get_latency_stats(Request::new(GetLatencyStatsRequest { reset: false }))
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn trade_cursor_tracks_ring_eviction() {
+        let s = svc(EngineConfig::default());
+        let cursor = |symbol: &str| {
+            let req = Request::new(GetTradeCursorRequest {
+                symbol: symbol.to_string(),
+            });
+            let s = s.clone();
+            async move { s.get_trade_cursor(req).await.unwrap().into_inner() }
+        };
+        assert_eq!(cursor("BTC-USD").await, GetTradeCursorResponse::default());
+
+        let trade = |trade_id| Trade {
+            trade_id,
+            ..Default::default()
+        };
+        s.with_state(|st| {
+            s.append_trades(st, "BTC-USD", vec![trade(1)]);
+            let rest = (2..=MAX_TRADES_PER_SYMBOL as u64 + 2).map(trade).collect();
+            s.append_trades(st, "BTC-USD", rest);
+        });
+
+        let c = cursor("BTC-USD").await;
+        assert_eq!(c.oldest_trade_id, 3);
+        assert_eq!(c.newest_trade_id, MAX_TRADES_PER_SYMBOL as u64 + 2);
+        assert_eq!(c.evicted_through_trade_id, 2);
+        assert_eq!(cursor("ETH-USD").await, GetTradeCursorResponse::default());
+    }
+
+    #[tokio::test]
+    async fn coalesced_tape_sums_same_price_fills_and_keeps_cursors() {
+        let config = EngineConfig::from_json(
+            br#"{ "BTC-USD": { "coalesce_tape": true, "taker_fee_bps": 10 } }"#,
+        )
+        .unwrap();
+        let s = svc(config);
+        for (price, qty) in [(101, 1), (101, 2), (101, 3), (102, 4)] {
+            s.submit(order(Side::Sell, price, qty), None).unwrap();
+        }
+        let eth = |side, qty| SubmitOrderRequest {
+            symbol: "ETH-USD".to_string(),
+            ..order(side, 50, qty)
+        };
+        s.submit(eth(Side::Sell, 1), None).unwrap();
+        s.submit(eth(Side::Sell, 1), None).unwrap();
+
+        // Response keeps one fill per maker; the tape gets one trade per price
+        let resp = s.submit(order(Side::Buy, 102, 8), None).unwrap();
+        assert_eq!(resp.fills.len(), 4);
+        let eth_resp = s.submit(eth(Side::Buy, 2), None).unwrap();
+
+        let (btc, eth_tape, volume) = s.with_state(|st| {
+            (
+                st.trades["BTC-USD"].clone(),
+                st.trades["ETH-USD"].clone(),
+                st.volume["BTC-USD"],
+            )
+        });
+        let rows: Vec<_> = btc
+            .iter()
+            .map(|t| (t.trade_id, t.price, t.qty, t.maker_seq, t.coalesced_fills))
+            .collect();
+        assert_eq!(rows, vec![(1, 101, 6, 0, 3), (2, 102, 2, 4, 1)]);
+        assert_eq!(volume, resp.total_filled_qty);
+        assert_eq!(
+            btc.iter().map(|t| t.taker_fee).sum::<i64>(),
+            resp.fills.iter().map(|f| f.taker_fee).sum::<i64>()
+        );
+
+        // Raw symbols are untouched and ids stay engine-wide
+        assert_eq!(eth_resp.fills.len(), 2);
+        let rows: Vec<_> = eth_tape
+            .iter()
+            .map(|t| (t.trade_id, t.maker_seq, t.coalesced_fills))
+            .collect();
+        assert_eq!(rows, vec![(3, 5, 0), (4, 6, 0)]);
+
+        let after = s
+            .get_recent_trades(Request::new(GetRecentTradesRequest {
+                symbol: "BTC-USD".to_string(),
+                after_trade_id: 1,
+                limit: 10,
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(
+            after.trades.iter().map(|t| t.trade_id).collect::<Vec<_>>(),
+            vec![2]
+        );
+        assert!(s.with_state(|st| verify_consistency(st)).issues.is_empty());
+
+        // Each merged maker keeps its own part, for the tape and for GetOrderFills
+        let shares: Vec<_> = btc[0].makers.iter().map(|m| (m.maker_seq, m.qty)).collect();
+        assert_eq!(shares, vec![(1, 1), (2, 2), (3, 3)]);
+        assert!(btc[1].makers.is_empty());
+        let maker = s
+            .get_order_fills(Request::new(GetOrderFillsRequest {
+                symbol: "BTC-USD".to_string(),
+                seq: 2,
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(maker.fills.len(), 1);
+        assert_eq!(maker.fills[0].role, LiquidityRole::Maker as i32);
+        let part = maker.fills[0].trade.as_ref().unwrap();
+        assert_eq!(
+            (part.trade_id, part.price, part.qty, part.maker_seq),
+            (1, 101, 2, 2)
+        );
+        assert_eq!((part.coalesced_fills, part.makers.len()), (1, 0));
+        assert_eq!(part.taker_fee, resp.fills[1].taker_fee);
+    }
+
+    #[tokio::test]
+    async fn recent_trades_seek_past_id_holes_and_flag_gaps() {
+        let s = svc(EngineConfig::default());
+        let recent = |after_trade_id, limit| {
+            let req = Request::new(GetRecentTradesRequest {
+                symbol: "BTC-USD".to_string(),
+                after_trade_id,
+                limit,
+            });
+            let s = s.clone();
+            async move { s.get_recent_trades(req).await.unwrap().into_inner() }
+        };
+        let ids =
+            |r: &GetRecentTradesResponse| r.trades.iter().map(|t| t.trade_id).collect::<Vec<_>>();
+
+        // Even ids only, as if odd ones went to another symbol; the first two get evicted.
+        s.with_state(|st| {
+            let tape = (1..=MAX_TRADES_PER_SYMBOL as u64 + 2)
+                .map(|i| Trade {
+                    trade_id: 2 * i,
+                    ..Default::default()
+                })
+                .collect();
+            s.append_trades(st, "BTC-USD", tape);
+        });
+
+        let r = recent(7, 3).await;
+        assert_eq!(
+            (ids(&r), r.last_trade_id, r.gap),
+            (vec![8, 10, 12], 12, false)
+        );
+        let r = recent(8, 2).await;
+        assert_eq!((ids(&r), r.gap), (vec![10, 12], false));
+
+        // Below the retained window: clamp to the oldest and say so
+        let r = recent(0, 1).await;
+        assert_eq!((ids(&r), r.gap), (vec![6], true));
+        let r = recent(3, 1).await;
+        assert_eq!((ids(&r), r.gap), (vec![6], true));
+
+        // At or past the tail: nothing, echo the cursor
+        let newest = 2 * (MAX_TRADES_PER_SYMBOL as u64 + 2);
+        let r = recent(newest - 1, 5).await;
+        assert_eq!(ids(&r), vec![newest]);
+        let r = recent(newest, 5).await;
+        assert_eq!((r.trades.len(), r.last_trade_id, r.gap), (0, newest, false));
+    }
+
+    #[tokio::test]
+    async fn order_fills_mark_role_and_eviction() {
+        let s = svc(EngineConfig::default());
+        let fills = |seq| {
+            let req = Request::new(GetOrderFillsRequest {
+                symbol: "BTC-USD".to_string(),
+                seq,
+            });
+            let s = s.clone();
+            async move { s.get_order_fills(req).await.unwrap().into_inner() }
+        };
+
+        s.submit(order(Side::Sell, 100, 5), None).unwrap(); // seq 1
+        s.submit(order(Side::Buy, 100, 2), None).unwrap(); // seq 2
+        s.submit(order(Side::Buy, 100, 2), None).unwrap(); // seq 3
+
+        let maker = fills(1).await;
+        assert!(!maker.truncated);
+        assert_eq!(maker.fills.len(), 2);
+        assert!(maker
+            .fills
+            .iter()
+            .all(|f| f.role == LiquidityRole::Maker as i32));
+        let taker = fills(3).await;
+        assert_eq!(taker.fills.len(), 1);
+        assert_eq!(taker.fills[0].role, LiquidityRole::Taker as i32);
+        assert_eq!(taker.fills[0].trade.as_ref().unwrap().maker_seq, 1);
+        assert!(fills(9).await.fills.is_empty());
+
+        // Push seq 1's fills out of the ring; seq 1 is older than every buffered trade's taker.
+        s.with_state(|st| {
+            let filler = (0..MAX_TRADES_PER_SYMBOL as u64)
+                .map(|i| Trade {
+                    trade_id: 100 + i,
+                    taker_seq: 100,
+                    ..Default::default()
+                })
+                .collect();
+            s.append_trades(st, "BTC-USD", filler);
+        });
+        let maker = fills(1).await;
+        assert!(maker.truncated && maker.fills.is_empty());
+        assert!(!fills(101).await.truncated);
+    }
+
+    #[test]
+    fn circuit_breaker_halts_replays_and_resumes() {
+        let cfg = EngineConfig::from_json(
+            br#"{ "BTC-USD": { "circuit_breaker": { "threshold_bps": 1000, "window_ms": 60000, "cooldown_ms": 60000 } } }"#,
+        )
+        .unwrap();
+        let s = svc(cfg);
+
+        cross(&s, 100); // reference
+        cross(&s, 109); // 9%: within band
+        assert!(s.with_state(|st| st.halts.is_empty()));
+
+        cross(&s, 115); // 15%: trips
+        let halt = s.with_state(|st| st.halts.get("BTC-USD").cloned()).unwrap();
+        assert_eq!(halt.trigger_price, 115);
+
+        let err = s.submit(order(Side::Buy, 115, 1), None).unwrap_err();
+        assert_eq!(err.code(), tonic::Code::FailedPrecondition);
+
+        // HALT is in the WAL, so replay reproduces it
+        let mut replayed = EngineState::default();
+        s.wal.replay_into_with_stats(&mut replayed).unwrap();
+        assert_eq!(replayed.halts.get("BTC-USD"), Some(&halt));
+
+        // Cooldown elapsed -> resumed, and the old move doesn't re-trip it
+        s.with_state(|st| st.halts.get_mut("BTC-USD").unwrap().resume_at_ms = 0);
+        s.resume_due_halts();
+        cross(&s, 115);
+        assert!(s.with_state(|st| st.halts.is_empty()));
+
+        let mut replayed = EngineState::default();
+        s.wal.replay_into_with_stats(&mut replayed).unwrap();
+        assert!(replayed.halts.is_empty());
+    }
+
+    #[test]
+    fn order_events_follow_lifecycle() {
+        let s = svc(EngineConfig::default());
+        let tagged = |side, qty, id: &str| SubmitOrderRequest {
+            client_order_id: id.to_string(),
+            ..order(side, 100, qty)
+        };
+        s.submit(tagged(Side::Sell, 2, "maker"), None).unwrap();
+        s.submit(tagged(Side::Buy, 3, "taker"), None).unwrap();
+        s.with_state(|st| s.cancel_resting(st, "BTC-USD", 2).unwrap());
+
+        let events = s.with_state(|st| st.events.since(0));
+        let got: Vec<(u64, i32, u64, i64, i64)> = events
+            .iter()
+            .map(|e| (e.event_seq, e.event_type, e.seq, e.qty, e.remaining_qty))
+            .collect();
+
+        use OrderEventType::*;
+        assert_eq!(
+            got,
+            vec![
+                (1, Accepted as i32, 1, 2, 2),
+                (2, Resting as i32, 1, 2, 2),
+                (3, Accepted as i32, 2, 3, 3),
+                (4, Filled as i32, 1, 2, 0),          // maker
+                (5, PartiallyFilled as i32, 2, 2, 1), // taker
+                (6, Resting as i32, 2, 1, 1),
+                (7, Cancelled as i32, 2, 1, 0),
+            ]
+        );
+        // Maker fill reports the maker's side and client id, taker fill the taker's
+        assert_eq!(
+            (events[3].side, events[3].client_order_id.as_str()),
+            (Side::Sell as i32, "maker")
+        );
+        assert_eq!(
+            (events[4].side, events[4].client_order_id.as_str()),
+            (Side::Buy as i32, "taker")
+        );
+    }
+
+    #[test]
+    fn force_snapshot_requires_token_and_keeps_wal_on_failure() {
+        let s = svc(EngineConfig::default());
+        let mut md = MetadataMap::new();
+        assert_eq!(
+            s.authorize_admin(&md).unwrap_err().code(),
+            tonic::Code::Unauthenticated
+        );
+        md.insert(ADMIN_TOKEN_HEADER, "nope".parse().unwrap());
+        assert_eq!(
+            s.authorize_admin(&md).unwrap_err().code(),
+            tonic::Code::PermissionDenied
+        );
+        md.insert(ADMIN_TOKEN_HEADER, "secret".parse().unwrap());
+        assert!(s.authorize_admin(&md).is_ok());
+
+        s.submit(order(Side::Buy, 100, 1), None).unwrap();
+
+        // Snapshot can't be renamed into place: error, and the WAL is not truncated
+        std::fs::create_dir_all(s.wal.snapshot_path().join("blocker")).unwrap();
+        assert!(s.force_snapshot(true).is_err());
+        assert!(std::fs::metadata(s.wal.wal_path()).unwrap().len() > 0);
+        std::fs::remove_dir_all(s.wal.snapshot_path()).unwrap();
+
+        let (seq, written, truncated) = s.force_snapshot(true).unwrap();
+        assert_eq!((seq, truncated), (1, true));
+        assert_eq!(
+            std::fs::metadata(s.wal.snapshot_path()).unwrap().len(),
+            written.bytes
+        );
+        assert_eq!(std::fs::metadata(s.wal.wal_path()).unwrap().len(), 0);
+
+        let mut replayed = EngineState::default();
+        s.wal.replay_into_with_stats(&mut replayed).unwrap();
+        assert_eq!(replayed.books["BTC-USD"].top_of_book(), (100, 1, 0, 0));
+    }
+
+    #[test]
+    fn replay_summary_counts_orders_per_symbol() {
+        let s = svc(EngineConfig::default());
+        s.submit(order(Side::Buy, 100, 1), None).unwrap();
+        s.submit(order(Side::Sell, 101, 1), None).unwrap();
+        let eth = SubmitOrderRequest {
+            symbol: "ETH-USD".to_string(),
+            ..order(Side::Buy, 10, 1)
+        };
+        s.submit(eth, None).unwrap();
+
+        let restored = restore_state(&s.wal, EngineConfig::default()).unwrap();
+        let summary = replay_summary(&restored);
+        let lines: Vec<&str> = summary.lines().collect();
+        assert!(lines[0].starts_with("seq=3 checksum="));
+        assert_eq!(
+            &lines[1..],
+            ["BTC-USD bids=1 asks=1", "ETH-USD bids=1 asks=0"]
+        );
+        // same report as the live state it was replayed from
+        assert_eq!(summary, s.with_state(|st| replay_summary(st)));
+    }
+
+    #[test]
+    fn per_symbol_layout_rejects_unusable_symbols_before_taking_a_seq() {
+        let mut s = svc(EngineConfig::default());
+        s.wal = s.wal.with_layout(WalLayout::PerSymbol);
+        let slashed = SubmitOrderRequest {
+            symbol: "BTC/USD".to_string(),
+            ..order(Side::Buy, 100, 1)
+        };
+        let err = s.submit(slashed, None).unwrap_err();
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+        assert!(err.message().contains("per_symbol"), "{}", err.message());
+
+        assert_eq!(
+            s.submit(order(Side::Buy, 100, 1), None)
+                .unwrap()
+                .accepted_seq,
+            1
+        );
+        assert_eq!(s.with_state(|st| st.seq), 1);
+    }
+
+    #[test]
+    fn per_symbol_untruncated_snapshot_restores_under_strict_seq_check() {
+        let mut s = svc(EngineConfig::default());
+        s.wal = s
+            .wal
+            .with_layout(WalLayout::PerSymbol)
+            .with_seq_mismatch(SeqMismatchPolicy::Strict);
+        let eth = SubmitOrderRequest {
+            symbol: "ETH-USD".to_string(),
+            ..order(Side::Buy, 10, 1)
+        };
+        s.submit(order(Side::Buy, 100, 1), None).unwrap();
+        s.submit(eth, None).unwrap();
+        s.submit(order(Side::Sell, 101, 1), None).unwrap();
+
+        // ETH's WAL ends at seq 2, its snapshot says 3: not a mismatch
+        let (seq, _, truncated) = s.force_snapshot(false).unwrap();
+        assert_eq!((seq, truncated), (3, false));
+        let restored = restore_state(&s.wal, EngineConfig::default()).unwrap();
+        assert_eq!(
+            replay_summary(&restored),
+            s.with_state(|st| replay_summary(st))
+        );
+
+        // A stale engine-wide WAL still is
+        let marks = std::fs::read_to_string(s.wal.wal_path()).unwrap();
+        std::fs::write(
+            s.wal.wal_path(),
+            marks.lines().next().unwrap().to_string() + "\n",
+        )
+        .unwrap();
+        let err = restore_state(&s.wal, EngineConfig::default()).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("snapshot seq 3 is ahead of the WAL's max seq 1"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn replay_offline_leaves_wal_files_untouched() {
+        use std::io::Write;
+        let s = svc(EngineConfig::default());
+        s.submit(order(Side::Buy, 100, 1), None).unwrap();
+        s.submit(order(Side::Sell, 101, 1), None).unwrap();
+        // archived segment whose last entry lost its newline
+        let wal_bytes = std::fs::read(s.wal.wal_path()).unwrap();
+        std::fs::write(s.wal.wal_path(), &wal_bytes[..wal_bytes.len() - 1]).unwrap();
+        s.wal.rotate_wal(2, 0).unwrap().unwrap();
+        // active WAL: a full entry, then a torn fragment
+        s.submit(order(Side::Buy, 99, 1), None).unwrap();
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(s.wal.wal_path())
+            .unwrap()
+            .write_all(br#"{"seq":4,"symbol":"BTC"#)
+            .unwrap();
+
+        let files = || {
+            let mut files: Vec<(std::path::PathBuf, Vec<u8>)> =
+                std::fs::read_dir(s.wal.wal_path().parent().unwrap())
+                    .unwrap()
+                    .map(|e| e.unwrap().path())
+                    .map(|p| (p.clone(), std::fs::read(p).unwrap()))
+                    .collect();
+            files.sort();
+            files
+        };
+        let before = files();
+        assert_eq!(before.len(), 2);
+
+        let summary = replay_offline(&s.wal, EngineConfig::default()).unwrap();
+        assert!(summary.starts_with("seq=3 "));
+        assert_eq!(files(), before);
+    }
+
+    #[test]
+    fn wal_failure_policies() {
+        assert_eq!(
+            WalFailurePolicy::parse("RETRY_3"),
+            Some(WalFailurePolicy::Retry(3))
+        );
+        assert_eq!(WalFailurePolicy::parse("RETRY_"), None);
+        assert_eq!(WalFailurePolicy::parse("fail_stop"), None);
+
+        // A directory where the WAL file should be makes every append fail.
+        let break_wal = |s: &EngineSvc| {
+            let _ = std::fs::remove_file(s.wal.wal_path());
+            std::fs::create_dir_all(s.wal.wal_path()).unwrap();
+        };
+        let heal_wal = |s: &EngineSvc| std::fs::remove_dir_all(s.wal.wal_path()).unwrap();
+
+        for policy in [WalFailurePolicy::FailRequest, WalFailurePolicy::Retry(2)] {
+            let s = EngineSvc {
+                wal_policy: policy,
+                ..svc(EngineConfig::default())
+            };
+            break_wal(&s);
+            let err = s.submit(order(Side::Buy, 100, 1), None).unwrap_err();
+            assert_eq!(err.code(), tonic::Code::Unavailable);
+            assert_eq!(s.with_state(|st| st.seq), 0);
+            heal_wal(&s);
+            assert_eq!(
+                s.submit(order(Side::Buy, 100, 1), None)
+                    .unwrap()
+                    .accepted_seq,
+                1
+            );
+        }
+
+        // FAIL_STOP: rejects, signals shutdown and keeps refusing even once the disk is back
+        let s = EngineSvc {
+            wal_policy: WalFailurePolicy::FailStop,
+            ..svc(EngineConfig::default())
+        };
+        s.submit(order(Side::Buy, 100, 1), None).unwrap();
+        break_wal(&s);
+        assert!(s.submit(order(Side::Buy, 100, 1), None).is_err());
+        heal_wal(&s);
+        assert!(s.submit(order(Side::Buy, 100, 1), None).is_err());
+        assert!(s.fail_stop.tripped.load(Ordering::Acquire));
+        assert_eq!(s.with_state(|st| st.seq), 1);
+    }
+
+    #[test]
+    fn external_seq_mode_uses_upstream_seq_and_rejects_mixing() {
+        let internal = svc(EngineConfig::default());
+        let tagged = |external_seq: u64, side: Side, price: i64| SubmitOrderRequest {
+            external_seq,
+            ..order(side, price, 1)
+        };
+        let err = internal
+            .submit(tagged(5, Side::Buy, 100), None)
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+
+        let s = EngineSvc {
+            seq_mode: SeqMode::External,
+            ..svc(EngineConfig::default())
+        };
+        assert!(s.submit(order(Side::Buy, 100, 1), None).is_err());
+
+        assert_eq!(
+            s.submit(tagged(10, Side::Buy, 99), None)
+                .unwrap()
+                .accepted_seq,
+            10
+        );
+        assert_eq!(
+            s.submit(tagged(15, Side::Buy, 98), None)
+                .unwrap()
+                .accepted_seq,
+            15
+        );
+        for stale in [15, 12] {
+            let err = s.submit(tagged(stale, Side::Buy, 97), None).unwrap_err();
+            assert_eq!(err.message(), "external_seq must be > last seq 15");
+        }
+
+        // Replay keeps the recorded seqs
+        let mut replayed = EngineState::default();
+        s.wal.replay_into_with_stats(&mut replayed).unwrap();
+        assert_eq!(replayed.seq, 15);
+        assert!(replayed.books["BTC-USD"].get(10).is_some());
+    }
+
+    #[test]
+    fn external_seq_mode_keeps_engine_entries_out_of_the_upstream_seq_space() {
+        let s = EngineSvc {
+            seq_mode: SeqMode::External,
+            ..svc(EngineConfig::default())
+        };
+        let tagged = |external_seq: u64, price: i64| SubmitOrderRequest {
+            external_seq,
+            ..order(Side::Buy, price, 1)
+        };
+        let expired_halt = |s: &EngineSvc| {
+            s.with_state(|st| {
+                let halt = SymbolHalt {
+                    since_ms: 0,
+                    resume_at_ms: 1,
+                    trigger_price: 100,
+                };
+                s.halt_symbol(st, "BTC-USD", halt).unwrap()
+            })
+        };
+
+        s.submit(tagged(10, 99), None).unwrap();
+        expired_halt(&s);
+        assert_eq!(s.with_state(|st| (st.seq, st.engine_seq)), (10, 1));
+
+        // Stale: rejected before the expired halt is resumed, so nothing is logged
+        let err = s.submit(tagged(10, 98), None).unwrap_err();
+        assert_eq!(err.message(), "external_seq must be > last seq 10");
+        assert!(s.with_state(|st| st.halts.contains_key("BTC-USD")));
+        assert_eq!(s.with_state(|st| st.engine_seq), 1);
+
+        // The halt and its resume took engine seqs, so upstream's next seq is still free
+        assert_eq!(s.submit(tagged(11, 98), None).unwrap().accepted_seq, 11);
+        assert_eq!(s.with_state(|st| (st.seq, st.engine_seq)), (11, 2));
+        assert!(s.with_state(|st| st.halts.is_empty()));
+
+        // An engine entry after a snapshot at the same seq still replays
+        s.force_snapshot(true).unwrap();
+        assert!(s
+            .with_state(|st| s.cancel_resting(st, "BTC-USD", 10))
+            .unwrap()
+            .is_some());
+        let mut replayed = EngineState::default();
+        s.wal.replay_into_with_stats(&mut replayed).unwrap();
+        assert_eq!((replayed.seq, replayed.engine_seq), (11, 3));
+        let book = &replayed.books["BTC-USD"];
+        assert!(book.get(10).is_none() && book.get(11).is_some());
+    }
+
+    #[test]
+    fn min_fill_qty_rejects_dust_but_lets_non_crossing_orders_rest() {
+        let s = svc(EngineConfig::default());
+        s.submit(order(Side::Sell, 100, 2), None).unwrap();
+
+        let min = |side: Side, price: i64, qty: i64, min_fill_qty: i64| SubmitOrderRequest {
+            min_fill_qty,
+            ..order(side, price, qty)
+        };
+
+        // Only 2 crossable: rejected, nothing logged or consumed
+        let err = s.submit(min(Side::Buy, 100, 10, 5), None).unwrap_err();
+        assert_eq!(err.code(), tonic::Code::FailedPrecondition);
+        assert_eq!(s.with_state(|st| st.seq), 1);
+
+        // Doesn't cross: rests as a normal GTC
+        assert!(s
+            .submit(min(Side::Buy, 99, 10, 5), None)
+            .unwrap()
+            .fills
+            .is_empty());
+
+        // Enough liquidity: matches and rests the remainder
+        s.submit(order(Side::Sell, 100, 3), None).unwrap();
+        let fills = s.submit(min(Side::Buy, 100, 10, 5), None).unwrap().fills;
+        assert_eq!(fills.iter().map(|f| f.qty).sum::<i64>(), 5);
+
+        let err = s.submit(min(Side::Buy, 100, 1, 2), None).unwrap_err();
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+    }
+
+    #[test]
+    fn max_qty_caps_configured_symbols_only() {
+        let s = svc(EngineConfig::from_json(br#"{ "BTC-USD": { "max_qty": 100 } }"#).unwrap());
+
+        let err = s.submit(order(Side::Buy, 10, 101), None).unwrap_err();
+        assert_eq!(err.message(), "qty must be <= max qty 100");
+        let v = status::tests::field_violation(&err).unwrap();
+        assert_eq!(v.value, "101");
+
+        s.submit(order(Side::Buy, 10, 100), None).unwrap();
+        let unbounded = SubmitOrderRequest {
+            symbol: "ETH-USD".to_string(),
+            ..order(Side::Buy, 10, i64::MAX)
+        };
+        s.submit(unbounded, None).unwrap();
+    }
+
+    #[tokio::test]
+    async fn qty_scale_applies_to_decimal_qty_fees_and_notional() {
+        let s = svc(EngineConfig::from_json(
+            br#"{ "BTC-USD": { "qty_scale": 3, "taker_fee_bps": 100 } }"#,
+        )
+        .unwrap());
+        let decimal = |side, price, qty: &str| SubmitOrderRequest {
+            qty_decimal: qty.to_string(),
+            ..order(side, price, 0)
+        };
+
+        let ack = s.submit(decimal(Side::Sell, 20_000, "1.5"), None).unwrap();
+        assert_eq!(ack.qty_scale, 3);
+        assert_eq!(
+            s.with_state(|st| st.books["BTC-USD"].get(1).unwrap().remaining_qty),
+            1_500
+        );
+
+        for bad in ["0.0001", "1e3", "-1", ".", "99999999999999999"] {
+            let err = s.submit(decimal(Side::Buy, 20_000, bad), None).unwrap_err();
+            assert_eq!(
+                status::tests::field_violation(&err).unwrap().field,
+                "qty_decimal"
+            );
+        }
+        let both = SubmitOrderRequest {
+            qty: 5,
+            ..decimal(Side::Buy, 20_000, "1")
+        };
+        assert!(s.submit(both, None).is_err());
+
+        // 0.25 * 20_000 = 5_000 notional; 1% taker fee = 50
+        let fill = &s
+            .submit(decimal(Side::Buy, 20_000, ".25"), None)
+            .unwrap()
+            .fills[0];
+        assert_eq!((fill.qty, fill.taker_fee), (250, 50));
+
+        let req = Request::new(GetRestingNotionalRequest {
+            symbol: "BTC-USD".to_string(),
+        });
+        let n = s.get_resting_notional(req).await.unwrap().into_inner();
+        assert_eq!(n.ask_notional, "25000.000");
+        assert_eq!(scaled_decimal(-1_005, 3), "-1.005");
+    }
+
+    #[tokio::test]
+    async fn verify_consistency_is_admin_only_and_pinpoints_divergence() {
+        let s = svc(EngineConfig::default());
+        s.submit(order(Side::Sell, 101, 5), None).unwrap();
+        s.submit(order(Side::Buy, 101, 2), None).unwrap();
+        s.submit(order(Side::Buy, 100, 2), None).unwrap();
+
+        let verify = || {
+            let mut req = Request::new(VerifyConsistencyRequest {});
+            req.metadata_mut()
+                .insert("x-admin-token", "secret".parse().unwrap());
+            s.verify_consistency(req)
+        };
+        let err = s
+            .verify_consistency(Request::new(VerifyConsistencyRequest {}))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::Unauthenticated);
+
+        let ok = verify().await.unwrap().into_inner();
+        assert_eq!((ok.books_checked, ok.orders_checked), (1, 2));
+        assert!(ok.issues.is_empty());
+
+        let before = s.with_state(|st| {
+            st.books
+                .get_mut("BTC-USD")
+                .unwrap()
+                .bids
+                .get_mut(&100)
+                .unwrap()[0]
+                .remaining_qty = 0;
+            st.volume.insert("BTC-USD".to_string(), 0);
+            wal::state_checksum(st)
+        });
+        let report = verify().await.unwrap().into_inner();
+        assert_eq!(report.issues.len(), 2);
+        assert_eq!((report.issues[0].price, report.issues[0].seq), (100, 3));
+        assert!(report.issues[1].detail.starts_with("volume 0"));
+        // read-only
+        assert_eq!(s.with_state(|st| wal::state_checksum(st)), before);
+    }
+
+    #[tokio::test]
+    async fn tick_and_lot_apply_to_every_entry_path() {
+        let s = svc(EngineConfig::from_json(
+            br#"{ "BTC-USD": { "tick_size": 5, "lot_size": 10 } }"#,
+        )
+        .unwrap());
+
+        // Shared validator (also what Session goes through via `submit`)
+        for (price, qty) in [(101, 10), (100, 15)] {
+            let err = s.submit(order(Side::Buy, price, qty), None).unwrap_err();
+            assert_eq!(err.code(), tonic::Code::InvalidArgument);
+        }
+        let err = s.submit(order(Side::Buy, 101, 10), Some(1)).unwrap_err();
+        assert!(err.message().contains("tick size 5"));
+
+        // Unary RPC
+        let err = s
+            .submit_order(Request::new(order(Side::Sell, 100, 7)))
+            .await
+            .unwrap_err();
+        assert!(err.message().contains("lot size 10"));
+        s.submit_order(Request::new(order(Side::Sell, 105, 20)))
+            .await
+            .unwrap();
+
+        // Rejections consumed no seq
+        assert_eq!(s.with_state(|st| st.seq), 1);
+    }
+
+    #[test]
+    fn checkpoints_written_every_n_seqs() {
+        let s = EngineSvc {
+            checkpoint_every: 2,
+            ..svc(EngineConfig::default())
+        };
+        for i in 0..5 {
+            s.submit(order(Side::Buy, 100 + i, 1), None).unwrap();
+        }
+        s.submit(order(Side::Sell, 100, 3), None).unwrap();
+
+        let logged = std::fs::read_to_string(s.wal.wal_path()).unwrap();
+        assert_eq!(logged.matches("CHECKPOINT").count(), 3); // after seq 2, 4, 6
+        let restored = restore_state(&s.wal, EngineConfig::default()).unwrap();
+        assert_eq!((restored.seq, restored.last_checkpoint_seq), (6, 6));
+        let stats = s
+            .wal
+            .replay_into_with_stats(&mut EngineState::default())
+            .unwrap();
+        assert_eq!((stats.wal_replayed, stats.wal_checkpoints_verified), (6, 3));
+    }
+
+    #[test]
+    fn max_levels_cancels_remainder_and_replays_identically() {
+        let s = svc(EngineConfig::default());
+        for price in [100, 101, 102] {
+            s.submit(order(Side::Sell, price, 2), None).unwrap();
+        }
+
+        let capped = SubmitOrderRequest {
+            max_levels: 2,
+            ..order(Side::Buy, 102, 5)
+        };
+        let resp = s.submit(capped, None).unwrap();
+        assert_eq!(resp.accepted_seq, 4);
+        assert_eq!(
+            (resp.total_filled_qty, resp.levels_swept, resp.cancelled_qty),
+            (4, 2, 1)
+        );
+        assert_eq!(
+            s.with_state(|st| st.books["BTC-USD"].top_of_book()),
+            (0, 0, 102, 2)
+        );
+
+        let last = s.with_state(|st| st.events.since(0)).pop().unwrap();
+        assert_eq!(last.event_type, OrderEventType::Cancelled as i32);
+        assert_eq!((last.seq, last.qty, last.remaining_qty), (4, 1, 0));
+
+        // min_fill_qty sees the capped sweep, not the whole crossing side
+        let err = s
+            .submit(
+                SubmitOrderRequest {
+                    max_levels: 1,
+                    min_fill_qty: 3,
+                    ..order(Side::Buy, 103, 3)
+                },
+                None,
+            )
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::FailedPrecondition);
+
+        // Uncapped orders report levels too
+        let resp = s.submit(order(Side::Buy, 103, 3), None).unwrap();
+        assert_eq!((resp.levels_swept, resp.cancelled_qty), (1, 0));
+
+        let mut replayed = EngineState::default();
+        s.wal.replay_into_with_stats(&mut replayed).unwrap();
+        assert_eq!(replayed.books["BTC-USD"].top_of_book(), (103, 1, 0, 0));
+    }
+
+    #[tokio::test]
+    async fn submit_reports_rest_position_matching_get_queue_position() {
+        let s = svc(EngineConfig::default());
+        for (i, qty) in [2, 3, 1].into_iter().enumerate() {
+            let resp = s.submit(order(Side::Buy, 100, qty), None).unwrap();
+            assert_eq!((resp.resting_qty, resp.orders_ahead), (qty, i as u32));
+
+            let pos = s
+                .get_queue_position(Request::new(GetQueuePositionRequest {
+                    symbol: "BTC-USD".into(),
+                    seq: resp.accepted_seq,
+                }))
+                .await
+                .unwrap()
+                .into_inner();
+            assert_eq!(
+                (pos.orders_ahead, pos.remaining_qty),
+                (resp.orders_ahead, resp.resting_qty)
+            );
+        }
+
+        // Partial fill: the remainder rests first in line at its own price
+        let resp = s.submit(order(Side::Sell, 100, 6), None).unwrap();
+        assert_eq!(
+            (resp.total_filled_qty, resp.resting_qty, resp.orders_ahead),
+            (6, 0, 0)
+        );
+        let resp = s.submit(order(Side::Sell, 100, 4), None).unwrap();
+        assert_eq!(
+            (resp.total_filled_qty, resp.resting_qty, resp.orders_ahead),
+            (0, 4, 0)
+        );
+        let resp = s.submit(order(Side::Buy, 100, 1), None).unwrap();
+        assert_eq!((resp.total_filled_qty, resp.resting_qty), (1, 0));
+    }
+
+    #[tokio::test]
+    async fn liquidity_curve_is_bounded_and_ordered_from_the_touch() {
+        let s = svc(EngineConfig::default());
+        for price in [95, 96, 97, 98, 99] {
+            s.submit(order(Side::Buy, price, 2), None).unwrap();
+            s.submit(order(Side::Sell, price + 10, 1), None).unwrap();
+        }
+        let curve = |symbol: &str, max_points| {
+            let s = s.clone();
+            let symbol = symbol.to_string();
+            async move {
+                s.get_liquidity_curve(Request::new(GetLiquidityCurveRequest {
+                    symbol,
+                    max_points,
+                }))
+                .await
+                .unwrap()
+                .into_inner()
+            }
+        };
+
+        let c = curve("BTC-USD", 3).await;
+        let points = |side: &[LiquidityPoint]| {
+            side.iter()
+                .map(|p| (p.price, p.cumulative_qty))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(points(&c.bids), vec![(99, 2), (98, 4), (97, 6)]);
+        assert_eq!(points(&c.asks), vec![(105, 1), (106, 2), (107, 3)]);
+        assert_eq!(curve("BTC-USD", 0).await.bids.len(), 5);
+        assert!(curve("ETH-USD", 3).await.asks.is_empty());
+    }
+
+    #[tokio::test]
+    async fn drain_refuses_orders_then_snapshots_once_quiet() {
+        let s = EngineSvc {
+            drain_quiet: Duration::from_secs(3600),
+            ..svc(EngineConfig::default())
+        };
+        s.submit(order(Side::Buy, 100, 1), None).unwrap();
+        s.submit(order(Side::Buy, 99, 2), None).unwrap();
+
+        assert_eq!(s.start_drain(), (DrainState::Draining, true));
+        assert_eq!(s.start_drain(), (DrainState::Draining, false));
+        let err = s.submit(order(Side::Sell, 100, 1), None).unwrap_err();
+        assert_eq!(err.code(), tonic::Code::Unavailable);
+
+        // Reads and cancels still served; not quiet long enough for the final snapshot
+        let cancel = CancelRangeRequest {
+            symbol: "BTC-USD".to_string(),
+            side: Side::Buy as i32,
+            min_price: 99,
+            max_price: 99,
+        };
+        assert_eq!(
+            s.cancel_range_inner(cancel.clone())
+                .unwrap()
+                .cancelled_orders,
+            1
+        );
+        s.drain_tick();
+        assert_eq!(s.drain.phase(), DrainState::Draining);
+        assert!(
+            s.health(Request::new(HealthRequest {}))
+                .await
+                .unwrap()
+                .into_inner()
+                .ready
+        );
+
+        // Reversible before the snapshot
+        assert_eq!(s.stop_drain().unwrap(), (DrainState::Serving, true));
+        s.submit(order(Side::Buy, 98, 3), None).unwrap();
+
+        let s = EngineSvc {
+            drain_quiet: Duration::ZERO,
+            ..s.clone()
+        };
+        s.start_drain();
+        s.drain_tick();
+        let h = s
+            .health(Request::new(HealthRequest {}))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(
+            (h.ready, h.drain_state),
+            (false, DrainState::Drained as i32)
+        );
+        assert_eq!(std::fs::metadata(s.wal.wal_path()).unwrap().len(), 0);
+
+        assert_eq!(
+            s.stop_drain().unwrap_err().code(),
+            tonic::Code::FailedPrecondition
+        );
+        assert_eq!(
+            s.submit(order(Side::Buy, 100, 1), None).unwrap_err().code(),
+            tonic::Code::Unavailable
+        );
+        let cancel = CancelRangeRequest {
+            min_price: 98,
+            max_price: 98,
+            ..cancel
+        };
+        assert_eq!(s.cancel_range_inner(cancel).unwrap().cancelled_orders, 1);
+
+        let restored = restore_state(&s.wal, EngineConfig::default()).unwrap();
+        assert_eq!(restored.books["BTC-USD"].top_of_book(), (100, 1, 0, 0));
+    }
+
+    #[tokio::test]
+    async fn cancel_by_client_order_id_requires_exactly_one_match() {
+        let s = svc(EngineConfig::default());
+        let tagged = |side, price, qty, id: &str| SubmitOrderRequest {
+            client_order_id: id.to_string(),
+            ..order(side, price, qty)
+        };
+        let cancel = |id: &str| {
+            let req = Request::new(CancelByClientOrderIdRequest {
+                symbol: "BTC-USD".to_string(),
+                client_order_id: id.to_string(),
+            });
+            let s = s.clone();
+            async move { s.cancel_by_client_order_id(req).await }
+        };
+
+        s.submit(tagged(Side::Buy, 99, 5, "a"), None).unwrap(); // seq 1
+        s.submit(tagged(Side::Sell, 101, 3, "dup"), None).unwrap(); // seq 2
+        s.submit(tagged(Side::Sell, 102, 3, "dup"), None).unwrap(); // seq 3
+        s.submit(tagged(Side::Buy, 100, 1, "gone"), None).unwrap(); // seq 4
+        s.submit(order(Side::Sell, 100, 1), None).unwrap(); // seq 5 fills "gone"
+
+        let resp = cancel("a").await.unwrap().into_inner();
+        assert_eq!(
+            (resp.seq, resp.side, resp.price, resp.cancelled_qty),
+            (1, Side::Buy as i32, 99, 5)
+        );
+        // The cancel is logged under its own seq
+        assert_eq!(s.with_state(|st| st.seq), 6);
+
+        for id in ["a", "gone", "never"] {
+            assert_eq!(cancel(id).await.unwrap_err().code(), tonic::Code::NotFound);
+        }
+        let err = cancel("dup").await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::FailedPrecondition);
+        assert!(err.message().contains("[2, 3]"), "{}", err.message());
+        assert_eq!(
+            cancel(" ").await.unwrap_err().code(),
+            tonic::Code::InvalidArgument
+        );
+
+        // Cancel one duplicate by seq range; the id becomes unambiguous
+        s.cancel_range(Request::new(CancelRangeRequest {
+            symbol: "BTC-USD".to_string(),
+            side: Side::Sell as i32,
+            min_price: 101,
+            max_price: 101,
+        }))
+        .await
+        .unwrap();
+        assert_eq!(cancel("dup").await.unwrap().into_inner().seq, 3);
+        assert_eq!(s.with_state(|st| st.seq), 8);
+    }
+
+    #[tokio::test]
+    async fn correlation_id_echoed_on_responses_and_errors() {
+        let s = svc(EngineConfig::default());
+        let cid_of = |md: &MetadataMap| {
+            md.get(logging::CORRELATION_ID_HEADER)
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .to_string()
+        };
+
+        let mut req = Request::new(order(Side::Buy, 100, 1));
+        req.metadata_mut()
+            .insert(logging::CORRELATION_ID_HEADER, "client-42".parse().unwrap());
+        let resp = s.submit_order(req).await.unwrap();
+        assert_eq!(cid_of(resp.metadata()), "client-42");
+
+        let mut req = Request::new(order(Side::Buy, 100, 0));
+        req.metadata_mut()
+            .insert(logging::CORRELATION_ID_HEADER, "client-43".parse().unwrap());
+        let err = s.submit_order(req).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+        assert_eq!(cid_of(err.metadata()), "client-43");
+
+        // No (or blank) id from the client: one is generated per request
+        let a = cid_of(
+            s.submit_order(Request::new(order(Side::Buy, 100, 1)))
+                .await
+                .unwrap()
+                .metadata(),
+        );
+        let mut req = Request::new(order(Side::Buy, 100, 1));
+        req.metadata_mut()
+            .insert(logging::CORRELATION_ID_HEADER, "  ".parse().unwrap());
+        let b = cid_of(s.submit_order(req).await.unwrap().metadata());
+        assert!(
+            a.starts_with("eng-") && b.starts_with("eng-") && a != b,
+            "{a} {b}"
+        );
+    }
+
+    #[test]
+    fn negative_price_only_for_configured_symbols() {
+        let s = svc(EngineConfig::from_json(
+            br#"{ "CL-SPREAD": { "allow_negative_price": true } }"#,
+        )
+        .unwrap());
+
+        let err = s.submit(order(Side::Buy, -1, 1), None).unwrap_err();
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+        assert_eq!(err.message(), "price must be >= 0");
+        let v = status::tests::field_violation(&err).unwrap();
+        assert_eq!((v.field.as_str(), v.value.as_str()), ("price", "-1"));
+
+        let spread = |side: Side, price: i64| SubmitOrderRequest {
+            symbol: "CL-SPREAD".to_string(),
+            ..order(side, price, 1)
+        };
+        s.submit(spread(Side::Buy, -3), None).unwrap();
+        let fills = s.submit(spread(Side::Sell, -4), None).unwrap().fills;
+        assert_eq!((fills.len(), fills[0].price), (1, -3));
+
+        // Replay rebuilds the book with the same setting
+        s.submit(spread(Side::Sell, -2), None).unwrap();
+        let mut replayed = EngineState {
+            config: s.with_state(|st| st.config.clone()),
+            ..Default::default()
+        };
+        s.wal.replay_into_with_stats(&mut replayed).unwrap();
+        assert_eq!(replayed.books["CL-SPREAD"].top_of_book(), (0, 0, -2, 1));
+
+        // ... and under a config that no longer allows them, since the WAL entry and then the
+        // snapshot record the decision; a later cancel of such an order still replays
+        s.submit(spread(Side::Sell, -1), None).unwrap(); // seq 4
+        let mut replayed = EngineState::default();
+        s.wal.replay_into_with_stats(&mut replayed).unwrap();
+        assert_eq!(replayed.books["CL-SPREAD"].top_of_book(), (0, 0, -2, 1));
+        s.force_snapshot(true).unwrap();
+        s.with_state(|st| s.cancel_resting(st, "CL-SPREAD", 3))
+            .unwrap();
+        let mut replayed = EngineState::default();
+        s.wal.replay_into_with_stats(&mut replayed).unwrap();
+        assert_eq!(replayed.books["CL-SPREAD"].top_of_book(), (0, 0, -1, 1));
+        assert!(verify_consistency(&replayed).issues.is_empty());
+    }
+
+    #[test]
+    fn zero_price_rejected_only_where_configured() {
+        let s = svc(EngineConfig::default());
+        s.submit(order(Side::Buy, 0, 2), None).unwrap();
+        assert!(s
+            .submit(order(Side::Sell, 1, 1), None)
+            .unwrap()
+            .fills
+            .is_empty());
+        let fills = s.submit(order(Side::Sell, 0, 1), None).unwrap().fills;
+        assert_eq!((fills.len(), fills[0].price), (1, 0));
+
+        // Turning the rule on later: entry rejects 0 (and only 0) ...
+        let config =
+            EngineConfig::from_json(br#"{ "BTC-USD": { "reject_zero_price": true } }"#).unwrap();
+        let strict = svc(config.clone());
+        let err = strict.submit(order(Side::Buy, 0, 1), None).unwrap_err();
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+        assert_eq!(err.message(), "price must be > 0");
+        strict.submit(order(Side::Buy, 1, 1), None).unwrap();
+
+        // ... but replay still rebuilds the 0-priced bid accepted before it
+        let mut replayed = EngineState {
+            config,
+            ..Default::default()
+        };
+        s.wal.replay_into_with_stats(&mut replayed).unwrap();
+        assert_eq!(replayed.books["BTC-USD"].top_of_book(), (0, 1, 1, 1));
+
+        let spread = EngineConfig::from_json(
+            br#"{ "X": { "reject_zero_price": true, "allow_negative_price": true } }"#,
+        )
+        .unwrap();
+        let err = validate_order(spread.symbol("X"), Side::Sell as i32, 0, 1).unwrap_err();
+        assert_eq!(err.message(), "price must be != 0");
+        validate_order(spread.symbol("X"), Side::Sell as i32, -1, 1).unwrap();
+    }
 }
@@ -1,5 +1,5 @@
 use serde::{Deserialize, Serialize};
-use std::collections::{BTreeMap, VecDeque};
+use std::collections::{BTreeMap, HashMap, VecDeque};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Side {
@@ -23,13 +23,21 @@ pub struct Order {
 }
 
 /// Resting order stored in the order book.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct RestingOrder {
     pub seq: u64,
     pub side: Side,
     pub price: i64,
     pub remaining_qty: i64,
     pub client_order_id: String,
+    // Accepted qty; never mutated by matching.
+    pub orig_qty: i64,
+}
+
+impl RestingOrder {
+    pub fn filled_qty(&self) -> i64 {
+        self.orig_qty - self.remaining_qty
+    }
 }
 
 impl From<Order> for RestingOrder {
@@ -40,6 +48,7 @@ impl From<Order> for RestingOrder {
             price: o.price,
             remaining_qty: o.qty,
             client_order_id: o.client_order_id,
+            orig_qty: o.qty,
         }
     }
 }
@@ -52,15 +61,84 @@ pub struct Fill {
     pub taker_seq: u64,
     pub price: i64,
     pub qty: i64,
+    // Maker state after this fill (for execution reports).
+    pub maker_remaining_qty: i64,
+}
+
+/// Result of `OrderBook::queue_position`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueuePosition {
+    pub side: Side,
+    pub price: i64,
+    pub orders_ahead: usize,
+    // Capped at i64::MAX, like `level_qty`.
+    pub qty_ahead: i64,
+    pub remaining_qty: i64,
+}
+
+/// How far an order's immediate match went, and where any remainder rested (`OrderBook::add_capped_with`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Sweep {
+    // Distinct opposite price levels that produced at least one fill.
+    pub levels_swept: u32,
+    // Remainder dropped because the level cap was hit (it still crossed, so it can't rest).
+    pub capped_qty: i64,
+    // Qty left resting at the order's price (0 = nothing rested).
+    pub rested_qty: i64,
+    // Orders ahead of it at its level when it rested: `queue_position`'s `orders_ahead` at that instant.
+    pub rest_rank: usize,
+}
+
+/// One broken invariant found by `OrderBook::verify`. `seq` is 0 for level- or book-wide problems.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BookViolation {
+    pub side: Side,
+    pub price: i64,
+    pub seq: u64,
+    pub detail: String,
+}
+
+/// Total remaining qty at one price level, capped at `i64::MAX`; the flag says the real total is
+/// larger. Summed in i128: every order fits i64 on its own, a deep enough level may not.
+pub fn level_qty(level: &VecDeque<RestingOrder>) -> (i64, bool) {
+    capped_qty(level.iter())
+}
+
+/// Remaining qty of `orders`, summed and capped like `level_qty`.
+fn capped_qty<'a>(orders: impl Iterator<Item = &'a RestingOrder>) -> (i64, bool) {
+    let total: i128 = orders.map(|o| o.remaining_qty as i128).sum();
+    (total.min(i64::MAX as i128) as i64, total > i64::MAX as i128)
+}
+
+fn index_client_order_id(ids: &mut HashMap<String, Vec<u64>>, o: &RestingOrder) {
+    ids.entry(o.client_order_id.clone())
+        .or_default()
+        .push(o.seq);
+}
+
+fn unindex_client_order_id(ids: &mut HashMap<String, Vec<u64>>, o: &RestingOrder) {
+    if let Some(seqs) = ids.get_mut(&o.client_order_id) {
+        seqs.retain(|&s| s != o.seq);
+        if seqs.is_empty() {
+            ids.remove(&o.client_order_id);
+        }
+    }
 }
 
 /// Price-level book with FIFO at each price.
 /// - bids: highest price is best bid
 /// - asks: lowest price is best ask
+///
+/// Keys are signed, so ordering holds across zero when negative prices are allowed.
 #[derive(Debug, Default)]
 pub struct OrderBook {
     pub bids: BTreeMap<i64, VecDeque<RestingOrder>>,
     pub asks: BTreeMap<i64, VecDeque<RestingOrder>>,
+    // Per-symbol opt-in; otherwise price < 0 is treated as a caller bug.
+    pub allow_negative_price: bool,
+    // client_order_id -> resting seqs, in the order they rested. Kept in step by add/cancel/rest,
+    // so orders must enter the levels through those, not by pushing onto bids/asks directly.
+    client_order_ids: HashMap<String, Vec<u64>>,
 }
 
 impl OrderBook {
@@ -68,26 +146,67 @@ impl OrderBook {
         Self::default()
     }
 
+    pub fn with_negative_prices(allow_negative_price: bool) -> Self {
+        Self {
+            allow_negative_price,
+            ..Self::default()
+        }
+    }
+
     /// Add an order:
     /// - If it crosses the book, match it (price-time priority, FIFO at each level).
     /// - Any remaining qty rests in the book.
     ///
     /// Returns fills (for trade reporting).
     pub fn add(&mut self, order: Order) -> Vec<Fill> {
+        let mut fills: Vec<Fill> = Vec::new();
+        self.add_with(order, |f| fills.push(f));
+        fills
+    }
+
+    /// Same matching as `add`, but hands each fill to `on_fill` as it happens
+    /// (same fills, same order) instead of collecting them.
+    pub fn add_with(&mut self, order: Order, on_fill: impl FnMut(Fill)) {
+        self.add_capped_with(order, 0, on_fill);
+    }
+
+    /// `add_with`, but stop after filling against `max_levels` distinct price levels (0 = no cap).
+    /// If the order still crosses at that point the remainder is cancelled, never rested:
+    /// resting it would leave a crossed book. A remainder that stops crossing rests as usual.
+    pub fn add_capped_with(
+        &mut self,
+        order: Order,
+        max_levels: u32,
+        mut on_fill: impl FnMut(Fill),
+    ) -> Sweep {
+        self.add_capped_with_maker(order, max_levels, |f, _| on_fill(f))
+    }
+
+    /// `add_capped_with`, also lending each fill's maker as it stands after the fill (before a
+    /// filled one leaves the book), so a caller that reports on makers copies only what it needs.
+    pub fn add_capped_with_maker(
+        &mut self,
+        order: Order,
+        max_levels: u32,
+        mut on_fill: impl FnMut(Fill, &RestingOrder),
+    ) -> Sweep {
+        let mut sweep = Sweep::default();
+        let capped = |sweep: &Sweep| max_levels > 0 && sweep.levels_swept >= max_levels;
+
         // Hard invariants: these should already be validated by the RPC layer,
         // but we guard here too so replay/future code can’t corrupt state.
         if order.qty <= 0 {
             // Reject silently at book level; caller (engine) should have validated already.
             // This avoids infinite loops / negative resting qty.
             debug_assert!(order.qty > 0, "OrderBook::add got qty <= 0");
-            return Vec::new();
+            return sweep;
         }
-        if order.price < 0 {
+        if order.price < 0 && !self.allow_negative_price {
             debug_assert!(order.price >= 0, "OrderBook::add got price < 0");
-            return Vec::new();
+            return sweep;
         }
-
-        let mut fills: Vec<Fill> = Vec::new();
+        // Price 0 is an ordinary price here, never "no price": a 0 bid crosses only asks <= 0.
+        // `reject_zero_price` is enforced at entry, not here, so replay rebuilds older 0-priced orders.
 
         // Taker remaining qty (mutated during matching)
         let mut remaining = order.qty;
@@ -104,6 +223,12 @@ impl OrderBook {
                     if order.price < best_ask_price {
                         break; // not crossing
                     }
+                    if capped(&sweep) {
+                        sweep.capped_qty = remaining;
+                        remaining = 0;
+                        break;
+                    }
+                    sweep.levels_swept += 1;
 
                     // Match against FIFO queue at best ask price
                     let mut remove_level = false;
@@ -126,23 +251,43 @@ impl OrderBook {
                             );
                             if front.remaining_qty <= 0 {
                                 // Defensive: remove corrupt maker and continue.
-                                q.pop_front();
+                                if let Some(gone) = q.pop_front() {
+                                    unindex_client_order_id(&mut self.client_order_ids, &gone);
+                                }
                                 continue;
                             }
 
+                            // Fills print at the maker's resting price, never worse for the taker than its limit.
+                            // Bound once, so the guard checks the very price the Fill carries.
+                            let price = best_ask_price;
+                            debug_assert!(
+                                front.price == price && price <= order.price,
+                                "buy fill at {} (maker {} rests at {}) breaches limit {}",
+                                price,
+                                front.seq,
+                                front.price,
+                                order.price
+                            );
+
                             let traded = remaining.min(front.remaining_qty);
                             remaining -= traded;
                             front.remaining_qty -= traded;
 
-                            fills.push(Fill {
-                                maker_seq: front.seq,
-                                taker_seq: order.seq,
-                                price: best_ask_price,
-                                qty: traded,
-                            });
+                            on_fill(
+                                Fill {
+                                    maker_seq: front.seq,
+                                    taker_seq: order.seq,
+                                    price,
+                                    qty: traded,
+                                    maker_remaining_qty: front.remaining_qty,
+                                },
+                                front,
+                            );
 
                             if front.remaining_qty == 0 {
-                                q.pop_front();
+                                if let Some(filled) = q.pop_front() {
+                                    unindex_client_order_id(&mut self.client_order_ids, &filled);
+                                }
                                 continue;
                             }
 
@@ -169,12 +314,14 @@ impl OrderBook {
                         price: order.price,
                         remaining_qty: remaining,
                         client_order_id: order.client_order_id.clone(),
+                        orig_qty: order.qty,
                     };
 
-                    self.bids
-                        .entry(order.price)
-                        .or_insert_with(VecDeque::new)
-                        .push_back(resting);
+                    index_client_order_id(&mut self.client_order_ids, &resting);
+                    let level = self.bids.entry(order.price).or_default();
+                    sweep.rested_qty = remaining;
+                    sweep.rest_rank = level.len();
+                    level.push_back(resting);
                 }
             }
 
@@ -189,6 +336,12 @@ impl OrderBook {
                     if order.price > best_bid_price {
                         break; // not crossing
                     }
+                    if capped(&sweep) {
+                        sweep.capped_qty = remaining;
+                        remaining = 0;
+                        break;
+                    }
+                    sweep.levels_swept += 1;
 
                     // Match against FIFO queue at best bid price
                     let mut remove_level = false;
@@ -209,23 +362,41 @@ impl OrderBook {
                                 "resting maker has non-positive remaining_qty"
                             );
                             if front.remaining_qty <= 0 {
-                                q.pop_front();
+                                if let Some(gone) = q.pop_front() {
+                                    unindex_client_order_id(&mut self.client_order_ids, &gone);
+                                }
                                 continue;
                             }
 
+                            let price = best_bid_price;
+                            debug_assert!(
+                                front.price == price && price >= order.price,
+                                "sell fill at {} (maker {} rests at {}) breaches limit {}",
+                                price,
+                                front.seq,
+                                front.price,
+                                order.price
+                            );
+
                             let traded = remaining.min(front.remaining_qty);
                             remaining -= traded;
                             front.remaining_qty -= traded;
 
-                            fills.push(Fill {
-                                maker_seq: front.seq,
-                                taker_seq: order.seq,
-                                price: best_bid_price,
-                                qty: traded,
-                            });
+                            on_fill(
+                                Fill {
+                                    maker_seq: front.seq,
+                                    taker_seq: order.seq,
+                                    price,
+                                    qty: traded,
+                                    maker_remaining_qty: front.remaining_qty,
+                                },
+                                front,
+                            );
 
                             if front.remaining_qty == 0 {
-                                q.pop_front();
+                                if let Some(filled) = q.pop_front() {
+                                    unindex_client_order_id(&mut self.client_order_ids, &filled);
+                                }
                                 continue;
                             }
 
@@ -252,37 +423,308 @@ impl OrderBook {
                         price: order.price,
                         remaining_qty: remaining,
                         client_order_id: order.client_order_id.clone(),
+                        orig_qty: order.qty,
                     };
 
-                    self.asks
-                        .entry(order.price)
-                        .or_insert_with(VecDeque::new)
-                        .push_back(resting);
+                    index_client_order_id(&mut self.client_order_ids, &resting);
+                    let level = self.asks.entry(order.price).or_default();
+                    sweep.rested_qty = remaining;
+                    sweep.rest_rank = level.len();
+                    level.push_back(resting);
                 }
             }
         }
+        sweep
+    }
 
-        fills
+    /// Qty an incoming order would fill right now (capped at `qty`), without mutating the book.
+    /// `max_levels` mirrors `add_capped_with` (0 = no cap).
+    pub fn matchable_qty(&self, side: Side, price: i64, qty: i64, max_levels: u32) -> i64 {
+        let crossing: Box<dyn Iterator<Item = (&i64, &VecDeque<RestingOrder>)>> = match side {
+            Side::Buy => Box::new(self.asks.range(..=price)),
+            Side::Sell => Box::new(self.bids.range(price..).rev()),
+        };
+
+        let levels = if max_levels == 0 {
+            usize::MAX
+        } else {
+            max_levels as usize
+        };
+        let mut matchable = 0i64;
+        for (_, q) in crossing.take(levels) {
+            matchable = matchable.saturating_add(level_qty(q).0);
+            if matchable >= qty {
+                return qty;
+            }
+        }
+        matchable
+    }
+
+    /// Look up a resting order by seq (linear scan over both sides).
+    pub fn get(&self, seq: u64) -> Option<&RestingOrder> {
+        self.bids
+            .values()
+            .chain(self.asks.values())
+            .flat_map(|q| q.iter())
+            .find(|o| o.seq == seq)
+    }
+
+    /// Seqs of resting orders carrying `client_order_id`, in the order they rested (indexed, not
+    /// scanned). Client ids aren't unique, so callers must handle more than one.
+    pub fn seqs_for_client_order_id(&self, client_order_id: &str) -> Vec<u64> {
+        self.client_order_ids
+            .get(client_order_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Append an already-resting order at the back of its side's price level, without matching.
+    /// For rebuilding a book from a snapshot, level by level in FIFO order.
+    pub fn rest(&mut self, order: RestingOrder) {
+        index_client_order_id(&mut self.client_order_ids, &order);
+        let levels = match order.side {
+            Side::Buy => &mut self.bids,
+            Side::Sell => &mut self.asks,
+        };
+        levels.entry(order.price).or_default().push_back(order);
+    }
+
+    /// FIFO position of a resting order within its price level.
+    pub fn queue_position(&self, seq: u64) -> Option<QueuePosition> {
+        let sides = [(Side::Buy, &self.bids), (Side::Sell, &self.asks)];
+        for (side, levels) in sides {
+            for (price, q) in levels.iter() {
+                let Some(idx) = q.iter().position(|o| o.seq == seq) else {
+                    continue;
+                };
+                return Some(QueuePosition {
+                    side,
+                    price: *price,
+                    orders_ahead: idx,
+                    qty_ahead: capped_qty(q.iter().take(idx)).0,
+                    remaining_qty: q[idx].remaining_qty,
+                });
+            }
+        }
+        None
+    }
+
+    /// Remove a resting order by seq, dropping its price level if it becomes empty.
+    /// Returns None if the seq is not resting (already filled, cancelled or unknown).
+    pub fn cancel(&mut self, seq: u64) -> Option<RestingOrder> {
+        for levels in [&mut self.bids, &mut self.asks] {
+            let found = levels.iter().find_map(|(price, q)| {
+                q.iter().position(|o| o.seq == seq).map(|idx| (*price, idx))
+            });
+
+            if let Some((price, idx)) = found {
+                let q = levels.get_mut(&price).expect("level disappeared");
+                let removed = q.remove(idx);
+                if q.is_empty() {
+                    levels.remove(&price);
+                }
+                if let Some(o) = &removed {
+                    unindex_client_order_id(&mut self.client_order_ids, o);
+                }
+                return removed;
+            }
+        }
+        None
+    }
+
+    /// Seqs resting on `side` with min_price <= price <= max_price, in price then FIFO order.
+    /// Collected up front so callers can cancel them without mutating the map mid-iteration.
+    pub fn seqs_in_range(&self, side: Side, min_price: i64, max_price: i64) -> Vec<u64> {
+        if min_price > max_price {
+            return Vec::new();
+        }
+        let levels = match side {
+            Side::Buy => &self.bids,
+            Side::Sell => &self.asks,
+        };
+        levels
+            .range(min_price..=max_price)
+            .flat_map(|(_, q)| q.iter().map(|o| o.seq))
+            .collect()
+    }
+
+    /// Exact sum of price * remaining_qty over one side, in i128. A few orders near i64::MAX at
+    /// extreme prices can still overflow that: then the total is capped at i128::MAX (i128::MIN
+    /// for a negative price) and the flag is set, like `level_qty`.
+    pub fn resting_notional(&self, side: Side) -> (i128, bool) {
+        let levels = match side {
+            Side::Buy => &self.bids,
+            Side::Sell => &self.asks,
+        };
+        let mut total = 0i128;
+        for (&price, q) in levels.iter() {
+            let qty: i128 = q.iter().map(|o| o.remaining_qty as i128).sum();
+            match (price as i128)
+                .checked_mul(qty)
+                .and_then(|n| total.checked_add(n))
+            {
+                Some(t) => total = t,
+                None => return (if price < 0 { i128::MIN } else { i128::MAX }, true),
+            }
+        }
+        (total, false)
+    }
+
+    /// Brute-force check of every book invariant straight from the level queues. Read-only;
+    /// empty means consistent. Anything derived from the queues (the client_order_id index, and
+    /// later level totals or a seq index) is cross-checked here too.
+    pub fn verify(&self) -> Vec<BookViolation> {
+        let mut out = Vec::new();
+        let mut seen = std::collections::HashSet::new();
+        let mut ids: HashMap<&str, Vec<u64>> = HashMap::new();
+        let mut flag = |side, price, seq, detail: String| {
+            out.push(BookViolation {
+                side,
+                price,
+                seq,
+                detail,
+            })
+        };
+
+        for (side, levels) in [(Side::Buy, &self.bids), (Side::Sell, &self.asks)] {
+            for (&price, q) in levels.iter() {
+                if q.is_empty() {
+                    flag(side, price, 0, "empty price level".to_string());
+                }
+                if price < 0 && !self.allow_negative_price {
+                    flag(side, price, 0, "negative price level".to_string());
+                }
+                for o in q.iter() {
+                    if o.side != side {
+                        flag(
+                            side,
+                            price,
+                            o.seq,
+                            format!("order side {:?} on the {:?} side", o.side, side),
+                        );
+                    }
+                    if o.price != price {
+                        flag(
+                            side,
+                            price,
+                            o.seq,
+                            format!("order price {} at level {}", o.price, price),
+                        );
+                    }
+                    if o.remaining_qty <= 0 || o.remaining_qty > o.orig_qty {
+                        flag(
+                            side,
+                            price,
+                            o.seq,
+                            format!(
+                                "remaining_qty {} outside 1..={}",
+                                o.remaining_qty, o.orig_qty
+                            ),
+                        );
+                    }
+                    if !seen.insert(o.seq) {
+                        flag(side, price, o.seq, "seq rests more than once".to_string());
+                    }
+                    let indexed = self.client_order_ids.get(&o.client_order_id);
+                    if !indexed.is_some_and(|seqs| seqs.contains(&o.seq)) {
+                        flag(
+                            side,
+                            price,
+                            o.seq,
+                            format!("client_order_id '{}' not indexed", o.client_order_id),
+                        );
+                    }
+                    ids.entry(&o.client_order_id).or_default().push(o.seq);
+                }
+            }
+        }
+
+        for (id, seqs) in self.client_order_ids.iter() {
+            for &seq in seqs {
+                if !ids
+                    .get(id.as_str())
+                    .is_some_and(|resting| resting.contains(&seq))
+                {
+                    flag(
+                        Side::Buy,
+                        0,
+                        seq,
+                        format!(
+                            "client_order_id '{}' indexes a seq not resting under it",
+                            id
+                        ),
+                    );
+                }
+            }
+        }
+
+        if let (Some((&bid, _)), Some((&ask, _))) =
+            (self.bids.last_key_value(), self.asks.first_key_value())
+        {
+            if bid >= ask {
+                flag(
+                    Side::Buy,
+                    bid,
+                    0,
+                    format!("book crossed: best bid {} >= best ask {}", bid, ask),
+                );
+            }
+        }
+        out
+    }
+
+    /// Supply/demand curve on `side` from the touch outward (bids descending, asks ascending),
+    /// at most `points` levels: (price, qty resting at that price or better, capped). The total is
+    /// what an opposite limit order at that price could fill. Summed in i128 and capped at
+    /// `i64::MAX` like `level_qty`, so it never decreases along the curve.
+    pub fn liquidity_curve(&self, side: Side, points: usize) -> Vec<(i64, i64, bool)> {
+        let levels: Box<dyn Iterator<Item = (&i64, &VecDeque<RestingOrder>)>> = match side {
+            Side::Buy => Box::new(self.bids.iter().rev()),
+            Side::Sell => Box::new(self.asks.iter()),
+        };
+
+        let (mut total, mut saturated) = (0i128, false);
+        levels
+            .take(points)
+            .map(|(price, q)| {
+                let (qty, level_saturated) = level_qty(q);
+                total += qty as i128;
+                saturated |= level_saturated || total > i64::MAX as i128;
+                (*price, total.min(i64::MAX as i128) as i64, saturated)
+            })
+            .collect()
     }
 
     /// Derived top-of-book (best price + aggregated qty at that price level).
+    /// Qtys are capped at `i64::MAX`; `top_of_book_saturated` says which were.
     pub fn top_of_book(&self) -> (i64, i64, i64, i64) {
         let (best_bid_price, best_bid_qty) = self
             .bids
             .iter()
             .next_back() // highest bid
-            .map(|(price, q)| (*price, q.iter().map(|o| o.remaining_qty).sum()))
+            .map(|(price, q)| (*price, level_qty(q).0))
             .unwrap_or((0, 0));
 
         let (best_ask_price, best_ask_qty) = self
             .asks
             .iter()
             .next() // lowest ask
-            .map(|(price, q)| (*price, q.iter().map(|o| o.remaining_qty).sum()))
+            .map(|(price, q)| (*price, level_qty(q).0))
             .unwrap_or((0, 0));
 
         (best_bid_price, best_bid_qty, best_ask_price, best_ask_qty)
     }
+
+    /// (best bid, best ask) qty in `top_of_book` was capped at `i64::MAX`.
+    pub fn top_of_book_saturated(&self) -> (bool, bool) {
+        (
+            self.bids
+                .values()
+                .next_back()
+                .is_some_and(|q| level_qty(q).1),
+            self.asks.values().next().is_some_and(|q| level_qty(q).1),
+        )
+    }
 }
 
 #[cfg(test)]
@@ -339,11 +781,13 @@ mod tests {
         assert_eq!(fills[1].price, 102);
         assert_eq!(fills[1].qty, 1);
 
-        // Remaining ask at 102 should be qty=1
+        // Remaining ask at 102 should be qty=1, with 1 of 2 filled
         let q = book.asks.get(&102).unwrap();
         assert_eq!(q.len(), 1);
         assert_eq!(q.front().unwrap().seq, 2);
         assert_eq!(q.front().unwrap().remaining_qty, 1);
+        assert_eq!(q.front().unwrap().orig_qty, 2);
+        assert_eq!(q.front().unwrap().filled_qty(), 1);
 
         // No bids should rest (taker fully filled)
         assert!(book.bids.is_empty());
@@ -436,8 +880,440 @@ mod tests {
         assert_eq!(qb.len(), 1);
         assert_eq!(qb.front().unwrap().seq, 2);
         assert_eq!(qb.front().unwrap().remaining_qty, 3);
+        // orig_qty is the taker's accepted qty, not what was left to rest
+        assert_eq!(qb.front().unwrap().orig_qty, 5);
 
         let (bbp, bbq, bap, baq) = book.top_of_book();
         assert_eq!((bbp, bbq, bap, baq), (101, 3, 0, 0));
     }
+
+    #[test]
+    fn cancel_removes_order_and_empty_level() {
+        let mut book = OrderBook::new();
+
+        assert!(book.add(o(1, Side::Sell, 101, 2)).is_empty());
+        assert!(book.add(o(2, Side::Sell, 101, 3)).is_empty());
+        assert!(book.add(o(3, Side::Buy, 99, 4)).is_empty());
+
+        // Middle of a level: FIFO of the rest is preserved
+        let c = book.cancel(1).unwrap();
+        assert_eq!((c.seq, c.remaining_qty), (1, 2));
+        let q = book.asks.get(&101).unwrap();
+        assert_eq!(q.len(), 1);
+        assert_eq!(q.front().unwrap().seq, 2);
+
+        // Last order at a level drops the level
+        assert_eq!(book.cancel(3).unwrap().seq, 3);
+        assert!(book.bids.is_empty());
+
+        // Unknown / already-cancelled seq
+        assert!(book.cancel(3).is_none());
+        assert!(book.cancel(42).is_none());
+        assert!(book.get(1).is_none());
+        assert_eq!(book.get(2).unwrap().remaining_qty, 3);
+    }
+
+    #[test]
+    fn cancel_skips_filled_order() {
+        let mut book = OrderBook::new();
+
+        assert!(book.add(o(1, Side::Sell, 101, 2)).is_empty());
+        assert_eq!(book.add(o(2, Side::Buy, 101, 2)).len(), 1);
+
+        assert!(book.cancel(1).is_none());
+        assert!(book.asks.is_empty());
+    }
+
+    #[test]
+    fn sweeps_fill_at_each_makers_price_not_the_takers_limit() {
+        let mut book = OrderBook::new();
+        for (seq, price, qty) in [(1, 101, 1), (2, 102, 2), (3, 102, 1), (4, 103, 3)] {
+            book.add(o(seq, Side::Sell, price, qty));
+        }
+        let fills = book.add(o(5, Side::Buy, 200, 6));
+        let prints: Vec<(u64, i64, i64)> = fills
+            .iter()
+            .map(|f| (f.maker_seq, f.price, f.qty))
+            .collect();
+        assert_eq!(
+            prints,
+            vec![(1, 101, 1), (2, 102, 2), (3, 102, 1), (4, 103, 2)]
+        );
+        assert_eq!(book.top_of_book(), (0, 0, 103, 1));
+
+        for (seq, price, qty) in [(6, 99, 2), (7, 98, 1), (8, 97, 4)] {
+            book.add(o(seq, Side::Buy, price, qty));
+        }
+        let fills = book.add(o(9, Side::Sell, 1, 5));
+        let prints: Vec<(u64, i64, i64)> = fills
+            .iter()
+            .map(|f| (f.maker_seq, f.price, f.qty))
+            .collect();
+        assert_eq!(prints, vec![(6, 99, 2), (7, 98, 1), (8, 97, 2)]);
+        assert_eq!(book.top_of_book(), (97, 2, 103, 1));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "breaches limit")]
+    fn fill_price_guard_catches_a_maker_off_its_level() {
+        let mut book = OrderBook::new();
+        book.add(o(1, Side::Sell, 105, 1));
+        // Corrupt: the order says 105 but sits at the 100 level, so a 101 buy would print at 100.
+        let maker = book.asks.remove(&105).unwrap();
+        book.asks.insert(100, maker);
+        book.add(o(2, Side::Buy, 101, 1));
+    }
+
+    #[test]
+    fn liquidity_curve_accumulates_from_the_touch() {
+        let mut book = OrderBook::new();
+        for (seq, side, price, qty) in [
+            (1, Side::Buy, 99, 2),
+            (2, Side::Buy, 100, 1),
+            (3, Side::Buy, 100, 3),
+            (4, Side::Buy, 97, 5),
+            (5, Side::Sell, 103, 4),
+            (6, Side::Sell, 101, 1),
+        ] {
+            book.add(o(seq, side, price, qty));
+        }
+
+        assert_eq!(
+            book.liquidity_curve(Side::Buy, 10),
+            vec![(100, 4, false), (99, 6, false), (97, 11, false)]
+        );
+        assert_eq!(
+            book.liquidity_curve(Side::Buy, 2),
+            vec![(100, 4, false), (99, 6, false)]
+        );
+        assert_eq!(
+            book.liquidity_curve(Side::Sell, 10),
+            vec![(101, 1, false), (103, 5, false)]
+        );
+        // Each point is exactly what a marketable limit at that price could take
+        assert_eq!(book.matchable_qty(Side::Sell, 99, i64::MAX, 0), 6);
+
+        book.add(o(7, Side::Sell, 104, i64::MAX));
+        assert_eq!(
+            book.liquidity_curve(Side::Sell, 10)[2],
+            (104, i64::MAX, true)
+        );
+    }
+
+    #[test]
+    fn level_qty_saturates_instead_of_wrapping() {
+        let mut book = OrderBook::new();
+        book.add(o(1, Side::Buy, 100, i64::MAX - 1));
+        book.add(o(2, Side::Sell, 101, 5));
+        assert_eq!(book.top_of_book(), (100, i64::MAX - 1, 101, 5));
+        assert_eq!(book.top_of_book_saturated(), (false, false));
+
+        book.add(o(3, Side::Buy, 100, 1));
+        assert_eq!(level_qty(&book.bids[&100]), (i64::MAX, false));
+        book.add(o(4, Side::Buy, 100, 2));
+        assert_eq!(level_qty(&book.bids[&100]), (i64::MAX, true));
+        assert_eq!(book.top_of_book(), (100, i64::MAX, 101, 5));
+        assert_eq!(book.top_of_book_saturated(), (true, false));
+
+        // Everything else summed over a level saturates the same way
+        assert_eq!(book.queue_position(4).unwrap().qty_ahead, i64::MAX);
+        assert_eq!(
+            book.liquidity_curve(Side::Buy, 1),
+            vec![(100, i64::MAX, true)]
+        );
+    }
+
+    #[test]
+    fn rest_rank_matches_queue_position_at_insertion() {
+        let mut book = OrderBook::new();
+        let ranks: Vec<(i64, usize)> = [(1, 100, 2), (2, 100, 3), (3, 99, 1), (4, 100, 1)]
+            .into_iter()
+            .map(|(seq, price, qty)| {
+                let sweep = book.add_capped_with(o(seq, Side::Buy, price, qty), 0, |_| {});
+                let pos = book.queue_position(seq).unwrap();
+                assert_eq!(
+                    (sweep.rest_rank, sweep.rested_qty),
+                    (pos.orders_ahead, pos.remaining_qty)
+                );
+                (sweep.rested_qty, sweep.rest_rank)
+            })
+            .collect();
+        assert_eq!(ranks, vec![(2, 0), (3, 1), (1, 0), (1, 2)]);
+
+        // Partially filled, then rests: rank at its own (new) level
+        book.add(o(5, Side::Buy, 101, 1));
+        let sweep = book.add_capped_with(o(6, Side::Sell, 101, 3), 0, |_| {});
+        assert_eq!((sweep.rested_qty, sweep.rest_rank), (2, 0));
+        // Fully filled: nothing rests
+        let sweep = book.add_capped_with(o(7, Side::Sell, 100, 2), 0, |_| {});
+        assert_eq!((sweep.rested_qty, sweep.rest_rank), (0, 0));
+    }
+
+    #[test]
+    fn level_cap_cancels_a_still_crossing_remainder() {
+        let mut book = OrderBook::new();
+        for (seq, price) in [(1, 100), (2, 100), (3, 101), (4, 102)] {
+            book.add(o(seq, Side::Sell, price, 2));
+        }
+
+        // Two levels (100 twice counts once), then 102 would still cross: cancelled, not rested
+        let mut fills = Vec::new();
+        let sweep = book.add_capped_with(o(5, Side::Buy, 102, 10), 2, |f| fills.push(f));
+        assert_eq!(
+            (sweep.levels_swept, sweep.capped_qty, sweep.rested_qty),
+            (2, 4, 0)
+        );
+        assert_eq!(
+            fills.iter().map(|f| f.maker_seq).collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+        assert!(book.get(5).is_none());
+        assert_eq!(book.top_of_book(), (0, 0, 102, 2));
+
+        // Cap not reached because the order stops crossing: the remainder rests
+        let sweep = book.add_capped_with(o(6, Side::Buy, 102, 5), 2, |_| {});
+        assert_eq!(
+            (sweep.levels_swept, sweep.capped_qty, sweep.rested_qty),
+            (1, 0, 3)
+        );
+        assert_eq!(book.top_of_book(), (102, 3, 0, 0));
+
+        // A cap that is never reached changes nothing
+        let sweep = book.add_capped_with(o(7, Side::Sell, 90, 1), 5, |_| {});
+        assert_eq!(
+            (sweep.levels_swept, sweep.capped_qty, sweep.rested_qty),
+            (1, 0, 0)
+        );
+    }
+
+    #[test]
+    fn zero_priced_bid_only_crosses_zero_priced_asks() {
+        let mut book = OrderBook::new();
+
+        assert!(book.add(o(1, Side::Buy, 0, 3)).is_empty());
+        assert!(book.add(o(2, Side::Sell, 1, 1)).is_empty());
+        assert_eq!(book.top_of_book(), (0, 3, 1, 1));
+
+        let fills = book.add(o(3, Side::Sell, 0, 2));
+        assert_eq!(fills.len(), 1);
+        assert_eq!(
+            (fills[0].maker_seq, fills[0].price, fills[0].qty),
+            (1, 0, 2)
+        );
+        assert_eq!(book.top_of_book(), (0, 1, 1, 1));
+    }
+
+    #[test]
+    fn negative_prices_keep_best_bid_ask_semantics_across_zero() {
+        let mut book = OrderBook::with_negative_prices(true);
+
+        assert!(book.add(o(1, Side::Buy, -5, 1)).is_empty());
+        assert!(book.add(o(2, Side::Buy, -2, 2)).is_empty());
+        assert!(book.add(o(3, Side::Sell, 3, 1)).is_empty());
+        assert!(book.add(o(4, Side::Sell, -1, 4)).is_empty());
+
+        // Best bid is the highest (-2), best ask the lowest (-1)
+        assert_eq!(book.top_of_book(), (-2, 2, -1, 4));
+
+        // Sell at -6 sweeps bids from -2 down to -5, filling at maker prices
+        let fills = book.add(o(5, Side::Sell, -6, 3));
+        assert_eq!(fills.len(), 2);
+        assert_eq!(
+            (fills[0].maker_seq, fills[0].price, fills[0].qty),
+            (2, -2, 2)
+        );
+        assert_eq!(
+            (fills[1].maker_seq, fills[1].price, fills[1].qty),
+            (1, -5, 1)
+        );
+        assert!(book.bids.is_empty());
+
+        // Buy at 0 lifts the -1 ask across zero
+        let fills = book.add(o(6, Side::Buy, 0, 1));
+        assert_eq!((fills[0].maker_seq, fills[0].price), (4, -1));
+        assert_eq!(book.top_of_book(), (0, 0, -1, 3));
+    }
+
+    #[test]
+    fn seqs_in_range_is_inclusive_and_side_specific() {
+        let mut book = OrderBook::new();
+
+        assert!(book.add(o(1, Side::Buy, 98, 1)).is_empty());
+        assert!(book.add(o(2, Side::Buy, 99, 1)).is_empty());
+        assert!(book.add(o(3, Side::Buy, 99, 1)).is_empty());
+        assert!(book.add(o(4, Side::Buy, 100, 1)).is_empty());
+        assert!(book.add(o(5, Side::Sell, 101, 1)).is_empty());
+
+        assert_eq!(book.seqs_in_range(Side::Buy, 99, 100), vec![2, 3, 4]);
+        assert_eq!(book.seqs_in_range(Side::Sell, 0, 200), vec![5]);
+        assert!(book.seqs_in_range(Side::Buy, 101, 200).is_empty());
+        assert!(book.seqs_in_range(Side::Buy, 100, 99).is_empty());
+    }
+
+    #[test]
+    fn queue_position_counts_orders_ahead_at_the_level() {
+        let mut book = OrderBook::new();
+        assert!(book.add(o(1, Side::Sell, 101, 4)).is_empty());
+        assert!(book.add(o(2, Side::Sell, 101, 6)).is_empty());
+        assert!(book.add(o(3, Side::Sell, 101, 1)).is_empty());
+        assert!(book.add(o(4, Side::Sell, 100, 9)).is_empty());
+
+        let front = book.queue_position(1).unwrap();
+        assert_eq!(
+            (front.orders_ahead, front.qty_ahead, front.remaining_qty),
+            (0, 0, 4)
+        );
+
+        let third = book.queue_position(3).unwrap();
+        assert_eq!(
+            (third.side, third.price, third.orders_ahead, third.qty_ahead),
+            (Side::Sell, 101, 2, 10)
+        );
+
+        // Better-priced level doesn't count as "ahead" in the same queue
+        assert_eq!(book.queue_position(4).unwrap().orders_ahead, 0);
+
+        // Fully filled / unknown
+        let _ = book.add(o(5, Side::Buy, 100, 9));
+        assert!(book.queue_position(4).is_none());
+        assert!(book.queue_position(99).is_none());
+    }
+
+    #[test]
+    fn add_with_yields_the_same_fills_as_add() {
+        let makers = [(1, 100, 3), (2, 100, 2), (3, 101, 4), (4, 103, 1)];
+        let (mut a, mut b) = (OrderBook::new(), OrderBook::new());
+        for (seq, price, qty) in makers {
+            assert!(a.add(o(seq, Side::Sell, price, qty)).is_empty());
+            assert!(b.add(o(seq, Side::Sell, price, qty)).is_empty());
+        }
+
+        let collected = a.add(o(9, Side::Buy, 102, 8));
+        let mut streamed = Vec::new();
+        b.add_with(o(9, Side::Buy, 102, 8), |f| streamed.push(f));
+
+        let key = |f: &Fill| (f.maker_seq, f.price, f.qty, f.maker_remaining_qty);
+        assert_eq!(collected.len(), 3);
+        assert_eq!(
+            collected.iter().map(key).collect::<Vec<_>>(),
+            streamed.iter().map(key).collect::<Vec<_>>()
+        );
+        assert_eq!(a.top_of_book(), b.top_of_book());
+    }
+
+    #[test]
+    fn matchable_qty_only_counts_crossing_levels() {
+        let mut book = OrderBook::new();
+        assert!(book.add(o(1, Side::Sell, 100, 3)).is_empty());
+        assert!(book.add(o(2, Side::Sell, 101, 4)).is_empty());
+        assert!(book.add(o(3, Side::Sell, 102, 5)).is_empty());
+
+        assert_eq!(book.matchable_qty(Side::Buy, 99, 10, 0), 0);
+        assert_eq!(book.matchable_qty(Side::Buy, 101, 10, 0), 7);
+        assert_eq!(book.matchable_qty(Side::Buy, 102, 10, 0), 10); // capped at order qty
+        assert_eq!(book.matchable_qty(Side::Sell, 0, 10, 0), 0);
+        assert_eq!(book.matchable_qty(Side::Buy, 102, 10, 1), 3); // first level only
+    }
+
+    #[test]
+    fn verify_reports_the_diverging_order() {
+        let mut book = OrderBook::new();
+        book.add(o(1, Side::Buy, 100, 5));
+        book.add(o(2, Side::Buy, 100, 3));
+        book.add(o(3, Side::Sell, 105, 4));
+        assert!(book.verify().is_empty());
+
+        book.bids.get_mut(&100).unwrap()[1].price = 99;
+        book.asks.insert(98, VecDeque::new());
+        let v = book.verify();
+        assert_eq!(v.len(), 3);
+        assert_eq!((v[0].side, v[0].price, v[0].seq), (Side::Buy, 100, 2));
+        assert_eq!((v[1].side, v[1].price, v[1].seq), (Side::Sell, 98, 0));
+        assert!(v[2].detail.starts_with("book crossed"));
+    }
+
+    #[test]
+    fn client_order_id_index_follows_rest_fill_and_cancel() {
+        let mut book = OrderBook::new();
+        let tagged = |seq, side, price, qty, id: &str| Order {
+            client_order_id: id.to_string(),
+            ..o(seq, side, price, qty)
+        };
+        book.add(tagged(1, Side::Sell, 101, 2, "a"));
+        book.add(tagged(2, Side::Sell, 102, 2, "b"));
+        book.add(tagged(3, Side::Buy, 99, 2, "a"));
+        assert_eq!(book.seqs_for_client_order_id("a"), vec![1, 3]);
+
+        // a partial fill keeps the maker indexed; a full fill drops it
+        book.add(tagged(4, Side::Buy, 101, 1, "t"));
+        assert_eq!(book.seqs_for_client_order_id("a"), vec![1, 3]);
+        book.add(tagged(5, Side::Buy, 101, 1, "t"));
+        assert_eq!(book.seqs_for_client_order_id("a"), vec![3]);
+        assert!(book.seqs_for_client_order_id("t").is_empty());
+
+        book.cancel(3);
+        assert!(book.seqs_for_client_order_id("a").is_empty());
+        assert_eq!(book.seqs_for_client_order_id("b"), vec![2]);
+
+        // a rebuilt book indexes what it rests
+        let mut rebuilt = OrderBook::new();
+        for o in book.bids.values().chain(book.asks.values()).flatten() {
+            rebuilt.rest(o.clone());
+        }
+        assert_eq!(rebuilt.seqs_for_client_order_id("b"), vec![2]);
+        assert!(rebuilt.verify().is_empty());
+
+        // an order pushed past the index is caught
+        rebuilt.bids.entry(98).or_default().push_back(RestingOrder {
+            seq: 9,
+            side: Side::Buy,
+            price: 98,
+            remaining_qty: 1,
+            orig_qty: 1,
+            client_order_id: "x".to_string(),
+        });
+        let v = rebuilt.verify();
+        assert_eq!((v.len(), v[0].seq), (1, 9));
+        assert!(v[0].detail.contains("not indexed"));
+    }
+
+    #[test]
+    fn resting_notional_is_exact_past_i64() {
+        let mut book = OrderBook::new();
+        assert_eq!(book.resting_notional(Side::Buy), (0, false));
+
+        assert!(book.add(o(1, Side::Buy, i64::MAX - 1, i64::MAX)).is_empty());
+        assert!(book.add(o(2, Side::Buy, 10, 3)).is_empty());
+        assert!(book.add(o(3, Side::Buy, 10, 4)).is_empty());
+        assert!(book.add(o(4, Side::Sell, i64::MAX, 2)).is_empty());
+
+        let max = i64::MAX as i128;
+        assert_eq!(
+            book.resting_notional(Side::Buy),
+            ((max - 1) * max + 70, false)
+        );
+        assert_eq!(book.resting_notional(Side::Sell), (max * 2, false));
+    }
+
+    #[test]
+    fn resting_notional_saturates_past_i128() {
+        // Each order is ~2^126 of notional; the second one passes i128::MAX (~2^127)
+        let mut book = OrderBook::new();
+        book.add(o(1, Side::Sell, i64::MAX, i64::MAX));
+        assert_eq!(
+            book.resting_notional(Side::Sell),
+            ((i64::MAX as i128).pow(2), false)
+        );
+        book.add(o(2, Side::Sell, i64::MAX, i64::MAX));
+        book.add(o(3, Side::Sell, i64::MAX, i64::MAX));
+        assert_eq!(book.resting_notional(Side::Sell), (i128::MAX, true));
+
+        // Overflow while summing across levels saturates too, toward the prices' sign
+        let mut book = OrderBook::with_negative_prices(true);
+        for (seq, price) in [(1, i64::MIN), (2, i64::MIN + 1), (3, i64::MIN + 2)] {
+            book.add(o(seq, Side::Buy, price, i64::MAX));
+        }
+        assert_eq!(book.resting_notional(Side::Buy), (i128::MIN, true));
+    }
 }
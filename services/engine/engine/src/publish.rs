@@ -0,0 +1,230 @@
+use std::fs::OpenOptions;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use tokio::sync::mpsc;
+
+use crate::engine::Trade;
+
+/// Log the first drop, then every Nth, so a stalled sink can't flood stderr.
+const DROP_WARN_EVERY: u64 = 1_000;
+
+/// Pushes trades to downstream systems (risk, clearing, analytics) as they hit the tape.
+///
+/// `publish` is called under the engine state lock for every trade, so implementations must
+/// return immediately and can't fail the order: anything slow belongs behind a queue.
+pub trait TradePublisher: Send + Sync {
+    fn publish(&self, trade: &Trade);
+}
+
+/// Default: publishing disabled.
+#[derive(Debug, Default)]
+pub struct NoopPublisher;
+
+impl TradePublisher for NoopPublisher {
+    fn publish(&self, _trade: &Trade) {}
+}
+
+/// Where `ChannelPublisher` delivers trades. Runs on its own thread, so it may block.
+/// A Kafka/NATS producer plugs in here.
+pub trait TradeSink: Send + 'static {
+    fn write(&mut self, trade: &Trade) -> io::Result<()>;
+
+    /// Called whenever the queue drains, so batching sinks can flush.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Bounded hand-off to a sink thread. A full queue drops the trade with a warning rather than
+/// stalling matching; the tape (`GetRecentTrades`) stays the source of truth for backfill.
+#[derive(Debug)]
+pub struct ChannelPublisher {
+    tx: mpsc::Sender<Trade>,
+    dropped: AtomicU64,
+}
+
+impl ChannelPublisher {
+    pub fn spawn(capacity: usize, mut sink: impl TradeSink) -> io::Result<Self> {
+        let (tx, mut rx) = mpsc::channel::<Trade>(capacity);
+
+        std::thread::Builder::new()
+            .name("trade-sink".to_string())
+            .spawn(move || {
+                while let Some(t) = rx.blocking_recv() {
+                    if let Err(e) = sink.write(&t) {
+                        tracing::error!(trade_id = t.trade_id, error = %e, "trade sink write failed, trade not delivered");
+                    }
+                    if rx.is_empty() {
+                        if let Err(e) = sink.flush() {
+                            tracing::error!(error = %e, "trade sink flush failed");
+                        }
+                    }
+                }
+            })?;
+
+        Ok(Self {
+            tx,
+            dropped: AtomicU64::new(0),
+        })
+    }
+}
+
+impl TradePublisher for ChannelPublisher {
+    fn publish(&self, trade: &Trade) {
+        if self.tx.try_send(trade.clone()).is_err() {
+            let n = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+            if n == 1 || n % DROP_WARN_EVERY == 0 {
+                tracing::warn!(
+                    trade_id = trade.trade_id,
+                    dropped = n,
+                    "trade sink backlogged, trade dropped"
+                );
+            }
+        }
+    }
+}
+
+/// Appends each trade as one JSON line, e.g. for a log shipper to forward.
+#[derive(Debug)]
+pub struct JsonLinesSink {
+    out: BufWriter<std::fs::File>,
+}
+
+impl JsonLinesSink {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let f = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            out: BufWriter::new(f),
+        })
+    }
+}
+
+impl TradeSink for JsonLinesSink {
+    fn write(&mut self, t: &Trade) -> io::Result<()> {
+        let mut line = serde_json::json!({
+            "trade_id": t.trade_id,
+            "symbol": t.symbol,
+            "price": t.price,
+            "qty": t.qty,
+            "maker_seq": t.maker_seq,
+            "taker_seq": t.taker_seq,
+            "taker_side": t.taker_side().as_str_name(),
+            "ts_ms": t.ts_ms,
+            "maker_fee": t.maker_fee,
+            "taker_fee": t.taker_fee,
+            "coalesced_fills": t.coalesced_fills,
+        });
+        if !t.makers.is_empty() {
+            let makers: Vec<_> = t
+                .makers
+                .iter()
+                .map(|m| {
+                    serde_json::json!({
+                        "maker_seq": m.maker_seq,
+                        "qty": m.qty,
+                        "maker_fee": m.maker_fee,
+                        "taker_fee": m.taker_fee,
+                    })
+                })
+                .collect();
+            line["makers"] = makers.into();
+        }
+        serde_json::to_writer(&mut self.out, &line)?;
+        self.out.write_all(b"\n")
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc as std_mpsc;
+    use std::time::Duration;
+
+    #[test]
+    fn json_lines_sink_writes_coalesced_fields() {
+        let path =
+            std::env::temp_dir().join(format!("trades-{}-{}.jsonl", std::process::id(), line!()));
+        let _ = std::fs::remove_file(&path);
+        let mut sink = JsonLinesSink::open(&path).unwrap();
+        let share = |maker_seq, qty| crate::engine::MakerShare {
+            maker_seq,
+            qty,
+            ..Default::default()
+        };
+        sink.write(&Trade {
+            trade_id: 1,
+            qty: 3,
+            coalesced_fills: 2,
+            makers: vec![share(4, 1), share(5, 2)],
+            ..Default::default()
+        })
+        .unwrap();
+        sink.write(&Trade {
+            trade_id: 2,
+            ..Default::default()
+        })
+        .unwrap();
+        sink.flush().unwrap();
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<serde_json::Value> = text
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines[0]["coalesced_fills"], 2);
+        assert_eq!(lines[0]["makers"][1]["maker_seq"], 5);
+        assert_eq!(lines[0]["makers"][1]["qty"], 2);
+        assert_eq!(lines[1]["coalesced_fills"], 0);
+        assert!(lines[1].get("makers").is_none());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    /// Reports each trade it starts writing, then waits for the test to let it finish.
+    struct GatedSink {
+        started: std_mpsc::Sender<u64>,
+        gate: std_mpsc::Receiver<()>,
+    }
+
+    impl TradeSink for GatedSink {
+        fn write(&mut self, t: &Trade) -> io::Result<()> {
+            self.started.send(t.trade_id).unwrap();
+            self.gate.recv().unwrap();
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn full_queue_drops_instead_of_blocking() {
+        let (started_tx, started) = std_mpsc::channel();
+        let (gate, gate_rx) = std_mpsc::channel();
+        let publisher = ChannelPublisher::spawn(
+            1,
+            GatedSink {
+                started: started_tx,
+                gate: gate_rx,
+            },
+        )
+        .unwrap();
+        let trade = |trade_id| Trade {
+            trade_id,
+            ..Default::default()
+        };
+
+        publisher.publish(&trade(1));
+        assert_eq!(started.recv_timeout(Duration::from_secs(5)).unwrap(), 1);
+        publisher.publish(&trade(2)); // queued
+        publisher.publish(&trade(3)); // queue full: dropped, returns immediately
+        assert_eq!(publisher.dropped.load(Ordering::Relaxed), 1);
+
+        gate.send(()).unwrap();
+        assert_eq!(started.recv_timeout(Duration::from_secs(5)).unwrap(), 2);
+        gate.send(()).unwrap();
+        assert!(started.recv_timeout(Duration::from_millis(50)).is_err());
+    }
+}
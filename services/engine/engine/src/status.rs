@@ -0,0 +1,69 @@
+use prost::Message;
+use tonic::{codegen::Bytes, Code, Status};
+
+use crate::engine::FieldViolation;
+
+const FIELD_VIOLATION_TYPE_URL: &str = "type.googleapis.com/engine.v1.FieldViolation";
+
+/// Wire-compatible with `google.rpc.Status`, which is what gRPC clients decode from
+/// `grpc-status-details-bin` (tonic 0.11 has no matching tonic-types to build it for us).
+#[derive(Clone, PartialEq, Message)]
+pub struct RpcStatus {
+    #[prost(int32, tag = "1")]
+    pub code: i32,
+    #[prost(string, tag = "2")]
+    pub message: String,
+    #[prost(message, repeated, tag = "3")]
+    pub details: Vec<prost_types::Any>,
+}
+
+/// INVALID_ARGUMENT with the usual "<field> <constraint>" message plus a machine-readable
+/// `FieldViolation` detail, so clients don't have to parse the string.
+pub fn invalid_field(field: &str, value: impl ToString, constraint: &str) -> Status {
+    let message = format!("{field} {constraint}");
+    let violation = FieldViolation {
+        field: field.to_string(),
+        value: value.to_string(),
+        constraint: constraint.to_string(),
+    };
+    let details = RpcStatus {
+        code: Code::InvalidArgument as i32,
+        message: message.clone(),
+        details: vec![prost_types::Any {
+            type_url: FIELD_VIOLATION_TYPE_URL.to_string(),
+            value: violation.encode_to_vec(),
+        }],
+    };
+    Status::with_details(
+        Code::InvalidArgument,
+        message,
+        Bytes::from(details.encode_to_vec()),
+    )
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// What a client does: decode google.rpc.Status and pick out our detail by type URL.
+    pub(crate) fn field_violation(status: &Status) -> Option<FieldViolation> {
+        let rpc = RpcStatus::decode(status.details()).ok()?;
+        rpc.details
+            .iter()
+            .find(|d| d.type_url == FIELD_VIOLATION_TYPE_URL)
+            .and_then(|d| FieldViolation::decode(d.value.as_slice()).ok())
+    }
+
+    #[test]
+    fn detail_round_trips_and_message_is_unchanged() {
+        let status = invalid_field("qty", 0, "must be > 0");
+        assert_eq!(status.code(), Code::InvalidArgument);
+        assert_eq!(status.message(), "qty must be > 0");
+
+        let v = field_violation(&status).unwrap();
+        assert_eq!(
+            (v.field.as_str(), v.value.as_str(), v.constraint.as_str()),
+            ("qty", "0", "must be > 0")
+        );
+    }
+}
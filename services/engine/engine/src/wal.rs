@@ -1,31 +1,177 @@
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs::{self, OpenOptions};
 use std::io::{self, BufRead, BufReader, Read, Write};
+use std::ops::Bound;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
+use crate::config::SymbolConfig;
 use crate::order_book::{Order, OrderBook, RestingOrder, Side as BookSide};
-use crate::EngineState;
+use crate::{EngineState, SymbolHalt};
 
-/// One WAL line = one accepted order.
+/// What a WAL line records.
+/// Lines written before cancels existed carry no `kind` and are orders.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum WalKind {
+    #[default]
+    Order,
+    Cancel,
+    Halt,
+    Resume,
+    // Operational order-entry toggle, independent of halts.
+    #[serde(rename = "DISABLE_ENTRY")]
+    DisableEntry,
+    #[serde(rename = "ENABLE_ENTRY")]
+    EnableEntry,
+    // Verification marker, never applied: `state_checksum` as of `seq`.
+    Checkpoint,
+    // Per-symbol layout, engine-wide WAL only: a seq issued to some symbol's WAL.
+    #[serde(rename = "SEQ_MARK")]
+    SeqMark,
+}
+
+/// One WAL line = one sequenced engine event (accepted order, cancel, halt or resume).
 /// Stored as JSONL (one JSON object per line).
-#[derive(Debug, Clone, Serialize, Deserialize)]
+///
+/// A CANCEL consumes its own seq (so it is never skipped as "covered by snapshot")
+/// and echoes the removed order's side/price/remaining qty/client_order_id.
+/// HALT/RESUME also consume a seq; HALT stores the tripping trade price in `price`.
+/// DISABLE_ENTRY/ENABLE_ENTRY consume a seq and carry only the symbol.
+/// CHECKPOINT does not consume a seq: it repeats the last one and carries the state checksum
+/// at that point, which replay recomputes and compares.
+/// SEQ_MARK does not consume a seq either: logged engine-wide just before a symbol's entry, it
+/// keeps replay from resuming below that seq even if the symbol's directory is gone.
+///
+/// In external seq mode the seq space is upstream's, so CANCEL, HALT, RESUME and the entry
+/// toggles repeat the last seq and take the next `engine_seq` instead. Replay orders entries
+/// by (seq, engine_seq).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct WalEntry {
+    #[serde(default)]
+    pub kind: WalKind,
     pub seq: u64,
+    // External seq mode, engine-originated entries only: position after `seq` (absent = 0).
+    #[serde(default, skip_serializing_if = "is_zero_u64")]
+    pub engine_seq: u64,
     pub symbol: String,
     pub side: String, // "BUY" | "SELL"
     pub price: i64,
     pub qty: i64,
     pub client_order_id: String,
+    // CANCEL only: seq of the resting order being removed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_seq: Option<u64>,
+    // HALT/RESUME only: wall-clock time of the transition.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ts_ms: Option<i64>,
+    // HALT only: when the symbol auto-resumes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resume_at_ms: Option<i64>,
+    // ORDER only: the symbol's qty_scale when accepted (absent = 0). Replay rejects a mismatch.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub qty_scale: u32,
+    // ORDER only: the order's level cap (absent = none), so replay cuts the sweep where live did.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub max_levels: u32,
+    // ORDER only: accepted at a negative price under allow_negative_price (absent = false), so
+    // replay admits it even if the symbol's config has changed since.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub allow_negative_price: bool,
+    // CHECKPOINT only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<u64>,
+    // Fields set on this entry that change how it replays (see `with_must_understand`). A reader
+    // that doesn't know one fails even under ENGINE_DECODE=lenient.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub must_understand: Vec<String>,
+}
+
+impl WalEntry {
+    /// List the meaning-changing fields this entry sets, so no reader skips them as additive.
+    /// A new field that alters replay (not just adds to it) belongs here too.
+    pub fn with_must_understand(mut self) -> Self {
+        let fields = [
+            ("qty_scale", self.qty_scale != 0),
+            ("max_levels", self.max_levels != 0),
+            ("allow_negative_price", self.allow_negative_price),
+            ("engine_seq", self.engine_seq != 0),
+        ];
+        self.must_understand = fields
+            .iter()
+            .filter(|(_, set)| *set)
+            .map(|(f, _)| f.to_string())
+            .collect();
+        self
+    }
+}
+
+fn is_zero(v: &u32) -> bool {
+    *v == 0
 }
 
+fn is_zero_u64(v: &u64) -> bool {
+    *v == 0
+}
+
+fn is_false(v: &bool) -> bool {
+    !*v
+}
+
+/// Whether `symbol` can name its directory in the per-symbol layout: ASCII letters, digits and
+/// `._-`, other than `.` and `..`.
+pub fn symbol_names_a_dir(symbol: &str) -> bool {
+    !symbol.is_empty()
+        && symbol != "."
+        && symbol != ".."
+        && symbol
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "._-".contains(c))
+}
+
+/// Current snapshot format version.
+/// - 1 (or absent): resting orders as `Order`, `qty` = remaining.
+/// - 2: each order also carries `orig_qty`.
+pub const SNAPSHOT_VERSION: u32 = 2;
+
 /// Snapshot stores full engine state at a point in time.
 /// We keep it simple: seq + per-symbol list of resting orders.
 /// NOTE: Snapshot is only about resting book state. Matching during replay is fine
 /// because we replay WAL entries *after* snapshot seq.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Snapshot {
+    #[serde(default = "Snapshot::legacy_version")]
+    pub version: u32,
     pub seq: u64,
+    // Engine-originated entries covered after `seq` (external seq mode; absent = 0).
+    #[serde(default, skip_serializing_if = "is_zero_u64")]
+    pub engine_seq: u64,
     pub books: Vec<SnapshotBook>,
+    // `state_checksum` at write time. Absent in snapshots written before checksums existed.
+    #[serde(default)]
+    pub checksum: Option<u64>,
+    // Symbols halted at snapshot time, sorted by symbol.
+    #[serde(default)]
+    pub halts: Vec<SnapshotHalt>,
+    // Symbols not accepting new orders at snapshot time, sorted.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub entry_disabled: Vec<String>,
+}
+
+impl Snapshot {
+    fn legacy_version() -> u32 {
+        1
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotHalt {
+    pub symbol: String,
+    pub since_ms: i64,
+    pub resume_at_ms: i64,
+    pub trigger_price: i64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -33,25 +179,514 @@ pub struct SnapshotBook {
     pub symbol: String,
     // Snapshot stores RESTING orders in FIFO order grouped by price-level in OrderBook.
     // We serialize as `Order` for compatibility, where `qty` represents remaining qty at snapshot time.
-    pub bids: Vec<Order>,
-    pub asks: Vec<Order>,
+    pub bids: Vec<SnapshotOrder>,
+    pub asks: Vec<SnapshotOrder>,
+    // Symbol's qty_scale at snapshot time (absent = 0).
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub qty_scale: u32,
+    // The book admits negative prices (absent = false): set by config or by a replayed order
+    // that was accepted under it, and kept so its resting orders restore under any config.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub allow_negative_price: bool,
 }
 
-/// Startup / restore observability stats.
-#[derive(Debug, Clone)]
+/// `Order`'s fields (so v1 readers still parse it) plus the v2 `orig_qty`. Spelled out rather
+/// than `#[serde(flatten)]`, which would hide unknown order fields from `DecodeMode::Strict`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotOrder {
+    pub seq: u64,
+    pub side: BookSide,
+    pub price: i64,
+    // Remaining qty at snapshot time.
+    pub qty: i64,
+    pub client_order_id: String,
+    // Absent in v1 snapshots: restored as the remaining qty.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub orig_qty: Option<i64>,
+}
+
+impl From<&RestingOrder> for SnapshotOrder {
+    // Snapshot serializes as `Order` for compatibility; `qty` stores remaining qty.
+    fn from(ro: &RestingOrder) -> Self {
+        Self {
+            seq: ro.seq,
+            side: ro.side,
+            price: ro.price,
+            qty: ro.remaining_qty,
+            client_order_id: ro.client_order_id.clone(),
+            orig_qty: Some(ro.orig_qty),
+        }
+    }
+}
+
+impl From<SnapshotOrder> for RestingOrder {
+    fn from(o: SnapshotOrder) -> Self {
+        Self {
+            seq: o.seq,
+            side: o.side,
+            price: o.price,
+            remaining_qty: o.qty,
+            orig_qty: o.orig_qty.unwrap_or(o.qty),
+            client_order_id: o.client_order_id,
+        }
+    }
+}
+
+/// Full-state debug export (`DumpState`), read in steps so neither a lock hold nor a message
+/// grows with the book: the header, then pages of at most `max_orders` resting orders per book
+/// (bids then asks, ascending price, FIFO), then trade-tape cursors and a footer. Each `next`
+/// runs under its own state lock acquisition and the chunks are serialized after it is released.
+/// Matching carries on between steps, so the dump is one instant only if the footer's seq is
+/// the header's.
+#[derive(Debug, Default)]
+pub struct StateDump {
+    // None until the header step has listed the symbols.
+    symbols: Option<Vec<String>>,
+    book: usize,
+    page: BookCursor,
+    finished: bool,
+}
+
+/// Where the next page of one book starts.
+#[derive(Debug, Default)]
+struct BookCursor {
+    asks: bool,
+    // (price, seq) of the last order paged out on this side. Seqs rise along a level's queue,
+    // so the position survives orders filling or cancelling between pages.
+    after: Option<(i64, u64)>,
+    // Pages emitted for this book; an empty book still gets one.
+    pages: usize,
+}
+
+/// One `DumpState` document, serialized once the lock is released.
+#[derive(Debug)]
+pub enum DumpChunk {
+    Header(DumpHeader),
+    Book(SnapshotBook),
+    TradeCursor(DumpTradeCursor),
+    Footer(DumpFooter),
+}
+
+#[derive(Debug, Serialize)]
+pub struct DumpHeader {
+    pub seq: u64,
+    pub checksum: Option<u64>,
+    pub next_trade_id: u64,
+    pub halts: Vec<SnapshotHalt>,
+    pub entry_disabled: Vec<String>,
+    pub config: BTreeMap<String, SymbolConfig>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DumpTradeCursor {
+    pub symbol: String,
+    pub buffered: usize,
+    pub oldest_trade_id: u64,
+    pub newest_trade_id: u64,
+    pub evicted_through_trade_id: u64,
+}
+
+/// State as of the last step; equal to the header's when nothing moved during the dump.
+#[derive(Debug, Serialize)]
+pub struct DumpFooter {
+    pub seq: u64,
+    pub checksum: u64,
+}
+
+impl StateDump {
+    /// The next step's chunks, empty once the footer has been returned. Call under the state lock.
+    pub fn next(&mut self, st: &EngineState, max_orders: usize) -> Vec<DumpChunk> {
+        if self.finished {
+            return Vec::new();
+        }
+        let Some(symbols) = &self.symbols else {
+            let mut symbols: Vec<String> = st.books.keys().cloned().collect();
+            symbols.sort();
+            self.symbols = Some(symbols);
+
+            return vec![DumpChunk::Header(DumpHeader {
+                seq: st.seq,
+                checksum: Some(state_checksum(st)),
+                next_trade_id: st.next_trade_id,
+                halts: snapshot_halts(st, |_| true),
+                entry_disabled: st.entry_disabled.iter().cloned().collect(),
+                config: st
+                    .config
+                    .symbols()
+                    .map(|(symbol, cfg)| (symbol.clone(), cfg.clone()))
+                    .collect(),
+            })];
+        };
+
+        while let Some(symbol) = symbols.get(self.book) {
+            if let Some(page) = st
+                .books
+                .get(symbol)
+                .and_then(|b| book_page(st, symbol, b, &mut self.page, max_orders))
+            {
+                return vec![DumpChunk::Book(page)];
+            }
+            self.book += 1;
+            self.page = BookCursor::default();
+        }
+
+        self.finished = true;
+        let mut trade_cursors: Vec<DumpTradeCursor> = st
+            .trades
+            .iter()
+            .map(|(symbol, q)| DumpTradeCursor {
+                symbol: symbol.clone(),
+                buffered: q.len(),
+                oldest_trade_id: q.front().map(|t| t.trade_id).unwrap_or(0),
+                newest_trade_id: q.back().map(|t| t.trade_id).unwrap_or(0),
+                evicted_through_trade_id: st
+                    .trades_evicted_through
+                    .get(symbol)
+                    .copied()
+                    .unwrap_or(0),
+            })
+            .collect();
+        trade_cursors.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+
+        let mut out: Vec<DumpChunk> = trade_cursors
+            .into_iter()
+            .map(DumpChunk::TradeCursor)
+            .collect();
+        out.push(DumpChunk::Footer(DumpFooter {
+            seq: st.seq,
+            checksum: state_checksum(st),
+        }));
+        out
+    }
+}
+
+/// Up to `max_orders` of `book`'s orders after the cursor, or None once it has been paged out.
+fn book_page(
+    st: &EngineState,
+    symbol: &str,
+    book: &OrderBook,
+    cursor: &mut BookCursor,
+    max_orders: usize,
+) -> Option<SnapshotBook> {
+    let mut page = SnapshotBook {
+        symbol: symbol.to_string(),
+        bids: Vec::new(),
+        asks: Vec::new(),
+        qty_scale: st.config.symbol(symbol).qty_scale,
+        allow_negative_price: book.allow_negative_price,
+    };
+
+    let mut n = 0;
+    loop {
+        let (levels, out) = if cursor.asks {
+            (&book.asks, &mut page.asks)
+        } else {
+            (&book.bids, &mut page.bids)
+        };
+        let after = cursor.after;
+        let from = after.map_or(Bound::Unbounded, |(price, _)| Bound::Included(price));
+        let rest = levels
+            .range((from, Bound::Unbounded))
+            .flat_map(|(_, q)| q.iter())
+            .filter(|ro| after.is_none_or(|(price, seq)| ro.price != price || ro.seq > seq));
+        for ro in rest {
+            if n == max_orders {
+                break;
+            }
+            out.push(SnapshotOrder::from(ro));
+            cursor.after = Some((ro.price, ro.seq));
+            n += 1;
+        }
+        if n == max_orders || cursor.asks {
+            break;
+        }
+        cursor.asks = true;
+        cursor.after = None;
+    }
+
+    if n == 0 && cursor.pages > 0 {
+        return None;
+    }
+    cursor.pages += 1;
+    Some(page)
+}
+
+impl DumpChunk {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            DumpChunk::Header(_) => "header",
+            DumpChunk::Book(_) => "book",
+            DumpChunk::TradeCursor(_) => "trade_cursor",
+            DumpChunk::Footer(_) => "footer",
+        }
+    }
+
+    /// Pretty JSON of the document.
+    pub fn to_json(&self) -> io::Result<String> {
+        let json = match self {
+            DumpChunk::Header(v) => serde_json::to_string_pretty(v),
+            DumpChunk::Book(v) => serde_json::to_string_pretty(v),
+            DumpChunk::TradeCursor(v) => serde_json::to_string_pretty(v),
+            DumpChunk::Footer(v) => serde_json::to_string_pretty(v),
+        };
+        json.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+/// Startup / restore observability stats. In the per-symbol layout each field is summed (or
+/// maxed for `snapshot_seq`, min'd for `wal_after_seq`) over the engine-wide files and every symbol's.
+#[derive(Debug, Clone, Default)]
 pub struct RestoreStats {
     pub snapshot_present: bool,
     pub snapshot_seq: u64,
     pub snapshot_books: usize,
     pub snapshot_orders: usize,
+    pub snapshot_checksum_verified: bool,
     pub wal_replayed: usize,
     pub wal_after_seq: u64,
+    // Archived segments (keep mode) read vs. skipped by file name as covered by the snapshot.
+    pub wal_segments_replayed: usize,
+    pub wal_segments_skipped: usize,
+    // Bytes of an unparseable, unterminated final line that were skipped (0 = clean tail).
+    // Replay leaves them in the file; `repair_tail` cuts them at server startup.
+    pub wal_torn_tail_bytes: u64,
+    // CHECKPOINT entries whose checksum matched the replayed state (not counted in wal_replayed).
+    pub wal_checkpoints_verified: usize,
+    // Highest seq found in the WAL files read, applied or skipped (0 = none).
+    pub wal_max_seq: u64,
+    // Per-symbol layout only: symbol directories restored.
+    pub symbol_wals: usize,
+}
+
+impl RestoreStats {
+    /// Fold one symbol's restore into the running total (per-symbol layout).
+    fn absorb(&mut self, s: RestoreStats) {
+        if s.snapshot_present {
+            self.snapshot_checksum_verified = s.snapshot_checksum_verified
+                && (self.snapshot_checksum_verified || !self.snapshot_present);
+            self.snapshot_present = true;
+        }
+        self.snapshot_seq = self.snapshot_seq.max(s.snapshot_seq);
+        self.snapshot_books += s.snapshot_books;
+        self.snapshot_orders += s.snapshot_orders;
+        self.wal_replayed += s.wal_replayed;
+        self.wal_after_seq = self.wal_after_seq.min(s.wal_after_seq);
+        self.wal_segments_replayed += s.wal_segments_replayed;
+        self.wal_segments_skipped += s.wal_segments_skipped;
+        self.wal_torn_tail_bytes += s.wal_torn_tail_bytes;
+        self.wal_checkpoints_verified += s.wal_checkpoints_verified;
+        self.wal_max_seq = self.wal_max_seq.max(s.wal_max_seq);
+        self.symbol_wals += 1;
+    }
+}
+
+/// What `repair_tail` did to the active WAL file(s).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TailRepair {
+    // Bytes of unparseable, unterminated final lines cut off (never acknowledged).
+    pub cut_bytes: u64,
+    // Complete final entries that had only lost their newline.
+    pub newlines_added: usize,
+}
+
+/// What replaying one WAL file did.
+struct FileReplay {
+    applied: usize,
+    torn_tail_bytes: u64,
+    checkpoints_verified: usize,
+    // Highest seq in the file, covered by the snapshot or not (0 = no entries).
+    max_seq: u64,
+}
+
+/// What restore does when the WAL holds entries but none reach the snapshot's seq. After a
+/// snapshot the WAL is retired, or (if that failed) still ends exactly at the snapshot seq, so a
+/// WAL that stops short of it is stale or belongs to a different snapshot.
+///
+/// Per-symbol layout: only the engine-wide WAL is checked, whose SEQ_MARKs reach every seq
+/// issued. A symbol's snapshot carries the global seq, which its own WAL need never reach.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SeqMismatchPolicy {
+    /// Fail the restore, naming both seqs.
+    Strict,
+    /// Use the snapshot and skip the WAL, as before this check existed.
+    TrustSnapshot,
+    /// As TrustSnapshot, but log both seqs (default).
+    #[default]
+    Warn,
+}
+
+/// How snapshot and WAL readers treat JSON fields this build doesn't know.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DecodeMode {
+    /// Unknown fields fail the restore (default): unexpected keys are more likely corruption
+    /// than a newer writer.
+    #[default]
+    Strict,
+    /// Skip unknown fields, logging each distinct one, so a rolled-back binary can start on data
+    /// a newer build wrote. Only covers additive fields: an unknown entry kind, a field the entry
+    /// lists in `must_understand` or a newer snapshot version still fails, since those change
+    /// meaning rather than add to it.
+    Lenient,
+}
+
+impl DecodeMode {
+    /// Apply the mode to the unknown field paths found while parsing `what`.
+    fn check(self, what: &str, unknown: &BTreeSet<String>) -> io::Result<()> {
+        if unknown.is_empty() {
+            return Ok(());
+        }
+        let fields = unknown.iter().cloned().collect::<Vec<_>>().join(", ");
+        match self {
+            DecodeMode::Strict => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{what}: unknown field(s) {fields} (written by a newer build? ENGINE_DECODE=lenient skips additive fields)"
+                ),
+            )),
+            DecodeMode::Lenient => {
+                tracing::warn!(fields = %fields, what = %what, "lenient decode: ignoring unknown fields");
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Parse JSON, adding the path of every field the target type doesn't know to `unknown`
+/// (e.g. "books.0.bids.1.venue").
+fn from_json_tracking_unknown<T: DeserializeOwned>(
+    json: &[u8],
+    unknown: &mut BTreeSet<String>,
+) -> serde_json::Result<T> {
+    let mut de = serde_json::Deserializer::from_slice(json);
+    let value = serde_ignored::deserialize(&mut de, |path| {
+        unknown.insert(path.to_string());
+    })?;
+    de.end()?;
+    Ok(value)
+}
+
+/// What happens to the WAL once a snapshot covers it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WalRetention {
+    /// Empty the WAL (default): the snapshot is the only history kept.
+    #[default]
+    Truncate,
+    /// Rename the WAL to an archive segment `<wal>.<last seq, 20 digits>` and start a fresh one,
+    /// so the full order flow is retained. Replay skips segments the snapshot covers by name alone.
+    Keep,
+}
+
+/// Where WAL entries and snapshots are kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WalLayout {
+    /// One WAL and one snapshot for every symbol (default).
+    #[default]
+    Single,
+    /// `<wal dir>/symbols/<symbol>/{wal.jsonl,snapshot.json}`, so a corrupt file only blocks its
+    /// own symbol, which can then be inspected, restored or reset alone. Seqs stay engine-wide;
+    /// the configured WAL and snapshot keep anything without a symbol and the last seq as a floor.
+    PerSymbol,
+}
+
+/// How strictly `write_snapshot` must replace the previous snapshot.
+///
+/// Both write `<snapshot>.tmp`, fsync it, rename it over the snapshot and fsync the directory
+/// so the rename itself is durable. They differ where the platform can't do that:
+/// - Strict: a failed rename or directory fsync fails the snapshot (and so keeps the WAL).
+/// - BestEffort: if rename-over-existing is refused, move the old snapshot to `<snapshot>.bak`
+///   first (restore falls back to it if a crash lands in between); a directory fsync that
+///   isn't supported is logged and skipped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotAtomicity {
+    Strict,
+    BestEffort,
+}
+
+impl Default for SnapshotAtomicity {
+    /// Strict where rename and directory fsync are POSIX.
+    fn default() -> Self {
+        if cfg!(unix) {
+            Self::Strict
+        } else {
+            Self::BestEffort
+        }
+    }
+}
+
+/// How the snapshot was put in place, for callers to report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotReplace {
+    /// One rename over the old snapshot.
+    AtomicRename,
+    /// Old snapshot moved to `.bak`, then the new one renamed in (BestEffort fallback).
+    ViaBackup,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SnapshotWrite {
+    pub bytes: u64,
+    pub replace: SnapshotReplace,
+    // False only in BestEffort mode when the directory fsync failed or isn't supported.
+    pub dir_synced: bool,
+}
+
+/// The filesystem calls `write_snapshot` makes, so platform quirks and crashes can be injected.
+pub trait SnapshotFs: std::fmt::Debug + Send + Sync {
+    /// Create/truncate `path`, write `bytes` and fsync the file.
+    fn write_synced(&self, path: &Path, bytes: &[u8]) -> io::Result<()>;
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+    fn remove_file(&self, path: &Path) -> io::Result<()>;
+    /// fsync a directory so renames within it survive a crash.
+    fn sync_dir(&self, dir: &Path) -> io::Result<()>;
+}
+
+/// The real filesystem.
+#[derive(Debug, Default)]
+pub struct StdFs;
+
+impl SnapshotFs for StdFs {
+    fn write_synced(&self, path: &Path, bytes: &[u8]) -> io::Result<()> {
+        let mut f = OpenOptions::new()
+            .create(true)
+            .truncate(true)
+            .write(true)
+            .open(path)?;
+        f.write_all(bytes)?;
+        f.sync_all()
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        fs::rename(from, to)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        fs::remove_file(path)
+    }
+
+    #[cfg(unix)]
+    fn sync_dir(&self, dir: &Path) -> io::Result<()> {
+        fs::File::open(dir)?.sync_all()
+    }
+
+    #[cfg(not(unix))]
+    fn sync_dir(&self, _dir: &Path) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "directory fsync not supported",
+        ))
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Wal {
     path: PathBuf,
     snapshot_path: PathBuf,
+    retention: WalRetention,
+    atomicity: SnapshotAtomicity,
+    decode: DecodeMode,
+    layout: WalLayout,
+    seq_mismatch: SeqMismatchPolicy,
+    fs: Arc<dyn SnapshotFs>,
 }
 
 impl Wal {
@@ -76,7 +711,249 @@ impl Wal {
             .map(|p| p.join("snapshot.json"))
             .unwrap_or_else(|| PathBuf::from("snapshot.json"));
 
-        Self { path, snapshot_path }
+        Self {
+            path,
+            snapshot_path,
+            retention: WalRetention::default(),
+            atomicity: SnapshotAtomicity::default(),
+            decode: DecodeMode::default(),
+            layout: WalLayout::default(),
+            seq_mismatch: SeqMismatchPolicy::default(),
+            fs: Arc::new(StdFs),
+        }
+    }
+
+    /// WAL and snapshot in independent locations (e.g. WAL on local disk, snapshot on durable storage).
+    pub fn with_snapshot_path<P: AsRef<Path>, S: AsRef<Path>>(path: P, snapshot_path: S) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            snapshot_path: snapshot_path.as_ref().to_path_buf(),
+            retention: WalRetention::default(),
+            atomicity: SnapshotAtomicity::default(),
+            decode: DecodeMode::default(),
+            layout: WalLayout::default(),
+            seq_mismatch: SeqMismatchPolicy::default(),
+            fs: Arc::new(StdFs),
+        }
+    }
+
+    pub fn with_snapshot_atomicity(mut self, atomicity: SnapshotAtomicity) -> Self {
+        self.atomicity = atomicity;
+        self
+    }
+
+    /// Swap the filesystem `write_snapshot` uses (tests inject platform quirks and crashes).
+    #[cfg(test)]
+    pub fn with_snapshot_fs(mut self, fs: Arc<dyn SnapshotFs>) -> Self {
+        self.fs = fs;
+        self
+    }
+
+    pub fn snapshot_atomicity(&self) -> SnapshotAtomicity {
+        self.atomicity
+    }
+
+    pub fn with_retention(mut self, retention: WalRetention) -> Self {
+        self.retention = retention;
+        self
+    }
+
+    pub fn retention(&self) -> WalRetention {
+        self.retention
+    }
+
+    pub fn with_decode_mode(mut self, decode: DecodeMode) -> Self {
+        self.decode = decode;
+        self
+    }
+
+    pub fn with_seq_mismatch(mut self, policy: SeqMismatchPolicy) -> Self {
+        self.seq_mismatch = policy;
+        self
+    }
+
+    pub fn with_layout(mut self, layout: WalLayout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    pub fn layout(&self) -> WalLayout {
+        self.layout
+    }
+
+    /// Per-symbol layout root: `symbols/` next to the WAL.
+    pub fn symbols_dir(&self) -> PathBuf {
+        match self.path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.join("symbols"),
+            _ => PathBuf::from("symbols"),
+        }
+    }
+
+    /// The single-file WAL holding `symbol`'s entries and snapshot in the per-symbol layout.
+    /// Symbols become directory names, so only `[A-Za-z0-9._-]` (and not `.`/`..`) are allowed.
+    pub fn symbol_wal(&self, symbol: &str) -> io::Result<Wal> {
+        if !symbol_names_a_dir(symbol) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("symbol '{}' can't name a per-symbol WAL directory", symbol),
+            ));
+        }
+        let dir = self.symbols_dir().join(symbol);
+        Ok(Wal {
+            path: dir.join("wal.jsonl"),
+            snapshot_path: dir.join("snapshot.json"),
+            layout: WalLayout::Single,
+            ..self.clone()
+        })
+    }
+
+    /// Symbols with a directory under `symbols_dir`, sorted (the per-symbol replay order).
+    fn symbol_dirs(&self) -> io::Result<Vec<String>> {
+        let entries = match fs::read_dir(self.symbols_dir()) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let mut symbols = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                if let Some(name) = entry.file_name().to_str() {
+                    symbols.push(name.to_string());
+                }
+            }
+        }
+        symbols.sort();
+        Ok(symbols)
+    }
+
+    /// Drop (Truncate) or archive (Keep) the WAL after a snapshot through `through_seq` (and
+    /// `through_engine_seq`) is in place.
+    /// Must run under the state lock, like `append`, so no entry lands between the two.
+    /// In the per-symbol layout every symbol's WAL is retired too.
+    pub fn retire_wal(&self, through_seq: u64, through_engine_seq: u64) -> io::Result<()> {
+        if self.layout == WalLayout::PerSymbol {
+            for symbol in self.symbol_dirs()? {
+                self.symbol_wal(&symbol)?
+                    .retire_wal(through_seq, through_engine_seq)?;
+            }
+        }
+        match self.retention {
+            WalRetention::Truncate => self.truncate_wal(),
+            WalRetention::Keep => self.rotate_wal(through_seq, through_engine_seq).map(|_| ()),
+        }
+    }
+
+    /// Rename the active WAL to its archive segment; the next append starts a new file.
+    /// Returns the segment path, or None if there was nothing to archive.
+    pub fn rotate_wal(
+        &self,
+        through_seq: u64,
+        through_engine_seq: u64,
+    ) -> io::Result<Option<PathBuf>> {
+        match fs::metadata(&self.path) {
+            Ok(m) if m.len() > 0 => {}
+            Ok(_) => return Ok(None),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        }
+
+        let segment = self.segment_path(through_seq, through_engine_seq);
+        if segment.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("WAL segment {} already exists", segment.display()),
+            ));
+        }
+        fs::rename(&self.path, &segment)?;
+        Ok(Some(segment))
+    }
+
+    /// `<wal>.<seq>`, or `<wal>.<seq>.<engine_seq>` when engine-originated entries followed the
+    /// seq (external seq mode), so two snapshots at one seq don't collide. Both 20 digits.
+    fn segment_path(&self, through_seq: u64, through_engine_seq: u64) -> PathBuf {
+        let mut name = self.path.as_os_str().to_owned();
+        name.push(format!(".{:020}", through_seq));
+        if through_engine_seq > 0 {
+            name.push(format!(".{:020}", through_engine_seq));
+        }
+        PathBuf::from(name)
+    }
+
+    /// Archived segments next to the WAL as (last seq, path), oldest first. A segment's entries
+    /// never pass the snapshot it was retired at, so the seq alone says whether one is covered.
+    pub fn archived_segments(&self) -> io::Result<Vec<(u64, PathBuf)>> {
+        let (Some(dir), Some(stem)) = (self.path.parent(), self.path.file_name()) else {
+            return Ok(Vec::new());
+        };
+        let dir = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+        let prefix = format!("{}.", stem.to_string_lossy());
+
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+
+        let mut segments = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            let name = entry.file_name();
+            let seq = name
+                .to_str()
+                .and_then(|n| n.strip_prefix(&prefix))
+                .filter(|s| {
+                    s.len() == 20
+                        || (s.len() == 41
+                            && s.as_bytes()[20] == b'.'
+                            && s[21..].bytes().all(|b| b.is_ascii_digit()))
+                })
+                .and_then(|s| s[..20].parse::<u64>().ok());
+            if let Some(seq) = seq {
+                segments.push((seq, entry.path()));
+            }
+        }
+        segments.sort();
+        Ok(segments)
+    }
+
+    /// Create both parent directories and prove the snapshot location is writable,
+    /// so a bad path fails at startup instead of at the first snapshot.
+    pub fn prepare_dirs(&self) -> io::Result<()> {
+        self.ensure_parent_dir()?;
+        self.ensure_snapshot_parent_dir()?;
+        if self.layout == WalLayout::PerSymbol {
+            fs::create_dir_all(self.symbols_dir())?;
+        }
+
+        if self.snapshot_path.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "snapshot path {} is a directory",
+                    self.snapshot_path.display()
+                ),
+            ));
+        }
+
+        // Same temp file write_snapshot uses, so this exercises the real write path.
+        let probe = self.snapshot_path.with_extension("json.tmp");
+        OpenOptions::new()
+            .create(true)
+            .truncate(true)
+            .write(true)
+            .open(&probe)
+            .map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!("snapshot dir not writable ({}): {}", probe.display(), e),
+                )
+            })?;
+        fs::remove_file(&probe)
     }
 
     fn ensure_parent_dir_for(path: &Path) -> io::Result<()> {
@@ -96,8 +973,19 @@ impl Wal {
         Self::ensure_parent_dir_for(&self.snapshot_path)
     }
 
-    /// Append one entry as JSONL.
+    /// Append one entry as JSONL (to its symbol's WAL in the per-symbol layout).
     pub fn append(&self, entry: &WalEntry) -> io::Result<()> {
+        if self.layout == WalLayout::PerSymbol && !entry.symbol.is_empty() {
+            let symbol_wal = self.symbol_wal(&entry.symbol)?;
+            // Mark first: a seq the symbol WAL never got only leaves a gap, never a reissue.
+            self.append(&WalEntry {
+                kind: WalKind::SeqMark,
+                seq: entry.seq,
+                engine_seq: entry.engine_seq,
+                ..Default::default()
+            })?;
+            return symbol_wal.append(entry);
+        }
         self.ensure_parent_dir()?;
 
         let mut f = OpenOptions::new()
@@ -108,167 +996,634 @@ impl Wal {
         let line = serde_json::to_string(entry)
             .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
 
-        f.write_all(line.as_bytes())?;
-        f.write_all(b"\n")?;
-        f.flush()?;
+        let start = f.metadata()?.len();
+        let written = (|| {
+            f.write_all(line.as_bytes())?;
+            f.write_all(b"\n")?;
+            f.flush()
+        })();
+
+        if let Err(e) = written {
+            // Best effort: cut any partial line so a later append doesn't land after a fragment.
+            let _ = f.set_len(start);
+            return Err(e);
+        }
         Ok(())
     }
 
-    /// Write a full snapshot of the current EngineState.
-    /// This is atomic-ish: write temp file then rename.
-    pub fn write_snapshot(&self, st: &EngineState) -> io::Result<()> {
-        self.ensure_snapshot_parent_dir()?;
+    /// Write a full snapshot of the current EngineState; returns its size in bytes, how the old
+    /// file was replaced and whether the directory was fsynced.
+    /// This is atomic-ish: write temp file then rename. On failure the previous snapshot is untouched.
+    ///
+    /// Per-symbol layout: one snapshot per symbol, then the engine-wide one (seq only) last.
+    /// Each file is replaced on its own; a crash part-way leaves some symbols on the previous
+    /// snapshot, which is fine since each is replayed against its own WAL. Sizes are summed;
+    /// `dir_synced` holds only if every directory was, and any backup replace is reported.
+    pub fn write_snapshot(&self, st: &EngineState) -> io::Result<SnapshotWrite> {
+        if self.layout == WalLayout::Single {
+            return self.write_snapshot_file(&build_snapshot(st, |_| true));
+        }
 
-        let snap = Snapshot {
-            seq: st.seq,
-            books: st
-                .books
-                .iter()
-                .map(|(symbol, book)| SnapshotBook {
-                    symbol: symbol.clone(),
-                    bids: flatten_side(&book.bids),
-                    asks: flatten_side(&book.asks),
-                })
-                .collect(),
-        };
+        let mut symbols: BTreeSet<&str> = st.books.keys().map(String::as_str).collect();
+        symbols.extend(st.halts.keys().map(String::as_str));
+        symbols.extend(st.entry_disabled.iter().map(String::as_str));
 
-        let json = serde_json::to_vec_pretty(&snap)
+        let mut writes = Vec::new();
+        for symbol in symbols {
+            writes.push(
+                self.symbol_wal(symbol)?
+                    .write_snapshot_file(&build_snapshot(st, |s| s == symbol))?,
+            );
+        }
+        // Last, so until every symbol has its own snapshot the previous engine-wide one still holds them.
+        let mut total = self.write_snapshot_file(&build_snapshot(st, |_| false))?;
+        for w in writes {
+            total.bytes += w.bytes;
+            total.dir_synced &= w.dir_synced;
+            if w.replace == SnapshotReplace::ViaBackup {
+                total.replace = SnapshotReplace::ViaBackup;
+            }
+        }
+        Ok(total)
+    }
+
+    fn write_snapshot_file(&self, snap: &Snapshot) -> io::Result<SnapshotWrite> {
+        self.ensure_snapshot_parent_dir()?;
+
+        let json = serde_json::to_vec_pretty(snap)
             .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
 
         let tmp = self.snapshot_path.with_extension("json.tmp");
+        let mut bytes = json;
+        bytes.push(b'\n');
 
-        {
-            let mut f = OpenOptions::new()
-                .create(true)
-                .truncate(true)
-                .write(true)
-                .open(&tmp)?;
-            f.write_all(&json)?;
-            f.write_all(b"\n")?;
-            f.flush()?;
+        let replaced = self
+            .fs
+            .write_synced(&tmp, &bytes)
+            .and_then(|()| self.replace_snapshot(&tmp));
+        let replace = match replaced {
+            Ok(replace) => replace,
+            Err(e) => {
+                let _ = self.fs.remove_file(&tmp);
+                return Err(e);
+            }
+        };
+
+        // Without this the rename can be lost on power failure even though the data was synced.
+        let dir = match self.snapshot_path.parent() {
+            Some(d) if !d.as_os_str().is_empty() => d,
+            _ => Path::new("."),
+        };
+        let dir_synced = match self.fs.sync_dir(dir) {
+            Ok(()) => true,
+            Err(e) if self.atomicity == SnapshotAtomicity::BestEffort => {
+                tracing::warn!(dir = %dir.display(), error = %e, "snapshot directory fsync skipped");
+                false
+            }
+            Err(e) => return Err(e),
+        };
+
+        Ok(SnapshotWrite {
+            bytes: bytes.len() as u64,
+            replace,
+            dir_synced,
+        })
+    }
+
+    fn backup_snapshot_path(&self) -> PathBuf {
+        self.snapshot_path.with_extension("json.bak")
+    }
+
+    fn replace_snapshot(&self, tmp: &Path) -> io::Result<SnapshotReplace> {
+        let e = match self.fs.rename(tmp, &self.snapshot_path) {
+            Ok(()) => return Ok(SnapshotReplace::AtomicRename),
+            Err(e) => e,
+        };
+        if self.atomicity == SnapshotAtomicity::Strict || !self.snapshot_path.is_file() {
+            return Err(e);
         }
 
-        // Best-effort atomic replace on POSIX
-        fs::rename(tmp, &self.snapshot_path)?;
-        Ok(())
+        // Never a moment without a readable snapshot: the old one is only deleted once the new
+        // one is in place, and `read_snapshot` falls back to the backup in between.
+        let bak = self.backup_snapshot_path();
+        self.fs.rename(&self.snapshot_path, &bak)?;
+        if let Err(e) = self.fs.rename(tmp, &self.snapshot_path) {
+            let _ = self.fs.rename(&bak, &self.snapshot_path);
+            return Err(e);
+        }
+        let _ = self.fs.remove_file(&bak);
+        Ok(SnapshotReplace::ViaBackup)
     }
 
     /// Read snapshot if it exists.
     pub fn read_snapshot(&self) -> io::Result<Option<Snapshot>> {
-        if !self.snapshot_path.exists() {
+        // A BestEffort replace interrupted between its two renames leaves only the backup.
+        let path = if self.snapshot_path.exists() {
+            self.snapshot_path.clone()
+        } else if self.backup_snapshot_path().is_file() {
+            self.backup_snapshot_path()
+        } else {
             return Ok(None);
-        }
+        };
 
-        let f = OpenOptions::new().read(true).open(&self.snapshot_path)?;
+        let f = OpenOptions::new().read(true).open(&path)?;
         let mut reader = BufReader::new(f);
         let mut buf = Vec::new();
         reader.read_to_end(&mut buf)?;
 
-        let snap: Snapshot = serde_json::from_slice(&buf).map_err(|e| {
+        let mut unknown = BTreeSet::new();
+        let snap: Snapshot = from_json_tracking_unknown(&buf, &mut unknown).map_err(|e| {
             io::Error::new(
                 io::ErrorKind::InvalidData,
                 format!("snapshot parse error: {}", e),
             )
         })?;
 
+        if snap.version > SNAPSHOT_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "snapshot version {} is newer than supported version {}",
+                    snap.version, SNAPSHOT_VERSION
+                ),
+            ));
+        }
+        self.decode
+            .check(&format!("snapshot {}", path.display()), &unknown)?;
+
         Ok(Some(snap))
     }
 
-    /// Replay snapshot (if present) + WAL entries after snapshot seq into EngineState.
-    /// Sets st.seq to max seq observed so new orders continue monotonically.
+    /// Rebuild one symbol's book from the snapshot alone (no WAL replay, no `EngineState`).
+    /// For offline analysis and replica comparison. Ok(None) if there is no snapshot or the
+    /// symbol isn't in it. The book gets default symbol settings, except negative prices are
+    /// admitted if the snapshot says the book had them.
+    pub fn restore_symbol(&self, symbol: &str) -> io::Result<Option<OrderBook>> {
+        // Per-symbol layout: its own snapshot, else the engine-wide one (written before the switch).
+        if self.layout == WalLayout::PerSymbol {
+            if let Some(book) = self.symbol_wal(symbol)?.restore_symbol(symbol)? {
+                return Ok(Some(book));
+            }
+        }
+        let Some(snap) = self.read_snapshot()? else {
+            return Ok(None);
+        };
+        Ok(snap
+            .books
+            .into_iter()
+            .find(|b| b.symbol == symbol)
+            .map(|b| {
+                let allow_negative_price = b.allow_negative_price;
+                rebuild_book(b, allow_negative_price).0
+            }))
+    }
+
+    /// Replay snapshot (if present) + WAL entries after snapshot seq into EngineState.
+    /// Sets st.seq to max seq observed so new orders continue monotonically.
+    ///
+    /// If the snapshot carries a checksum, the rebuilt state is verified against it
+    /// before any WAL entry is applied; a mismatch fails the restore.
     ///
     /// Returns restore stats for clean startup logging.
     pub fn replay_into_with_stats(&self, st: &mut EngineState) -> io::Result<RestoreStats> {
+        match self.layout {
+            WalLayout::Single => {
+                // Restoring without them would silently drop every order they hold.
+                if !self.symbol_dirs()?.is_empty() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "per-symbol WAL directories found under {} but the layout is single",
+                            self.symbols_dir().display()
+                        ),
+                    ));
+                }
+                self.replay_files_into(st, apply_snapshot)
+            }
+            WalLayout::PerSymbol => self.replay_per_symbol_into(st),
+        }
+    }
+
+    /// Engine-wide files first (the seq floor, plus anything written before the switch to this
+    /// layout), then each symbol's snapshot and WAL in name order. Symbols share nothing but
+    /// the seq counter, restored as the max over all files, so the order can't change the result.
+    /// A failure names the symbol: the others' files are untouched by it.
+    fn replay_per_symbol_into(&self, st: &mut EngineState) -> io::Result<RestoreStats> {
+        let mut total = self.replay_files_into(st, apply_snapshot)?;
+        for symbol in self.symbol_dirs()? {
+            // The engine-wide check above covers the seq floor (see `SeqMismatchPolicy`).
+            let w = self
+                .symbol_wal(&symbol)?
+                .with_seq_mismatch(SeqMismatchPolicy::TrustSnapshot);
+            let stats = w
+                .replay_files_into(st, |st, snap| apply_symbol_snapshot(st, &symbol, snap))
+                .map_err(|e| {
+                    io::Error::new(
+                        e.kind(),
+                        format!(
+                            "symbol {} ({}): {}; other symbols are unaffected, so moving this directory aside restores them",
+                            symbol,
+                            self.symbols_dir().join(&symbol).display(),
+                            e
+                        ),
+                    )
+                })?;
+            total.absorb(stats);
+        }
+        Ok(total)
+    }
+
+    /// Snapshot (laid over `st` by `apply`) then archived segments and the WAL.
+    fn replay_files_into(
+        &self,
+        st: &mut EngineState,
+        apply: impl FnOnce(&mut EngineState, Snapshot) -> io::Result<(usize, usize)>,
+    ) -> io::Result<RestoreStats> {
         // 1) load snapshot if present
         let mut snapshot_present = false;
         let mut snapshot_seq = 0u64;
+        let mut snapshot_engine_seq = 0u64;
         let mut snapshot_books = 0usize;
         let mut snapshot_orders = 0usize;
+        let mut snapshot_checksum_verified = false;
 
         if let Some(snap) = self.read_snapshot()? {
             snapshot_present = true;
             snapshot_seq = snap.seq;
-            let (b, o) = apply_snapshot(st, snap)?;
+            snapshot_engine_seq = snap.engine_seq;
+            let stored_checksum = snap.checksum;
+            let symbols: BTreeSet<String> = snap.books.iter().map(|b| b.symbol.clone()).collect();
+            let (b, o) = apply(st, snap)?;
             snapshot_books = b;
             snapshot_orders = o;
+
+            // The WAL has not been applied yet, so the snapshot's books must be exactly as stored.
+            if let Some(expected) = stored_checksum {
+                let actual = books_checksum(st, snapshot_seq, |s| symbols.contains(s));
+                if actual != expected {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "snapshot checksum mismatch at seq {}: stored={:016x} rebuilt={:016x} ({})",
+                            snapshot_seq,
+                            expected,
+                            actual,
+                            self.snapshot_path.display()
+                        ),
+                    ));
+                }
+                snapshot_checksum_verified = true;
+            }
         }
 
-        // 2) replay WAL entries after snapshot seq
+        // 2) replay archived segments the snapshot doesn't cover, then the active WAL.
+        // A segment's file name carries its last seq, so covered ones are never opened.
         let wal_after_seq = snapshot_seq;
-        let wal_replayed = self.replay_wal_after_seq_into(st, wal_after_seq)?;
+        let after = (snapshot_seq, snapshot_engine_seq);
+        let mut wal_replayed = 0;
+        let mut wal_segments_replayed = 0;
+        let mut wal_segments_skipped = 0;
+        let mut wal_checkpoints_verified = 0;
+        let mut wal_max_seq = 0;
+        for (through_seq, path) in self.archived_segments()? {
+            if through_seq <= wal_after_seq {
+                wal_segments_skipped += 1;
+                continue;
+            }
+            let r = Self::replay_file_after_seq_into(&path, st, after, self.decode)
+                .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+            wal_replayed += r.applied;
+            wal_checkpoints_verified += r.checkpoints_verified;
+            wal_max_seq = wal_max_seq.max(r.max_seq);
+            wal_segments_replayed += 1;
+        }
+        let r = Self::replay_file_after_seq_into(&self.path, st, after, self.decode)?;
+        wal_replayed += r.applied;
+        wal_checkpoints_verified += r.checkpoints_verified;
+        wal_max_seq = wal_max_seq.max(r.max_seq);
+        let wal_torn_tail_bytes = r.torn_tail_bytes;
+
+        // 3) a WAL with entries must reach the snapshot (see `SeqMismatchPolicy`)
+        if wal_max_seq > 0 && wal_max_seq < snapshot_seq {
+            let detail = format!(
+                "snapshot seq {} is ahead of the WAL's max seq {} (snapshot={}, wal={})",
+                snapshot_seq,
+                wal_max_seq,
+                self.snapshot_path.display(),
+                self.path.display()
+            );
+            match self.seq_mismatch {
+                SeqMismatchPolicy::Strict => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("{detail}; the WAL is stale or from another snapshot"),
+                    ))
+                }
+                SeqMismatchPolicy::Warn => {
+                    tracing::warn!(detail = %detail, "trusting the snapshot, WAL entries skipped")
+                }
+                SeqMismatchPolicy::TrustSnapshot => {}
+            }
+        }
 
         Ok(RestoreStats {
             snapshot_present,
             snapshot_seq,
             snapshot_books,
             snapshot_orders,
+            snapshot_checksum_verified,
             wal_replayed,
             wal_after_seq,
+            wal_segments_replayed,
+            wal_segments_skipped,
+            wal_torn_tail_bytes,
+            wal_checkpoints_verified,
+            wal_max_seq,
+            symbol_wals: 0,
         })
     }
 
-    fn replay_wal_after_seq_into(&self, st: &mut EngineState, after_seq: u64) -> io::Result<usize> {
-        if !self.path.exists() {
-            return Ok(0);
+    /// A torn tail, if any, is reported in `torn_tail_bytes`. Read-only: offline tools replay
+    /// the files they check, so the file is never changed here (see `repair_tail`).
+    ///
+    /// A crash mid-`append` can leave the final line without its newline. If that line doesn't
+    /// parse it was never acknowledged: it is skipped and replay succeeds. One that parses is an
+    /// ordinary entry. A parse failure on any newline-terminated line is corruption.
+    ///
+    /// Unknown fields are handled per `decode`, after the file is read, and are never mistaken
+    /// for a torn tail: the line parsed, it just carried more than this build knows.
+    ///
+    /// `after` is the snapshot's (seq, engine_seq): entries at or before it are covered.
+    fn replay_file_after_seq_into(
+        path: &Path,
+        st: &mut EngineState,
+        after: (u64, u64),
+        decode: DecodeMode,
+    ) -> io::Result<FileReplay> {
+        let mut out = FileReplay {
+            applied: 0,
+            torn_tail_bytes: 0,
+            checkpoints_verified: 0,
+            max_seq: 0,
+        };
+        if !path.exists() {
+            return Ok(out);
         }
 
-        let f = OpenOptions::new().read(true).open(&self.path)?;
-        let reader = BufReader::new(f);
+        let f = OpenOptions::new().read(true).open(path)?;
+        let mut reader = BufReader::new(f);
 
-        let mut applied = 0usize;
+        let mut buf = Vec::new();
+        let mut unknown = BTreeSet::new();
+        let what = format!("WAL {}", path.display());
 
-        for (idx, line) in reader.lines().enumerate() {
-            let line = line?;
-            let line = line.trim();
-            if line.is_empty() {
-                continue;
+        for idx in 0.. {
+            buf.clear();
+            let n = reader.read_until(b'\n', &mut buf)?;
+            if n == 0 {
+                break;
             }
+            let terminated = buf.last() == Some(&b'\n');
 
-            let entry: WalEntry = serde_json::from_str(line).map_err(|e| {
-                io::Error::new(
+            let mut line_unknown = BTreeSet::new();
+            let parsed = std::str::from_utf8(&buf)
+                .map_err(|e| e.to_string())
+                .and_then(|line| match line.trim() {
+                    "" => Ok(None),
+                    line => {
+                        from_json_tracking_unknown::<WalEntry>(line.as_bytes(), &mut line_unknown)
+                            .map(Some)
+                            .map_err(|e| e.to_string())
+                    }
+                });
+
+            let entry = match parsed {
+                Ok(Some(entry)) => entry,
+                Ok(None) => continue,
+                Err(_) if !terminated => {
+                    decode.check(&what, &unknown)?;
+                    out.torn_tail_bytes = n as u64;
+                    return Ok(out);
+                }
+                Err(e) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("WAL parse error at line {}: {}", idx + 1, e),
+                    ))
+                }
+            };
+
+            // The writer marked these as changing how the entry replays: never skipped, in any mode.
+            if let Some(field) = entry
+                .must_understand
+                .iter()
+                .find(|f| line_unknown.contains(*f))
+            {
+                return Err(io::Error::new(
                     io::ErrorKind::InvalidData,
-                    format!("WAL parse error at line {}: {}", idx + 1, e),
-                )
-            })?;
+                    format!(
+                        "{what} line {}: must-understand field {field} is unknown to this build",
+                        idx + 1
+                    ),
+                ));
+            }
+
+            // Strict fails at the offending line; lenient reports each field once per file, at the end.
+            match decode {
+                DecodeMode::Strict => {
+                    decode.check(&format!("{what} line {}", idx + 1), &line_unknown)?
+                }
+                DecodeMode::Lenient => unknown.append(&mut line_unknown),
+            }
+
+            out.max_seq = out.max_seq.max(entry.seq);
 
             // skip anything already covered by snapshot
-            if entry.seq <= after_seq {
+            if (entry.seq, entry.engine_seq) <= after {
                 continue;
             }
 
             if entry.seq > st.seq {
                 st.seq = entry.seq;
             }
+            st.engine_seq = st.engine_seq.max(entry.engine_seq);
 
-            let side = match entry.side.as_str() {
-                "BUY" => BookSide::Buy,
-                "SELL" => BookSide::Sell,
-                other => {
-                    return Err(io::Error::new(
-                        io::ErrorKind::InvalidData,
-                        format!("invalid side '{}' at line {}", other, idx + 1),
-                    ))
+            match entry.kind {
+                WalKind::Order => {
+                    let side = match parse_side(&entry.side) {
+                        Some(side) => side,
+                        None => {
+                            return Err(io::Error::new(
+                                io::ErrorKind::InvalidData,
+                                format!("invalid side '{}' at line {}", entry.side, idx + 1),
+                            ))
+                        }
+                    };
+
+                    let qty_scale = st.config.symbol(&entry.symbol).qty_scale;
+                    if entry.qty_scale != qty_scale {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!(
+                                "order at line {} was accepted with qty_scale {} but config has {} for {}",
+                                idx + 1,
+                                entry.qty_scale,
+                                qty_scale,
+                                entry.symbol
+                            ),
+                        ));
+                    }
+
+                    let book: &mut OrderBook = st.book_mut(&entry.symbol);
+                    // Live accepted it, so replay must too, whatever the config says now.
+                    if entry.allow_negative_price {
+                        book.allow_negative_price = true;
+                    }
+
+                    // Apply order exactly as it was accepted (matching included).
+                    // Fills aren't needed on replay, so don't collect them.
+                    book.add_capped_with(
+                        Order {
+                            seq: entry.seq,
+                            side,
+                            price: entry.price,
+                            qty: entry.qty,
+                            client_order_id: entry.client_order_id.clone(),
+                        },
+                        entry.max_levels,
+                        |_| {},
+                    );
                 }
-            };
+                WalKind::Cancel => {
+                    // A cancel is only logged for an order that was resting, so a miss means divergence.
+                    let target = entry.target_seq.unwrap_or(0);
+                    let removed = st
+                        .books
+                        .get_mut(&entry.symbol)
+                        .and_then(|b| b.cancel(target));
+                    if removed.is_none() {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!(
+                                "cancel of seq {} at line {} does not match a resting order",
+                                target,
+                                idx + 1
+                            ),
+                        ));
+                    }
+                }
+                WalKind::Halt => {
+                    st.halts.insert(
+                        entry.symbol.clone(),
+                        SymbolHalt {
+                            since_ms: entry.ts_ms.unwrap_or(0),
+                            resume_at_ms: entry.resume_at_ms.unwrap_or(0),
+                            trigger_price: entry.price,
+                        },
+                    );
+                }
+                WalKind::Resume => {
+                    st.halts.remove(&entry.symbol);
+                }
+                WalKind::DisableEntry => {
+                    st.entry_disabled.insert(entry.symbol.clone());
+                }
+                WalKind::EnableEntry => {
+                    st.entry_disabled.remove(&entry.symbol);
+                }
+                // Fail at the exact point of divergence rather than at the next snapshot check.
+                WalKind::Checkpoint => {
+                    let actual = state_checksum(st);
+                    if entry.checksum != Some(actual) {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!(
+                                "checkpoint at line {} (seq {}) does not match replayed state: logged={} replayed={:016x}",
+                                idx + 1,
+                                entry.seq,
+                                entry.checksum.map_or("none".to_string(), |c| format!("{c:016x}")),
+                                actual
+                            ),
+                        ));
+                    }
+                    out.checkpoints_verified += 1;
+                    continue; // verified, not applied
+                }
+                // Raises st.seq (above) and nothing else; not an applied entry.
+                WalKind::SeqMark => continue,
+            }
 
-            let book: &mut OrderBook = st
-                .books
-                .entry(entry.symbol.clone())
-                .or_insert_with(OrderBook::new);
+            out.applied += 1;
+        }
 
-            // Apply order exactly as it was accepted (matching included).
-            let _fills = book.add(Order {
-                seq: entry.seq,
-                side,
-                price: entry.price,
-                qty: entry.qty,
-                client_order_id: entry.client_order_id.clone(),
-            });
+        decode.check(&what, &unknown)?;
+        Ok(out)
+    }
 
-            applied += 1;
+    /// Server startup only, after a successful restore and before the first append: make the
+    /// active WAL end on a line boundary, so the next append starts a clean line. An unterminated
+    /// final line that doesn't parse was never acknowledged (replay skipped it) and is cut; one
+    /// that parses only lost its newline and gets it back. Archived segments are never appended
+    /// to, so they are left alone. In the per-symbol layout every symbol's WAL is repaired too.
+    pub fn repair_tail(&self) -> io::Result<TailRepair> {
+        let mut total = Self::repair_tail_file(&self.path)?;
+        if self.layout == WalLayout::PerSymbol {
+            for symbol in self.symbol_dirs()? {
+                let r = Self::repair_tail_file(&self.symbol_wal(&symbol)?.path)?;
+                total.cut_bytes += r.cut_bytes;
+                total.newlines_added += r.newlines_added;
+            }
         }
+        Ok(total)
+    }
 
-        Ok(applied)
+    fn repair_tail_file(path: &Path) -> io::Result<TailRepair> {
+        let mut out = TailRepair::default();
+        let f = match OpenOptions::new().read(true).open(path) {
+            Ok(f) => f,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(out),
+            Err(e) => return Err(e),
+        };
+        let mut reader = BufReader::new(f);
+
+        // Only the final line can lack its newline.
+        let mut line_start = 0u64;
+        let mut buf = Vec::new();
+        loop {
+            buf.clear();
+            let n = reader.read_until(b'\n', &mut buf)?;
+            if n == 0 {
+                return Ok(out);
+            }
+            if buf.last() != Some(&b'\n') {
+                break;
+            }
+            line_start += n as u64;
+        }
+
+        // Same test as replay's: unknown fields don't make a line torn.
+        let complete = std::str::from_utf8(&buf)
+            .ok()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .is_some_and(|line| {
+                from_json_tracking_unknown::<WalEntry>(line.as_bytes(), &mut BTreeSet::new())
+                    .is_ok()
+            });
+        if complete {
+            OpenOptions::new()
+                .append(true)
+                .open(path)?
+                .write_all(b"\n")?;
+            out.newlines_added = 1;
+        } else {
+            OpenOptions::new()
+                .write(true)
+                .open(path)?
+                .set_len(line_start)?;
+            out.cut_bytes = buf.len() as u64;
+        }
+        Ok(out)
     }
 
     /// Expose paths for debugging / tests if needed.
@@ -283,57 +1638,1293 @@ impl Wal {
 
 // ---- Helpers ----
 
-fn flatten_side(levels: &std::collections::BTreeMap<i64, std::collections::VecDeque<RestingOrder>>) -> Vec<Order> {
+/// Deterministic FNV-1a checksum of the engine's book state.
+///
+/// Covers seq plus every resting order (symbols sorted, bids then asks, ascending price,
+/// FIFO within a level), so it is independent of HashMap iteration order and stable
+/// across processes. Trade tape is not included: it is not restored from disk.
+pub fn state_checksum(st: &EngineState) -> u64 {
+    books_checksum(st, st.seq, |_| true)
+}
+
+/// `state_checksum` over only the books `keep` accepts, with `seq` in place of `st.seq`:
+/// what one per-symbol snapshot covers.
+fn books_checksum(st: &EngineState, seq: u64, keep: impl Fn(&str) -> bool) -> u64 {
+    const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    fn mix(h: &mut u64, bytes: &[u8]) {
+        for b in bytes {
+            *h ^= *b as u64;
+            *h = h.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    let mut h = FNV_OFFSET;
+    mix(&mut h, &seq.to_le_bytes());
+
+    let mut symbols: Vec<&String> = st.books.keys().filter(|s| keep(s)).collect();
+    symbols.sort();
+
+    for symbol in symbols {
+        let book = &st.books[symbol];
+        mix(&mut h, &(symbol.len() as u64).to_le_bytes());
+        mix(&mut h, symbol.as_bytes());
+
+        for levels in [&book.bids, &book.asks] {
+            mix(&mut h, &(levels.len() as u64).to_le_bytes());
+            for (price, q) in levels.iter() {
+                mix(&mut h, &price.to_le_bytes());
+                mix(&mut h, &(q.len() as u64).to_le_bytes());
+                for ro in q.iter() {
+                    mix(&mut h, &ro.seq.to_le_bytes());
+                    mix(&mut h, &[ro.side as u8]);
+                    mix(&mut h, &ro.remaining_qty.to_le_bytes());
+                    mix(&mut h, &(ro.client_order_id.len() as u64).to_le_bytes());
+                    mix(&mut h, ro.client_order_id.as_bytes());
+                }
+            }
+        }
+    }
+
+    h
+}
+
+fn flatten_side(
+    levels: &std::collections::BTreeMap<i64, std::collections::VecDeque<RestingOrder>>,
+) -> Vec<SnapshotOrder> {
     // Deterministic order:
     // - iterate price levels in ascending price order (BTreeMap iter)
     // - within each level, FIFO order (VecDeque front -> back)
-    //
-    // Snapshot serializes as `Order` for compatibility; `qty` stores remaining qty.
-    let mut out = Vec::new();
-    for (_price, q) in levels.iter() {
-        for ro in q.iter() {
-            out.push(Order {
-                seq: ro.seq,
-                side: ro.side,
-                price: ro.price,
-                qty: ro.remaining_qty,
-                client_order_id: ro.client_order_id.clone(),
-            });
+    levels.values().flatten().map(SnapshotOrder::from).collect()
+}
+
+/// Side as read from a WAL line. The engine writes "BUY"/"SELL"; externally generated
+/// entries may use any case or stray whitespace.
+fn parse_side(s: &str) -> Option<BookSide> {
+    let s = s.trim();
+    if s.eq_ignore_ascii_case("BUY") {
+        Some(BookSide::Buy)
+    } else if s.eq_ignore_ascii_case("SELL") {
+        Some(BookSide::Sell)
+    } else {
+        None
+    }
+}
+
+/// Snapshot of the current state, limited to the symbols `keep` accepts (all of them in the
+/// single layout, one per file in the per-symbol layout). Symbols are sorted so identical state
+/// always yields identical bytes (hash/diff friendly).
+fn build_snapshot(st: &EngineState, keep: impl Fn(&str) -> bool) -> Snapshot {
+    let mut symbols: Vec<&String> = st.books.keys().filter(|s| keep(s)).collect();
+    symbols.sort();
+
+    Snapshot {
+        version: SNAPSHOT_VERSION,
+        seq: st.seq,
+        engine_seq: st.engine_seq,
+        books: symbols
+            .into_iter()
+            .map(|symbol| SnapshotBook {
+                symbol: symbol.clone(),
+                bids: flatten_side(&st.books[symbol].bids),
+                asks: flatten_side(&st.books[symbol].asks),
+                qty_scale: st.config.symbol(symbol).qty_scale,
+                allow_negative_price: st.books[symbol].allow_negative_price,
+            })
+            .collect(),
+        checksum: Some(books_checksum(st, st.seq, &keep)),
+        halts: snapshot_halts(st, &keep),
+        entry_disabled: st
+            .entry_disabled
+            .iter()
+            .filter(|s| keep(s))
+            .cloned()
+            .collect(),
+    }
+}
+
+/// Halts of the symbols `keep` accepts, sorted by symbol.
+fn snapshot_halts(st: &EngineState, keep: impl Fn(&str) -> bool) -> Vec<SnapshotHalt> {
+    let mut halts: Vec<SnapshotHalt> = st
+        .halts
+        .iter()
+        .filter(|(symbol, _)| keep(symbol))
+        .map(|(symbol, h)| SnapshotHalt {
+            symbol: symbol.clone(),
+            since_ms: h.since_ms,
+            resume_at_ms: h.resume_at_ms,
+            trigger_price: h.trigger_price,
+        })
+        .collect();
+    halts.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+    halts
+}
+
+/// Startup self-check: serialize the live state as a snapshot, parse it back, rebuild a fresh
+/// state from it and compare. Catches fields that stop round-tripping (e.g. lost in a refactor).
+pub fn verify_snapshot_round_trip(st: &EngineState) -> io::Result<()> {
+    let json = serde_json::to_vec(&build_snapshot(st, |_| true))
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let snap: Snapshot = serde_json::from_slice(&json).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("snapshot round-trip parse error: {}", e),
+        )
+    })?;
+
+    let mut rebuilt = EngineState {
+        config: st.config.clone(),
+        ..Default::default()
+    };
+    apply_snapshot(&mut rebuilt, snap)?;
+
+    match first_difference(st, &rebuilt) {
+        None => Ok(()),
+        Some(diff) => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("snapshot round-trip mismatch: {}", diff),
+        )),
+    }
+}
+
+/// First difference in snapshotted state (seq, halts, then resting orders by symbol), if any.
+fn first_difference(live: &EngineState, reloaded: &EngineState) -> Option<String> {
+    if live.seq != reloaded.seq {
+        return Some(format!("seq live={} reloaded={}", live.seq, reloaded.seq));
+    }
+    if live.engine_seq != reloaded.engine_seq {
+        return Some(format!(
+            "engine_seq live={} reloaded={}",
+            live.engine_seq, reloaded.engine_seq
+        ));
+    }
+    if live.halts != reloaded.halts {
+        return Some(format!(
+            "halts live={:?} reloaded={:?}",
+            live.halts, reloaded.halts
+        ));
+    }
+    if live.entry_disabled != reloaded.entry_disabled {
+        return Some(format!(
+            "entry_disabled live={:?} reloaded={:?}",
+            live.entry_disabled, reloaded.entry_disabled
+        ));
+    }
+
+    let mut symbols: Vec<&String> = live.books.keys().chain(reloaded.books.keys()).collect();
+    symbols.sort();
+    symbols.dedup();
+
+    for symbol in symbols {
+        let orders = |st: &EngineState| -> Vec<RestingOrder> {
+            st.books
+                .get(symbol)
+                .map(|b| {
+                    b.bids
+                        .values()
+                        .chain(b.asks.values())
+                        .flatten()
+                        .cloned()
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+        let (a, b) = (orders(live), orders(reloaded));
+        for i in 0..a.len().max(b.len()) {
+            if a.get(i) != b.get(i) {
+                return Some(format!(
+                    "{} order #{}: live={:?} reloaded={:?}",
+                    symbol,
+                    i,
+                    a.get(i),
+                    b.get(i)
+                ));
+            }
         }
     }
-    out
+    None
 }
 
 fn apply_snapshot(st: &mut EngineState, snap: Snapshot) -> io::Result<(usize, usize)> {
     st.seq = snap.seq;
+    st.engine_seq = snap.engine_seq;
     st.books.clear();
+    st.halts.clear();
+    st.entry_disabled.clear();
+    merge_snapshot(st, snap)
+}
+
+/// Per-symbol layout: lay one symbol's snapshot over the state restored so far, replacing
+/// whatever the engine-wide files had for it. Anything for another symbol is corruption.
+fn apply_symbol_snapshot(
+    st: &mut EngineState,
+    symbol: &str,
+    snap: Snapshot,
+) -> io::Result<(usize, usize)> {
+    let foreign = snap
+        .books
+        .iter()
+        .map(|b| &b.symbol)
+        .chain(snap.halts.iter().map(|h| &h.symbol))
+        .chain(snap.entry_disabled.iter())
+        .find(|s| *s != symbol);
+    if let Some(other) = foreign {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("snapshot for {} also holds {}", symbol, other),
+        ));
+    }
+
+    st.seq = st.seq.max(snap.seq);
+    st.engine_seq = st.engine_seq.max(snap.engine_seq);
+    st.books.remove(symbol);
+    st.halts.remove(symbol);
+    st.entry_disabled.remove(symbol);
+    merge_snapshot(st, snap)
+}
+
+/// Add a snapshot's books, halts and entry toggles to `st` (seq is the caller's).
+fn merge_snapshot(st: &mut EngineState, snap: Snapshot) -> io::Result<(usize, usize)> {
+    st.halts.extend(snap.halts.into_iter().map(|h| {
+        (
+            h.symbol,
+            SymbolHalt {
+                since_ms: h.since_ms,
+                resume_at_ms: h.resume_at_ms,
+                trigger_price: h.trigger_price,
+            },
+        )
+    }));
+    st.entry_disabled.extend(snap.entry_disabled);
 
     let mut books = 0usize;
     let mut orders = 0usize;
 
-    for b in snap.books.into_iter() {
-        let mut book = OrderBook::new();
-
-        // Rebuild bids/asks exactly as resting orders.
-        // Push them back into exact price levels, preserving FIFO.
-        for o in b.bids.into_iter() {
-            orders += 1;
-            book.bids
-                .entry(o.price)
-                .or_insert_with(std::collections::VecDeque::new)
-                .push_back(o.into());
-        }
-        for o in b.asks.into_iter() {
-            orders += 1;
-            book.asks
-                .entry(o.price)
-                .or_insert_with(std::collections::VecDeque::new)
-                .push_back(o.into());
+    for mut b in snap.books.into_iter() {
+        let symbol = std::mem::take(&mut b.symbol);
+        let cfg = st.config.symbol(&symbol);
+        // Resting quantities are only meaningful at the scale they were accepted with.
+        if b.qty_scale != cfg.qty_scale && !(b.bids.is_empty() && b.asks.is_empty()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "snapshot book {} has qty_scale {} but config has {}; qty_scale can't change while orders rest",
+                    symbol, b.qty_scale, cfg.qty_scale
+                ),
+            ));
         }
+        let allow_negative_price = b.allow_negative_price || cfg.allow_negative_price;
+        let (book, n) = rebuild_book(b, allow_negative_price);
 
-        st.books.insert(b.symbol, book);
+        st.books.insert(symbol, book);
         books += 1;
+        orders += n;
     }
 
     Ok((books, orders))
 }
+
+/// Rebuild bids/asks exactly as resting orders.
+/// Push them back into exact price levels, preserving FIFO. Returns the book and its order count.
+fn rebuild_book(b: SnapshotBook, allow_negative_price: bool) -> (OrderBook, usize) {
+    let mut book = OrderBook::with_negative_prices(allow_negative_price);
+    let mut orders = 0usize;
+
+    for o in b.bids.into_iter().chain(b.asks) {
+        orders += 1;
+        book.rest(o.into());
+    }
+
+    (book, orders)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::config::EngineConfig;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static NEXT_DIR: AtomicUsize = AtomicUsize::new(0);
+
+    /// Fresh WAL under a unique temp dir (snapshot lands next to it).
+    pub(crate) fn temp_wal() -> Wal {
+        let dir = std::env::temp_dir().join(format!(
+            "engine-wal-test-{}-{}",
+            std::process::id(),
+            NEXT_DIR.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        Wal::new(dir.join("wal.jsonl"))
+    }
+
+    fn entry(seq: u64, side: &str, price: i64, qty: i64) -> WalEntry {
+        WalEntry {
+            kind: WalKind::Order,
+            seq,
+            symbol: "BTC-USD".to_string(),
+            side: side.to_string(),
+            price,
+            qty,
+            client_order_id: format!("c{}", seq),
+            ..Default::default()
+        }
+    }
+
+    fn cancel(seq: u64, target_seq: u64) -> WalEntry {
+        WalEntry {
+            kind: WalKind::Cancel,
+            target_seq: Some(target_seq),
+            ..entry(seq, "BUY", 100, 5)
+        }
+    }
+
+    fn replay(wal: &Wal) -> (EngineState, RestoreStats) {
+        let mut st = EngineState::default();
+        let stats = wal.replay_into_with_stats(&mut st).unwrap();
+        (st, stats)
+    }
+
+    #[test]
+    fn snapshot_checksum_round_trips_and_is_verified() {
+        let wal = temp_wal();
+        wal.append(&entry(1, "BUY", 100, 5)).unwrap();
+        wal.append(&entry(2, "SELL", 101, 3)).unwrap();
+        wal.append(&entry(3, "SELL", 100, 2)).unwrap();
+
+        let (st, _) = replay(&wal);
+        wal.write_snapshot(&st).unwrap();
+        wal.truncate_wal().unwrap();
+
+        let (restored, stats) = replay(&wal);
+        assert!(stats.snapshot_checksum_verified);
+        assert_eq!(state_checksum(&restored), state_checksum(&st));
+    }
+
+    #[test]
+    fn keep_retention_archives_segments_and_skips_covered_ones() {
+        let wal = temp_wal().with_retention(WalRetention::Keep);
+        wal.append(&entry(1, "BUY", 100, 5)).unwrap();
+        wal.append(&entry(2, "SELL", 101, 3)).unwrap();
+        let (st, _) = replay(&wal);
+        wal.write_snapshot(&st).unwrap();
+        wal.retire_wal(st.seq, 0).unwrap();
+        // nothing new since the last rotation: no empty segment
+        wal.retire_wal(st.seq, 0).unwrap();
+
+        wal.append(&entry(3, "SELL", 100, 2)).unwrap();
+        wal.append(&cancel(4, 2)).unwrap();
+        let (st, _) = replay(&wal);
+        wal.write_snapshot(&st).unwrap();
+        wal.retire_wal(st.seq, 0).unwrap();
+        wal.append(&entry(5, "BUY", 99, 1)).unwrap();
+
+        let segments = wal.archived_segments().unwrap();
+        assert_eq!(segments.iter().map(|s| s.0).collect::<Vec<_>>(), vec![2, 4]);
+
+        let (restored, stats) = replay(&wal);
+        assert_eq!(
+            (stats.wal_segments_skipped, stats.wal_segments_replayed),
+            (2, 0)
+        );
+        assert_eq!((restored.seq, stats.wal_replayed), (5, 1));
+
+        // Without a snapshot the archive alone rebuilds the same state.
+        fs::remove_file(wal.snapshot_path()).unwrap();
+        let (from_archive, stats) = replay(&wal);
+        assert_eq!((stats.wal_segments_replayed, stats.wal_replayed), (2, 5));
+        assert_eq!(state_checksum(&from_archive), state_checksum(&restored));
+    }
+
+    #[test]
+    fn engine_seq_orders_entries_that_repeat_a_seq() {
+        let wal = temp_wal().with_retention(WalRetention::Keep);
+        let at = |engine_seq: u64, e: WalEntry| WalEntry { engine_seq, ..e }.with_must_understand();
+        wal.append(&entry(1, "BUY", 100, 5)).unwrap();
+        wal.append(&entry(2, "BUY", 99, 5)).unwrap();
+        wal.append(&at(1, cancel(2, 1))).unwrap();
+        let (st, _) = replay(&wal);
+        wal.write_snapshot(&st).unwrap();
+        wal.retire_wal(st.seq, st.engine_seq).unwrap();
+
+        // Same seq, later engine_seq: not covered by the snapshot, and its segment gets its own name
+        wal.append(&at(2, cancel(2, 2))).unwrap();
+        let (st, stats) = replay(&wal);
+        assert_eq!((st.seq, st.engine_seq, stats.wal_replayed), (2, 2, 1));
+        assert!(st.books["BTC-USD"].get(2).is_none());
+        wal.write_snapshot(&st).unwrap();
+        wal.retire_wal(st.seq, st.engine_seq).unwrap();
+
+        let segments = wal.archived_segments().unwrap();
+        assert_eq!(segments.iter().map(|s| s.0).collect::<Vec<_>>(), vec![2, 2]);
+        fs::remove_file(wal.snapshot_path()).unwrap();
+        let (from_archive, _) = replay(&wal);
+        assert_eq!(state_checksum(&from_archive), state_checksum(&st));
+        assert_eq!(from_archive.engine_seq, 2);
+    }
+
+    #[test]
+    fn qty_scale_change_is_rejected_on_restore() {
+        let scaled = |scale: u32| {
+            EngineConfig::from_json(
+                format!(r#"{{ "BTC-USD": {{ "qty_scale": {scale} }} }}"#).as_bytes(),
+            )
+            .unwrap()
+        };
+        let restore = |wal: &Wal, scale| {
+            let mut st = EngineState {
+                config: scaled(scale),
+                ..Default::default()
+            };
+            wal.replay_into_with_stats(&mut st).map(|_| st)
+        };
+
+        let wal = temp_wal();
+        wal.append(&WalEntry {
+            qty_scale: 3,
+            ..entry(1, "BUY", 100, 1500)
+        })
+        .unwrap();
+        assert!(fs::read_to_string(wal.wal_path())
+            .unwrap()
+            .contains(r#""qty_scale":3"#));
+        assert!(restore(&wal, 2)
+            .unwrap_err()
+            .to_string()
+            .contains("qty_scale"));
+
+        let st = restore(&wal, 3).unwrap();
+        wal.write_snapshot(&st).unwrap();
+        wal.truncate_wal().unwrap();
+        assert!(restore(&wal, 3).is_ok());
+        assert!(restore(&wal, 0)
+            .unwrap_err()
+            .to_string()
+            .contains("qty_scale"));
+    }
+
+    /// StdFs that refuses rename-over-existing and directory fsync (non-POSIX platforms),
+    /// optionally "crashing" after the old snapshot was moved aside, and logs every call.
+    #[derive(Debug, Default)]
+    struct QuirkyFs {
+        crash_after_backup: bool,
+        calls: std::sync::Mutex<Vec<String>>,
+    }
+
+    impl SnapshotFs for QuirkyFs {
+        fn write_synced(&self, path: &Path, bytes: &[u8]) -> io::Result<()> {
+            self.calls.lock().unwrap().push("write_synced".to_string());
+            StdFs.write_synced(path, bytes)
+        }
+
+        fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+            let ext = |p: &Path| p.extension().unwrap().to_string_lossy().into_owned();
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("rename {}->{}", ext(from), ext(to)));
+            if to.exists() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    "target exists",
+                ));
+            }
+            if self.crash_after_backup && ext(from) == "tmp" {
+                return Err(io::Error::other("crash"));
+            }
+            StdFs.rename(from, to)
+        }
+
+        fn remove_file(&self, path: &Path) -> io::Result<()> {
+            StdFs.remove_file(path)
+        }
+
+        fn sync_dir(&self, _dir: &Path) -> io::Result<()> {
+            self.calls.lock().unwrap().push("sync_dir".to_string());
+            Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "no directory fsync",
+            ))
+        }
+    }
+
+    #[test]
+    fn snapshot_replace_strategies() {
+        let base = temp_wal();
+        base.append(&entry(1, "BUY", 100, 5)).unwrap();
+        let (st, _) = replay(&base);
+
+        // Real filesystem: single rename, then the directory is synced.
+        let w = base.write_snapshot(&st).unwrap();
+        assert_eq!(
+            (w.replace, w.dir_synced),
+            (SnapshotReplace::AtomicRename, true)
+        );
+        assert_eq!(w.bytes, fs::metadata(base.snapshot_path()).unwrap().len());
+
+        let quirky = Arc::new(QuirkyFs::default());
+        let wal = base.clone().with_snapshot_fs(quirky.clone());
+        assert!(wal
+            .clone()
+            .with_snapshot_atomicity(SnapshotAtomicity::Strict)
+            .write_snapshot(&st)
+            .is_err());
+
+        let wal = wal.with_snapshot_atomicity(SnapshotAtomicity::BestEffort);
+        quirky.calls.lock().unwrap().clear();
+        let w = wal.write_snapshot(&st).unwrap();
+        assert_eq!(
+            (w.replace, w.dir_synced),
+            (SnapshotReplace::ViaBackup, false)
+        );
+        assert_eq!(
+            *quirky.calls.lock().unwrap(),
+            [
+                "write_synced",
+                "rename tmp->json",
+                "rename json->bak",
+                "rename tmp->json",
+                "sync_dir"
+            ]
+        );
+        assert!(!wal.backup_snapshot_path().exists());
+
+        // Crash between the two renames: only the backup is left, and restore uses it.
+        let crashing = base
+            .clone()
+            .with_snapshot_fs(Arc::new(QuirkyFs {
+                crash_after_backup: true,
+                ..Default::default()
+            }))
+            .with_snapshot_atomicity(SnapshotAtomicity::BestEffort);
+        assert!(crashing.write_snapshot(&st).is_err());
+        fs::rename(base.snapshot_path(), base.backup_snapshot_path()).unwrap();
+        assert_eq!(base.read_snapshot().unwrap().unwrap().seq, 1);
+    }
+
+    #[test]
+    fn snapshot_checksum_mismatch_fails_restore() {
+        let wal = temp_wal();
+        wal.append(&entry(1, "BUY", 100, 5)).unwrap();
+
+        let (st, _) = replay(&wal);
+        wal.write_snapshot(&st).unwrap();
+
+        // Tamper with a resting qty but keep the stored checksum.
+        let mut snap = wal.read_snapshot().unwrap().unwrap();
+        snap.books[0].bids[0].qty = 4;
+        fs::write(wal.snapshot_path(), serde_json::to_vec(&snap).unwrap()).unwrap();
+
+        let mut restored = EngineState::default();
+        let err = wal.replay_into_with_stats(&mut restored).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("checksum mismatch at seq 1"));
+    }
+
+    #[test]
+    fn legacy_lines_without_kind_replay_as_orders() {
+        let wal = temp_wal();
+        fs::create_dir_all(wal.wal_path().parent().unwrap()).unwrap();
+        fs::write(
+            wal.wal_path(),
+            "{\"seq\":1,\"symbol\":\"BTC-USD\",\"side\":\"BUY\",\"price\":100,\"qty\":5,\"client_order_id\":\"\"}\n",
+        )
+        .unwrap();
+
+        let (st, stats) = replay(&wal);
+        assert_eq!(stats.wal_replayed, 1);
+        assert_eq!(st.books["BTC-USD"].top_of_book(), (100, 5, 0, 0));
+    }
+
+    #[test]
+    fn cancel_entries_replay_and_consume_seq() {
+        let wal = temp_wal();
+        wal.append(&entry(1, "BUY", 100, 5)).unwrap();
+        wal.append(&entry(2, "BUY", 99, 1)).unwrap();
+        wal.append(&cancel(3, 1)).unwrap();
+
+        let (st, stats) = replay(&wal);
+        assert_eq!(stats.wal_replayed, 3);
+        assert_eq!(st.seq, 3);
+        assert!(st.books["BTC-USD"].get(1).is_none());
+        assert_eq!(st.books["BTC-USD"].top_of_book(), (99, 1, 0, 0));
+    }
+
+    #[test]
+    fn side_is_parsed_case_insensitively() {
+        let wal = temp_wal();
+        wal.append(&entry(1, "buy", 100, 5)).unwrap();
+        wal.append(&entry(2, " Sell ", 101, 3)).unwrap();
+
+        let (st, _) = replay(&wal);
+        assert_eq!(st.books["BTC-USD"].top_of_book(), (100, 5, 101, 3));
+
+        wal.append(&entry(3, "BID", 100, 1)).unwrap();
+        let mut st = EngineState::default();
+        let err = wal.replay_into_with_stats(&mut st).unwrap_err();
+        assert!(err.to_string().contains("invalid side 'BID' at line 3"));
+    }
+
+    #[test]
+    fn torn_final_line_is_skipped_by_replay_and_cut_by_repair() {
+        let wal = temp_wal();
+        wal.append(&entry(1, "BUY", 100, 5)).unwrap();
+        let torn = br#"{"seq":2,"symbol":"BTC-U"#;
+        OpenOptions::new()
+            .append(true)
+            .open(wal.wal_path())
+            .unwrap()
+            .write_all(torn)
+            .unwrap();
+        let before = fs::read(wal.wal_path()).unwrap();
+
+        // Replay reports the fragment and leaves the file as it found it
+        let (st, stats) = replay(&wal);
+        assert_eq!((st.seq, stats.wal_replayed), (1, 1));
+        assert_eq!(stats.wal_torn_tail_bytes, torn.len() as u64);
+        assert_eq!(fs::read(wal.wal_path()).unwrap(), before);
+
+        // Repair cuts it, so the next append is a clean line
+        assert_eq!(
+            wal.repair_tail().unwrap(),
+            TailRepair {
+                cut_bytes: torn.len() as u64,
+                newlines_added: 0
+            }
+        );
+        assert_eq!(wal.repair_tail().unwrap(), TailRepair::default());
+        wal.append(&entry(2, "SELL", 101, 1)).unwrap();
+        let (st, stats) = replay(&wal);
+        assert_eq!((st.seq, stats.wal_torn_tail_bytes), (2, 0));
+
+        // A complete entry that only lost its newline replays, and repair restores the newline
+        let mut line = serde_json::to_vec(&entry(3, "SELL", 102, 1)).unwrap();
+        OpenOptions::new()
+            .append(true)
+            .open(wal.wal_path())
+            .unwrap()
+            .write_all(&line)
+            .unwrap();
+        let before = fs::read(wal.wal_path()).unwrap();
+        let (st, stats) = replay(&wal);
+        assert_eq!(
+            (st.seq, stats.wal_replayed, stats.wal_torn_tail_bytes),
+            (3, 3, 0)
+        );
+        assert_eq!(fs::read(wal.wal_path()).unwrap(), before);
+        assert_eq!(
+            wal.repair_tail().unwrap(),
+            TailRepair {
+                cut_bytes: 0,
+                newlines_added: 1
+            }
+        );
+        line.push(b'\n');
+        assert!(fs::read(wal.wal_path()).unwrap().ends_with(&line));
+    }
+
+    #[test]
+    fn unparseable_terminated_line_is_corruption() {
+        let wal = temp_wal();
+        wal.append(&entry(1, "BUY", 100, 5)).unwrap();
+        OpenOptions::new()
+            .append(true)
+            .open(wal.wal_path())
+            .unwrap()
+            .write_all(b"{\"seq\":2,\n")
+            .unwrap();
+        wal.append(&entry(3, "BUY", 100, 5)).unwrap();
+
+        let mut st = EngineState::default();
+        let err = wal.replay_into_with_stats(&mut st).unwrap_err();
+        assert!(err.to_string().contains("WAL parse error at line 2"));
+
+        // Torn-looking but not last: still corruption
+        let wal = temp_wal();
+        wal.append(&entry(1, "BUY", 100, 5)).unwrap();
+        OpenOptions::new()
+            .append(true)
+            .open(wal.wal_path())
+            .unwrap()
+            .write_all(b"{\"seq\":2,\n\n")
+            .unwrap();
+        let mut st = EngineState::default();
+        assert!(wal.replay_into_with_stats(&mut st).is_err());
+    }
+
+    #[test]
+    fn unknown_fields_fail_strict_and_are_skipped_lenient() {
+        let wal = temp_wal();
+        wal.append(&entry(1, "BUY", 100, 5)).unwrap();
+        let (st, _) = replay(&wal);
+        wal.write_snapshot(&st).unwrap();
+
+        // A newer writer added a field to resting orders (flattened `Order`) and to the snapshot
+        let mut snap: serde_json::Value =
+            serde_json::from_slice(&fs::read(wal.snapshot_path()).unwrap()).unwrap();
+        snap["books"][0]["bids"][0]["venue"] = "X".into();
+        snap["epoch"] = 3.into();
+        fs::write(wal.snapshot_path(), serde_json::to_vec(&snap).unwrap()).unwrap();
+        // ... and to WAL lines
+        let mut line = serde_json::to_value(entry(2, "SELL", 101, 1)).unwrap();
+        line["stp_group"] = 9.into();
+        let mut f = OpenOptions::new()
+            .append(true)
+            .open(wal.wal_path())
+            .unwrap();
+        writeln!(f, "{}", line).unwrap();
+
+        let err = wal.read_snapshot().unwrap_err().to_string();
+        assert!(
+            err.contains("books.0.bids.0.venue") && err.contains("epoch"),
+            "{err}"
+        );
+
+        let lenient = wal.clone().with_decode_mode(DecodeMode::Lenient);
+        let (st, stats) = replay(&lenient);
+        assert_eq!(
+            (st.seq, stats.snapshot_orders, stats.wal_replayed),
+            (2, 1, 1)
+        );
+
+        // Strict names the WAL line too, once the snapshot is readable again
+        fs::remove_file(wal.snapshot_path()).unwrap();
+        let wal = Wal::new(wal.wal_path());
+        let mut st = EngineState::default();
+        let err = wal.replay_into_with_stats(&mut st).unwrap_err().to_string();
+        assert!(err.contains("line 2") && err.contains("stp_group"), "{err}");
+
+        // Lenient never covers a change of meaning: an unknown kind still fails
+        let lenient = temp_wal().with_decode_mode(DecodeMode::Lenient);
+        lenient.append(&entry(1, "BUY", 100, 5)).unwrap();
+        let mut f = OpenOptions::new()
+            .append(true)
+            .open(lenient.wal_path())
+            .unwrap();
+        writeln!(f, r#"{{"kind":"AMEND","seq":2,"symbol":"BTC-USD","side":"BUY","price":1,"qty":1,"client_order_id":""}}"#).unwrap();
+        let mut st = EngineState::default();
+        assert!(lenient.replay_into_with_stats(&mut st).is_err());
+    }
+
+    #[test]
+    fn must_understand_fields_fail_even_lenient() {
+        // Writers list the meaning-changing fields they set
+        let capped = WalEntry {
+            max_levels: 2,
+            ..entry(1, "BUY", 100, 5)
+        }
+        .with_must_understand();
+        assert_eq!(capped.must_understand, ["max_levels"]);
+        assert!(entry(1, "BUY", 100, 5)
+            .with_must_understand()
+            .must_understand
+            .is_empty());
+
+        let lenient = temp_wal().with_decode_mode(DecodeMode::Lenient);
+        lenient.append(&capped).unwrap();
+        // An unknown field the writer didn't list is skipped ...
+        let mut line = serde_json::to_value(entry(2, "SELL", 101, 1)).unwrap();
+        line["stp_group"] = 9.into();
+        let mut f = OpenOptions::new()
+            .append(true)
+            .open(lenient.wal_path())
+            .unwrap();
+        writeln!(f, "{}", line).unwrap();
+        let (st, _) = replay(&lenient);
+        assert_eq!(st.seq, 2);
+
+        // ... one it listed is not
+        line["seq"] = 3.into();
+        line["must_understand"] = serde_json::json!(["stp_group"]);
+        writeln!(f, "{}", line).unwrap();
+        let mut st = EngineState::default();
+        let err = lenient
+            .replay_into_with_stats(&mut st)
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("line 3") && err.contains("must-understand field stp_group"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn checkpoints_are_verified_not_applied() {
+        let wal = temp_wal();
+        wal.append(&entry(1, "BUY", 100, 5)).unwrap();
+        wal.append(&entry(2, "SELL", 100, 2)).unwrap();
+        let (st, _) = replay(&wal);
+        let checkpoint = |checksum| WalEntry {
+            kind: WalKind::Checkpoint,
+            seq: 2,
+            checksum: Some(checksum),
+            ..Default::default()
+        };
+        wal.append(&checkpoint(state_checksum(&st))).unwrap();
+        wal.append(&entry(3, "BUY", 99, 1)).unwrap();
+
+        let (st, stats) = replay(&wal);
+        assert_eq!(
+            (st.seq, stats.wal_replayed, stats.wal_checkpoints_verified),
+            (3, 3, 1)
+        );
+        assert!(fs::read_to_string(wal.wal_path())
+            .unwrap()
+            .contains(r#""kind":"CHECKPOINT""#));
+
+        // Divergence is reported at the checkpoint's line, not later
+        let wal = temp_wal();
+        wal.append(&entry(1, "BUY", 100, 5)).unwrap();
+        wal.append(&checkpoint(0xdead)).unwrap();
+        wal.append(&entry(3, "BUY", 99, 1)).unwrap();
+        let mut st = EngineState::default();
+        let err = wal.replay_into_with_stats(&mut st).unwrap_err().to_string();
+        assert!(err.contains("checkpoint at line 2 (seq 2)"), "{err}");
+    }
+
+    #[test]
+    fn cancel_of_missing_order_fails_replay() {
+        let wal = temp_wal();
+        wal.append(&entry(1, "BUY", 100, 5)).unwrap();
+        wal.append(&cancel(2, 7)).unwrap();
+
+        let mut st = EngineState::default();
+        let err = wal.replay_into_with_stats(&mut st).unwrap_err();
+        assert!(err.to_string().contains("cancel of seq 7 at line 2"));
+    }
+
+    #[test]
+    fn halt_and_resume_replay_and_survive_snapshot() {
+        let wal = temp_wal();
+        wal.append(&entry(1, "BUY", 100, 5)).unwrap();
+        wal.append(&WalEntry {
+            kind: WalKind::Halt,
+            seq: 2,
+            symbol: "BTC-USD".to_string(),
+            price: 120,
+            ts_ms: Some(1_000),
+            resume_at_ms: Some(61_000),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let (st, stats) = replay(&wal);
+        assert_eq!(stats.wal_replayed, 2);
+        assert_eq!(
+            st.halts["BTC-USD"],
+            SymbolHalt {
+                since_ms: 1_000,
+                resume_at_ms: 61_000,
+                trigger_price: 120
+            }
+        );
+
+        wal.write_snapshot(&st).unwrap();
+        wal.truncate_wal().unwrap();
+        let (restored, _) = replay(&wal);
+        assert_eq!(restored.halts, st.halts);
+
+        wal.append(&WalEntry {
+            kind: WalKind::Resume,
+            seq: 3,
+            symbol: "BTC-USD".to_string(),
+            ts_ms: Some(61_000),
+            ..Default::default()
+        })
+        .unwrap();
+        let (resumed, _) = replay(&wal);
+        assert!(resumed.halts.is_empty());
+        assert_eq!(resumed.seq, 3);
+    }
+
+    #[test]
+    fn snapshot_carries_orig_qty_and_reads_v1() {
+        let wal = temp_wal();
+        wal.append(&entry(1, "SELL", 101, 10)).unwrap();
+        wal.append(&entry(2, "BUY", 101, 3)).unwrap();
+
+        let (st, _) = replay(&wal);
+        wal.write_snapshot(&st).unwrap();
+        wal.truncate_wal().unwrap();
+
+        let (restored, _) = replay(&wal);
+        let ro = restored.books["BTC-USD"].get(1).unwrap();
+        assert_eq!((ro.orig_qty, ro.remaining_qty, ro.filled_qty()), (10, 7, 3));
+
+        // v1 snapshot: no version, no orig_qty -> orig defaults to remaining
+        fs::write(
+            wal.snapshot_path(),
+            r#"{"seq":2,"books":[{"symbol":"BTC-USD","bids":[],"asks":[{"seq":1,"side":"Sell","price":101,"qty":7,"client_order_id":"c1"}]}]}"#,
+        )
+        .unwrap();
+        let (legacy, stats) = replay(&wal);
+        assert!(!stats.snapshot_checksum_verified);
+        let ro = legacy.books["BTC-USD"].get(1).unwrap();
+        assert_eq!((ro.orig_qty, ro.remaining_qty), (7, 7));
+    }
+
+    #[test]
+    fn restore_symbol_reads_one_book_from_snapshot() {
+        let wal = temp_wal();
+        assert!(wal.restore_symbol("BTC-USD").unwrap().is_none());
+
+        wal.append(&entry(1, "BUY", 100, 5)).unwrap();
+        wal.append(&entry(2, "SELL", 101, 3)).unwrap();
+        wal.append(&WalEntry {
+            symbol: "ETH-USD".to_string(),
+            ..entry(3, "BUY", 50, 1)
+        })
+        .unwrap();
+        let (st, _) = replay(&wal);
+        wal.write_snapshot(&st).unwrap();
+
+        let book = wal.restore_symbol("BTC-USD").unwrap().unwrap();
+        assert_eq!(book.top_of_book(), st.books["BTC-USD"].top_of_book());
+        assert!(book.get(3).is_none());
+        assert!(wal.restore_symbol("SOL-USD").unwrap().is_none());
+    }
+
+    #[test]
+    fn snapshot_path_can_live_apart_from_wal() {
+        let base = temp_wal();
+        let dir = base.wal_path().parent().unwrap();
+        let wal = Wal::with_snapshot_path(
+            dir.join("wal/wal.jsonl"),
+            dir.join("snap/deep/snapshot.json"),
+        );
+        wal.prepare_dirs().unwrap();
+        assert!(dir.join("wal").is_dir() && dir.join("snap/deep").is_dir());
+
+        wal.append(&entry(1, "BUY", 100, 5)).unwrap();
+        let (st, _) = replay(&wal);
+        wal.write_snapshot(&st).unwrap();
+        assert!(wal.snapshot_path().starts_with(dir.join("snap")));
+        assert_eq!(replay(&wal).1.snapshot_seq, 1);
+
+        // Misconfigured: snapshot path is a directory
+        let bad = Wal::with_snapshot_path(dir.join("wal/wal.jsonl"), dir.join("snap"));
+        assert!(bad.prepare_dirs().is_err());
+    }
+
+    #[test]
+    fn snapshot_bytes_are_deterministic() {
+        let wal = temp_wal();
+        for (i, symbol) in ["SOL-USD", "BTC-USD", "ETH-USD", "ADA-USD", "XRP-USD"]
+            .iter()
+            .enumerate()
+        {
+            wal.append(&WalEntry {
+                symbol: symbol.to_string(),
+                ..entry(i as u64 + 1, "BUY", 100, 5)
+            })
+            .unwrap();
+        }
+
+        // Separately rebuilt states: independent HashMap iteration orders
+        let (a, _) = replay(&wal);
+        wal.write_snapshot(&a).unwrap();
+        let first = fs::read(wal.snapshot_path()).unwrap();
+
+        let mut b = EngineState::default();
+        wal.replay_into_with_stats(&mut b).unwrap();
+        wal.write_snapshot(&b).unwrap();
+        assert_eq!(fs::read(wal.snapshot_path()).unwrap(), first);
+    }
+
+    #[test]
+    fn round_trip_verification_reports_first_difference() {
+        let wal = temp_wal();
+        wal.append(&entry(1, "BUY", 100, 5)).unwrap();
+        wal.append(&entry(2, "SELL", 101, 3)).unwrap();
+        wal.append(&entry(3, "SELL", 100, 2)).unwrap();
+        let (st, _) = replay(&wal);
+        verify_snapshot_round_trip(&st).unwrap();
+
+        let (mut other, _) = replay(&wal);
+        assert!(first_difference(&st, &other).is_none());
+        other
+            .books
+            .get_mut("BTC-USD")
+            .unwrap()
+            .asks
+            .get_mut(&101)
+            .unwrap()[0]
+            .orig_qty = 9;
+        let diff = first_difference(&st, &other).unwrap();
+        assert!(diff.starts_with("BTC-USD order #1:"), "{}", diff);
+    }
+
+    #[test]
+    fn state_dump_covers_books_config_and_tape() {
+        let wal = temp_wal();
+        wal.append(&entry(1, "BUY", 100, 5)).unwrap();
+        let mut st = EngineState {
+            config: crate::config::EngineConfig::from_json(br#"{ "BTC-USD": { "max_qty": 50 } }"#)
+                .unwrap(),
+            ..Default::default()
+        };
+        wal.replay_into_with_stats(&mut st).unwrap();
+        st.trades
+            .entry("BTC-USD".to_string())
+            .or_default()
+            .push_back(crate::engine::Trade {
+                trade_id: 7,
+                ..Default::default()
+            });
+
+        let chunks = dump_all(&st, 10);
+        let kinds: Vec<&str> = chunks.iter().map(|c| c.0).collect();
+        assert_eq!(kinds, vec!["header", "book", "trade_cursor", "footer"]);
+
+        let header: serde_json::Value = serde_json::from_str(&chunks[0].1).unwrap();
+        assert_eq!(header["seq"], 1);
+        assert_eq!(header["config"]["BTC-USD"]["max_qty"], 50);
+        let book: SnapshotBook = serde_json::from_str(&chunks[1].1).unwrap();
+        assert_eq!(book.bids[0].seq, 1);
+        assert!(chunks[2].1.contains("\"newest_trade_id\": 7"));
+        let footer: serde_json::Value = serde_json::from_str(&chunks[3].1).unwrap();
+        assert_eq!(
+            (&footer["seq"], &footer["checksum"]),
+            (&header["seq"], &header["checksum"])
+        );
+    }
+
+    /// Every chunk of a dump taken in one go, as (kind, json).
+    fn dump_all(st: &EngineState, max_orders: usize) -> Vec<(&'static str, String)> {
+        let mut dump = StateDump::default();
+        let mut out = Vec::new();
+        loop {
+            let chunks = dump.next(st, max_orders);
+            if chunks.is_empty() {
+                return out;
+            }
+            out.extend(chunks.iter().map(|c| (c.kind(), c.to_json().unwrap())));
+        }
+    }
+
+    #[test]
+    fn state_dump_pages_books_and_resumes_across_changes() {
+        let wal = temp_wal();
+        for seq in 1..=5 {
+            wal.append(&entry(seq, "BUY", 100 - (seq as i64 % 2), 1))
+                .unwrap();
+        }
+        wal.append(&entry(6, "SELL", 105, 1)).unwrap();
+        wal.append(&WalEntry {
+            symbol: "ETH-USD".to_string(),
+            ..entry(7, "SELL", 10, 1)
+        })
+        .unwrap();
+        let (mut st, _) = replay(&wal);
+        st.books.insert("EMPTY".to_string(), OrderBook::new());
+
+        // Pages of two: BTC-USD bids 99,99 | 100,100 | 100 + ask 105; an empty book still shows up
+        let pages: Vec<SnapshotBook> = dump_all(&st, 2)
+            .into_iter()
+            .filter(|c| c.0 == "book")
+            .map(|c| serde_json::from_str(&c.1).unwrap())
+            .collect();
+        let rows: Vec<_> = pages
+            .iter()
+            .map(|p| {
+                (
+                    p.symbol.as_str(),
+                    p.bids
+                        .iter()
+                        .chain(p.asks.iter())
+                        .map(|o| o.seq)
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                ("BTC-USD", vec![1, 3]),
+                ("BTC-USD", vec![5, 2]),
+                ("BTC-USD", vec![4, 6]),
+                ("EMPTY", vec![]),
+                ("ETH-USD", vec![7])
+            ]
+        );
+
+        // Orders leaving or joining between pages don't make the cursor skip or repeat the rest
+        let mut dump = StateDump::default();
+        dump.next(&st, 2); // header
+        dump.next(&st, 2); // seqs 1, 3
+        let btc = st.books.get_mut("BTC-USD").unwrap();
+        btc.cancel(3).unwrap();
+        btc.cancel(5).unwrap();
+        btc.add(Order {
+            seq: 8,
+            side: BookSide::Buy,
+            price: 99,
+            qty: 1,
+            client_order_id: String::new(),
+        });
+        st.seq = 8;
+        let DumpChunk::Book(page) = dump.next(&st, 2).remove(0) else {
+            panic!("expected a book page");
+        };
+        assert_eq!(
+            page.bids.iter().map(|o| o.seq).collect::<Vec<_>>(),
+            vec![8, 2]
+        );
+        let rest: Vec<DumpChunk> =
+            std::iter::from_fn(|| Some(dump.next(&st, 2)).filter(|c| !c.is_empty()))
+                .flatten()
+                .collect();
+        let Some(DumpChunk::Footer(footer)) = rest.last() else {
+            panic!("expected a footer");
+        };
+        assert_eq!(footer.seq, 8);
+    }
+
+    #[test]
+    fn per_symbol_layout_replays_like_single_and_isolates_corruption() {
+        let single = temp_wal();
+        let per = temp_wal().with_layout(WalLayout::PerSymbol);
+        let eth = |seq, side, price, qty| WalEntry {
+            symbol: "ETH-USD".to_string(),
+            ..entry(seq, side, price, qty)
+        };
+        let append = |entries: &[WalEntry]| {
+            for e in entries {
+                single.append(e).unwrap();
+                per.append(e).unwrap();
+            }
+        };
+
+        append(&[
+            entry(1, "BUY", 100, 5),
+            eth(2, "SELL", 10, 3),
+            entry(3, "SELL", 100, 2),
+            cancel(4, 1),
+        ]);
+        // The engine-wide WAL only carries the seq high-water mark
+        let marks = fs::read_to_string(per.wal_path()).unwrap();
+        assert_eq!(marks.lines().count(), 4);
+        assert!(marks.lines().all(|l| l.contains(r#""kind":"SEQ_MARK""#)));
+        assert!(per
+            .symbols_dir()
+            .join("ETH-USD")
+            .join("wal.jsonl")
+            .is_file());
+        let (st, _) = replay(&per);
+        assert_eq!(
+            (st.seq, state_checksum(&st)),
+            (4, state_checksum(&replay(&single).0))
+        );
+
+        per.write_snapshot(&st).unwrap();
+        per.retire_wal(st.seq, 0).unwrap();
+        append(&[entry(5, "BUY", 99, 1), eth(6, "BUY", 9, 1)]);
+        let (st, stats) = replay(&per);
+        assert_eq!(
+            (stats.symbol_wals, stats.snapshot_books, stats.wal_replayed),
+            (2, 2, 2)
+        );
+        assert_eq!(state_checksum(&st), state_checksum(&replay(&single).0));
+        assert_eq!(
+            per.restore_symbol("ETH-USD")
+                .unwrap()
+                .unwrap()
+                .top_of_book(),
+            (0, 0, 10, 3)
+        );
+
+        // A corrupt line blocks only its own symbol; moving that directory aside restores the rest,
+        // and the next seq still clears the moved symbol's last one
+        let eth_dir = per.symbols_dir().join("ETH-USD");
+        OpenOptions::new()
+            .append(true)
+            .open(eth_dir.join("wal.jsonl"))
+            .unwrap()
+            .write_all(b"{oops\n")
+            .unwrap();
+        let err = per
+            .replay_into_with_stats(&mut EngineState::default())
+            .unwrap_err();
+        assert!(err.to_string().starts_with("symbol ETH-USD"), "{err}");
+        fs::rename(&eth_dir, per.symbols_dir().with_file_name("ETH-USD.bad")).unwrap();
+        let (st, _) = replay(&per);
+        assert_eq!(st.seq, 6);
+        assert_eq!(st.books["BTC-USD"].top_of_book(), (99, 1, 0, 0));
+        assert!(!st.books.contains_key("ETH-USD"));
+
+        // Symbol directories are never silently ignored, and symbols can't escape them
+        let err = Wal::new(per.wal_path())
+            .replay_into_with_stats(&mut EngineState::default())
+            .unwrap_err();
+        assert!(err.to_string().contains("layout is single"), "{err}");
+        assert!(per.symbol_wal("../BTC-USD").is_err());
+    }
+
+    #[test]
+    fn wal_behind_snapshot_is_a_seq_mismatch() {
+        let wal = temp_wal();
+        wal.append(&entry(1, "BUY", 100, 5)).unwrap();
+        wal.append(&entry(2, "SELL", 101, 3)).unwrap();
+        let (st, _) = replay(&wal);
+        wal.write_snapshot(&st).unwrap();
+
+        // Snapshot written but WAL left untruncated: it ends at the snapshot seq, no mismatch
+        let strict = wal.clone().with_seq_mismatch(SeqMismatchPolicy::Strict);
+        assert_eq!(
+            strict
+                .replay_into_with_stats(&mut EngineState::default())
+                .unwrap()
+                .wal_max_seq,
+            2
+        );
+
+        // Swap in an older WAL: seq 1 only
+        let older = temp_wal();
+        older.append(&entry(1, "BUY", 100, 5)).unwrap();
+        fs::copy(older.wal_path(), wal.wal_path()).unwrap();
+
+        let err = strict
+            .replay_into_with_stats(&mut EngineState::default())
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(
+            err.to_string()
+                .contains("snapshot seq 2 is ahead of the WAL's max seq 1"),
+            "{err}"
+        );
+
+        for policy in [SeqMismatchPolicy::TrustSnapshot, SeqMismatchPolicy::Warn] {
+            let mut restored = EngineState::default();
+            let stats = wal
+                .clone()
+                .with_seq_mismatch(policy)
+                .replay_into_with_stats(&mut restored)
+                .unwrap();
+            assert_eq!(
+                (stats.snapshot_seq, stats.wal_max_seq, stats.wal_replayed),
+                (2, 1, 0)
+            );
+            assert_eq!(state_checksum(&restored), state_checksum(&st));
+        }
+
+        // An empty WAL (snapshot then truncate) is the normal case
+        wal.truncate_wal().unwrap();
+        assert_eq!(
+            strict
+                .replay_into_with_stats(&mut EngineState::default())
+                .unwrap()
+                .wal_max_seq,
+            0
+        );
+    }
+
+    #[test]
+    fn newer_snapshot_version_is_rejected() {
+        let wal = temp_wal();
+        fs::create_dir_all(wal.snapshot_path().parent().unwrap()).unwrap();
+        fs::write(wal.snapshot_path(), r#"{"version":99,"seq":0,"books":[]}"#).unwrap();
+
+        let err = wal.read_snapshot().unwrap_err();
+        assert!(err.to_string().contains("snapshot version 99"));
+    }
+}